@@ -1,10 +1,13 @@
-use nalgebra::{Point3, Vector3, Point2, Vector2};
+use std::ops::Mul;
+
+use nalgebra::{Point3, Vector3, Point2, Vector2, Affine3};
 use rand::Rng;
 
-use crate::geometry::Ray;
+use crate::geometry::{Ray, RayDifferential};
 
 pub enum Camera {
     Perspective(PerspectiveCamera),
+    Orthographic(OrthographicCamera),
 }
 
 impl Camera {
@@ -30,16 +33,43 @@ impl Camera {
         })
     }
 
-    pub fn get_ray(&self, xy: Point2<u32>, img_size: (u32, u32)) -> Ray {
+    // height is the extent of the view along the up direction.
+    pub fn orthographic_look_to(pos: &Point3<f32>, forward: &Vector3<f32>, up_dir: &Vector3<f32>, height: f32, aspect: f32) -> Camera {
+
+        let forward = forward.normalize();
+        let right = forward.cross(up_dir).normalize();
+        let up = right.cross(&forward);
+
+        Camera::Orthographic(OrthographicCamera {
+            position: *pos,
+            forward,
+            horizontal: right * (height * aspect / 2.0),
+            vertical: up * (height / 2.0),
+        })
+    }
+
+    pub fn get_ray(&self, xy: Point2<u32>, img_size: (u32, u32)) -> RayDifferential {
         let mut rng = rand::thread_rng();
         
         let uv = Point2::new(
             (xy.x as f32 + rng.gen::<f32>()) / img_size.0 as f32,
             1.0 - (xy.y as f32 + rng.gen::<f32>()) / img_size.1 as f32,
         );
-        
+
+        // the rays through the same spot of the next pixel to the right and below.
+        let dx = Vector2::new(1.0 / img_size.0 as f32, 0.0);
+        let dy = Vector2::new(0.0, -1.0 / img_size.1 as f32);
+
+        RayDifferential {
+            ray: self.get_ray_at(&uv),
+            offsets: Some((self.get_ray_at(&(uv + dx)), self.get_ray_at(&(uv + dy)))),
+        }
+    }
+
+    fn get_ray_at(&self, uv: &Point2<f32>) -> Ray {
         match self {
-            Self::Perspective(camera) => camera.get_ray(&uv),
+            Self::Perspective(camera) => camera.get_ray(uv),
+            Self::Orthographic(camera) => camera.get_ray(uv),
         }
     }
 }
@@ -70,3 +100,40 @@ impl PerspectiveCamera {
         Ray { origin, direction }
     }
 }
+
+// a camera with parallel rays, starting on a rectangle around its position.
+pub struct OrthographicCamera {
+    position: Point3<f32>,
+    forward: Vector3<f32>,
+    horizontal: Vector3<f32>,
+    vertical: Vector3<f32>,
+}
+
+impl OrthographicCamera {
+    fn get_ray(&self, uv: &Point2<f32>) -> Ray {
+        let uv = 2.0 * uv - Vector2::new(1.0, 1.0);
+        let origin = self.position + uv.x * self.horizontal + uv.y * self.vertical;
+        Ray { origin, direction: self.forward }
+    }
+}
+
+// moves the camera along with the scene it is placed in.
+impl Mul<Camera> for Affine3<f32> {
+    type Output = Camera;
+    fn mul(self, rhs: Camera) -> Self::Output {
+        match rhs {
+            Camera::Perspective(camera) => Camera::Perspective(PerspectiveCamera {
+                position: self * camera.position,
+                forward: self * camera.forward,
+                horizontal: self * camera.horizontal,
+                vertical: self * camera.vertical,
+            }),
+            Camera::Orthographic(camera) => Camera::Orthographic(OrthographicCamera {
+                position: self * camera.position,
+                forward: (self * camera.forward).normalize(),
+                horizontal: self * camera.horizontal,
+                vertical: self * camera.vertical,
+            }),
+        }
+    }
+}
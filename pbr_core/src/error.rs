@@ -0,0 +1,212 @@
+use std::{fmt, io, path::{Path, PathBuf}};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// the part of a file that failed to load, named after the item or its index when it has no name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Node(String),
+    Mesh(String),
+    Primitive { mesh: String, index: usize },
+    Material(String),
+    Texture(String),
+    Light(String),
+    Camera(String),
+    Animation(String),
+    Line(usize),
+    Element(String),
+}
+
+impl Location {
+    pub fn name(name: Option<&str>, index: usize) -> String {
+        match name {
+            Some(name) => format!("'{}'", name),
+            None => format!("#{}", index),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Node(name) => write!(f, "node {}", name),
+            Location::Mesh(name) => write!(f, "mesh {}", name),
+            Location::Primitive { mesh, index } => write!(f, "primitive {} of mesh {}", index, mesh),
+            Location::Material(name) => write!(f, "material {}", name),
+            Location::Texture(name) => write!(f, "texture {}", name),
+            Location::Light(name) => write!(f, "light {}", name),
+            Location::Camera(name) => write!(f, "camera {}", name),
+            Location::Animation(name) => write!(f, "animation {}", name),
+            Location::Line(line) => write!(f, "line {}", line),
+            Location::Element(name) => write!(f, "element '{}'", name),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Cause {
+    Io(io::Error),
+    Gltf(gltf::Error),
+    Image(image::ImageError),
+    Exr(exr::error::Error),
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    Ron(ron::Error),
+    Xml(roxmltree::Error),
+    // a required attribute or section is absent.
+    Missing(String),
+    Unsupported(String),
+    Invalid(String),
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cause::Io(error) => write!(f, "{}", error),
+            Cause::Gltf(error) => write!(f, "{}", error),
+            Cause::Image(error) => write!(f, "{}", error),
+            Cause::Exr(error) => write!(f, "{}", error),
+            Cause::Json(error) => write!(f, "{}", error),
+            Cause::Toml(error) => write!(f, "{}", error),
+            Cause::Ron(error) => write!(f, "{}", error),
+            Cause::Xml(error) => write!(f, "{}", error),
+            Cause::Missing(what) => write!(f, "missing {}", what),
+            Cause::Unsupported(what) => write!(f, "unsupported {}", what),
+            Cause::Invalid(what) => write!(f, "invalid {}", what),
+        }
+    }
+}
+
+// An error while loading a scene or texture, with the file and the item in it that caused it.
+// the cause is boxed as the errors of the file format crates are large.
+#[derive(Debug)]
+pub struct Error {
+    pub path: Option<PathBuf>,
+    pub location: Option<Location>,
+    pub cause: Box<Cause>,
+}
+
+impl Error {
+    pub fn new(cause: Cause) -> Self {
+        Self { path: None, location: None, cause: Box::new(cause) }
+    }
+
+    pub fn missing(what: impl Into<String>) -> Self {
+        Self::new(Cause::Missing(what.into()))
+    }
+
+    pub fn unsupported(what: impl Into<String>) -> Self {
+        Self::new(Cause::Unsupported(what.into()))
+    }
+
+    pub fn invalid(what: impl Into<String>) -> Self {
+        Self::new(Cause::Invalid(what.into()))
+    }
+
+    // only sets the path if none is set yet, so the innermost file is reported.
+    pub fn in_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path.get_or_insert_with(|| path.as_ref().to_path_buf());
+        self
+    }
+
+    // only sets the location if none is set yet, so the innermost item is reported.
+    pub fn at(mut self, location: Location) -> Self {
+        self.location.get_or_insert(location);
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}: ", path.display())?;
+        }
+        if let Some(location) = &self.location {
+            write!(f, "{}: ", location)?;
+        }
+        write!(f, "{}", self.cause)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self.cause.as_ref() {
+            Cause::Io(error) => Some(error),
+            Cause::Gltf(error) => Some(error),
+            Cause::Image(error) => Some(error),
+            Cause::Exr(error) => Some(error),
+            Cause::Json(error) => Some(error),
+            Cause::Toml(error) => Some(error),
+            Cause::Ron(error) => Some(error),
+            Cause::Xml(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::new(Cause::Io(error))
+    }
+}
+
+impl From<gltf::Error> for Error {
+    fn from(error: gltf::Error) -> Self {
+        Self::new(Cause::Gltf(error))
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(error: image::ImageError) -> Self {
+        Self::new(Cause::Image(error))
+    }
+}
+
+impl From<exr::error::Error> for Error {
+    fn from(error: exr::error::Error) -> Self {
+        Self::new(Cause::Exr(error))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Self::new(Cause::Json(error))
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(error: toml::de::Error) -> Self {
+        Self::new(Cause::Toml(error))
+    }
+}
+
+impl From<ron::Error> for Error {
+    fn from(error: ron::Error) -> Self {
+        Self::new(Cause::Ron(error))
+    }
+}
+
+impl From<roxmltree::Error> for Error {
+    fn from(error: roxmltree::Error) -> Self {
+        Self::new(Cause::Xml(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{Error, Location};
+
+    #[test]
+    fn error_context_test() {
+        let error = Error::missing("normals")
+            .at(Location::Primitive { mesh: Location::name(Some("Cube"), 0), index: 1 })
+            .at(Location::Mesh(Location::name(None, 0)))
+            .in_file("scene.gltf")
+            .in_file("other.gltf");
+
+        assert_eq!(error.path, Some(PathBuf::from("scene.gltf")));
+        assert_eq!(error.to_string(), "scene.gltf: primitive 1 of mesh 'Cube': missing normals");
+    }
+}
@@ -1,6 +1,6 @@
 use std::f32::consts::PI;
 
-use nalgebra::{Vector3, Point3, Point2, Matrix3};
+use nalgebra::{Vector3, Point3, Point2, Matrix3, Vector2};
 
 use crate::{scene::Vertex, material::{Material, BrdfSample}, spectrum::Spectrum};
 
@@ -10,13 +10,35 @@ pub struct Ray {
     pub direction: Vector3<f32>,
 }
 
+// A ray together with the rays through the neighbouring pixels in x and y, used to estimate
+// how large a footprint a lookup covers on the surface. The offsets are dropped after
+// bounces off non specular surfaces.
+#[derive(Clone, Copy)]
+pub struct RayDifferential {
+    pub ray: Ray,
+    pub offsets: Option<(Ray, Ray)>,
+}
+
+impl RayDifferential {
+    pub fn new(ray: Ray) -> Self {
+        Self { ray, offsets: None }
+    }
+}
+
 
 #[derive(Clone, Copy)]
 pub struct SurfacePoint<'s> {
     pub position: Point3<f32>,
     pub normal: Vector3<f32>,
     pub tangent: Vector3<f32>,
+    pub bitangent_sign: f32,
     pub tex_coords: Point2<f32>,
+    pub color: Spectrum<f32>,
+    // change in position and texture coordinates towards the neighbouring pixels.
+    pub dpdx: Vector3<f32>,
+    pub dpdy: Vector3<f32>,
+    pub duvdx: Vector2<f32>,
+    pub duvdy: Vector2<f32>,
     pub material: &'s dyn Material,
 }
 
@@ -26,32 +48,115 @@ impl<'s> SurfacePoint<'s> {
     pub fn new(barycentrics: &Vector3<f32>, vertices: &[&Vertex; 3], material: &'s dyn Material) -> Self {
 
         let v = interpolate(barycentrics, vertices);
-        
+        Self::from_vertex(&v, material)
+    }
+
+    pub fn from_vertex(v: &Vertex, material: &'s dyn Material) -> Self {
         Self {
             position: v.position,
             normal: v.normal,
             tangent: v.tangent,
+            bitangent_sign: v.bitangent_sign,
             tex_coords: v.tex_coords,
+            color: v.color,
+            dpdx: Vector3::zeros(),
+            dpdy: Vector3::zeros(),
+            duvdx: Vector2::zeros(),
+            duvdy: Vector2::zeros(),
             material: material
         }
     }
 
+    // intersects the offset rays with the tangent plane and finds the texture coordinates there.
+    pub fn compute_differentials(&mut self, ray: &RayDifferential, vertices: &[&Vertex; 3]) {
+        let (rx, ry) = match &ray.offsets {
+            Some(offsets) => offsets,
+            None => return,
+        };
+
+        let offset_position = |r: &Ray| {
+            let ddotn = r.direction.dot(&self.normal);
+            if ddotn == 0.0 {
+                return None;
+            }
+            let t = (self.position - r.origin).dot(&self.normal) / ddotn;
+            Some(r.origin + r.direction * t)
+        };
+
+        let (px, py) = match (offset_position(rx), offset_position(ry)) {
+            (Some(px), Some(py)) => (px, py),
+            _ => return,
+        };
+
+        let uv_at = |p: &Point3<f32>| {
+            let b = plane_barycentrics(&vertices[0].position, &vertices[1].position, &vertices[2].position, p);
+            vertices[0].tex_coords.coords * b.x + vertices[1].tex_coords.coords * b.y + vertices[2].tex_coords.coords * b.z
+        };
+
+        self.dpdx = px - self.position;
+        self.dpdy = py - self.position;
+        self.duvdx = uv_at(&px) - self.tex_coords.coords;
+        self.duvdy = uv_at(&py) - self.tex_coords.coords;
+    }
+
+    // a point just off the surface on the side d points to, so rays leaving it in direction d
+    // don't hit the surface they start on. surfaces are hit from both sides, so this is not
+    // always the side the normal is on.
+    pub fn offset_towards(&self, d: &Vector3<f32>) -> Point3<f32> {
+        self.position + 0.0001 * d.dot(&self.normal).signum() * self.normal
+    }
+
+    // the ray continuing in direction wi from this point. differentials are carried through
+    // specular reflections and straight transmissions, treating the surface as locally flat.
+    pub fn spawn_ray(&self, ray: &RayDifferential, wi: &Vector3<f32>, wo: &Vector3<f32>) -> RayDifferential {
+        let t2w = self.tangent_to_world();
+
+        let direction = t2w * wi;
+        let next = Ray { origin: self.offset_towards(&direction), direction };
+
+        let offsets = ray.offsets.filter(|_| self.material.is_delta(self)).and_then(|(rx, ry)| {
+            let reflected = wi.z * wo.z > 0.0;
+            let transmitted = (wi + wo).norm_squared() < 1e-6;
+
+            let bounce = |r: &Ray, dp: &Vector3<f32>| {
+                let direction = if reflected {
+                    r.direction - 2.0 * r.direction.dot(&self.normal) * self.normal
+                } else {
+                    r.direction
+                };
+                Ray { origin: next.origin + dp, direction }
+            };
+
+            (reflected || transmitted).then(|| (bounce(&rx, &self.dpdx), bounce(&ry, &self.dpdy)))
+        });
+
+        RayDifferential { ray: next, offsets }
+    }
+
 
 
     pub fn tangent_to_world(&self) -> Matrix3<f32> {
         let t = self.tangent;
         let n = self.normal;
-        let b = n.cross(&t);
+        let b = n.cross(&t) * self.bitangent_sign;
 
         Matrix3::from_columns(&[t, b, n])   
     }
 
     pub fn brdf(&self, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32> {
-        self.material.brdf(&self.tex_coords, wi, wo)
+        self.material.brdf(self, wi, wo)
     }
     
     pub fn sample_brdf(&self, wo: &Vector3<f32>) -> BrdfSample {
-        self.material.sample_brdf(&self.tex_coords, wo)
+        self.material.sample_brdf(self, wo)
+    }
+
+    pub fn is_delta(&self) -> bool {
+        self.material.is_delta(self)
+    }
+
+    pub fn emission(&self, wo: &Vector3<f32>) -> Spectrum<f32> {
+        self.material.emission(self, wo)
     }
 
 }
@@ -158,17 +263,16 @@ pub fn triangle_intersect(p1: &Point3<f32>, p2: &Point3<f32>, p3: &Point3<f32>,
     let n = e1.cross(&e2);
     let ddotn = ray.direction.dot(&n);
 
-    if ddotn >= 0.0 {
+    if ddotn == 0.0 {
         return None;
     }
 
     let odotn = (p1 - ray.origin).dot(&n);
+    let t = odotn / ddotn;
 
-    if odotn >= 0.0 {
+    if t <= 0.0 {
         return None;
     }
-
-    let t = odotn / ddotn;
     let p = ray.origin + ray.direction * t;
     
     let n1 = e1.cross(&(p - p1));
@@ -187,6 +291,21 @@ pub fn triangle_intersect(p1: &Point3<f32>, p2: &Point3<f32>, p3: &Point3<f32>,
     None
 }
 
+// barycentric coordinates of a point in the plane of a triangle, which may lie outside of it.
+pub fn plane_barycentrics(p1: &Point3<f32>, p2: &Point3<f32>, p3: &Point3<f32>, p: &Point3<f32>) -> Vector3<f32> {
+    let n = (p2 - p1).cross(&(p3 - p2));
+    let area = n.norm_squared();
+    if area == 0.0 {
+        return Vector3::new(1.0, 0.0, 0.0);
+    }
+
+    Vector3::new(
+        n.dot(&(p3 - p2).cross(&(p - p2))),
+        n.dot(&(p1 - p3).cross(&(p - p3))),
+        n.dot(&(p2 - p1).cross(&(p - p1))),
+    ) / area
+}
+
 pub fn triangle_centroid(p1: &Point3<f32>, p2: &Point3<f32>, p3: &Point3<f32>) -> Point3<f32> {
     Point3::from((1.0 / 3.0) * (p1.coords + p2.coords + p3.coords))
 }
@@ -200,10 +319,13 @@ pub fn interpolate(barycentrics: &Vector3<f32>, vertices: &[&Vertex; 3]) -> Vert
     let tangent =  v1.tangent * b.x + v2.tangent * b.y + v3.tangent * b.z;
     // gram-schmidt
     let tangent = (tangent - normal * tangent.dot(&normal)).normalize();
+    // mirrored and unmirrored texture coordinates meet at a seam, a triangle doesn't straddle it.
+    let bitangent_sign = (v1.bitangent_sign * b.x + v2.bitangent_sign * b.y + v3.bitangent_sign * b.z).signum();
 
     let tex_coords = Point2::from(v1.tex_coords.coords * b.x + v2.tex_coords.coords * b.y + v3.tex_coords.coords * b.z);
+    let color = v1.color * b.x + v2.color * b.y + v3.color * b.z;
 
-    Vertex { position, normal, tangent, tex_coords }
+    Vertex { position, normal, tangent, bitangent_sign, tex_coords, color }
 }
 
 #[cfg(test)]
@@ -237,6 +359,26 @@ mod test {
         assert!(approx_eq!(f32, b.z, 0.25, ulps = 2));
     }
     
+
+    // triangles are hit from behind as well, so rays can leave closed meshes they were transmitted into.
+    #[test]
+    fn triangle_intersect_hits_back() {
+        let result = triangle_intersect(
+            &Point3::new(0.0, 0.0, 0.0),
+            &Point3::new(1.0, 0.0, 0.0),
+            &Point3::new(0.0, 1.0, 0.0),
+            &Ray {
+                origin: Point3::new(0.25, 0.25, -1.0),
+                direction: Vector3::new(0.0, 0.0, 1.0),
+            }
+        );
+
+        assert!(result.is_some());
+        let (t, b) = result.unwrap();
+
+        assert!(approx_eq!(f32, t, 1.0, ulps = 2));
+        assert!(approx_eq!(f32, b.x, 0.5, ulps = 2));
+    }
     
     #[test]
     fn triangle_intersect_miss_dir_away() {
@@ -317,18 +459,21 @@ mod test {
                 normal: normals[0],
                 tangent: tangents[0],
                 tex_coords: tex_coords[0],
+                ..Default::default()
             },
             Vertex {
                 position: positions[1],
                 normal: normals[1],
                 tangent: tangents[1],
                 tex_coords: tex_coords[1],
+                ..Default::default()
             },
             Vertex {
                 position: positions[2],
                 normal: normals[2],
                 tangent: tangents[2],
                 tex_coords: tex_coords[2],
+                ..Default::default()
             },
         ];
 
@@ -393,6 +538,49 @@ mod test {
         assert!(approx_eq!(f32, tmax, f32::sqrt(12.0), ulps = 2));
     }
 
+    #[test]
+    fn surface_point_differentials() {
+        // a triangle in the xy plane with texture coordinates equal to its positions.
+        let vertices = [
+            Vertex { position: Point3::new(0.0, 0.0, 0.0), normal: Vector3::new(0.0, 0.0, 1.0), tangent: Vector3::new(1.0, 0.0, 0.0), tex_coords: Point2::new(0.0, 0.0), ..Default::default() },
+            Vertex { position: Point3::new(1.0, 0.0, 0.0), normal: Vector3::new(0.0, 0.0, 1.0), tangent: Vector3::new(1.0, 0.0, 0.0), tex_coords: Point2::new(1.0, 0.0), ..Default::default() },
+            Vertex { position: Point3::new(0.0, 1.0, 0.0), normal: Vector3::new(0.0, 0.0, 1.0), tangent: Vector3::new(1.0, 0.0, 0.0), tex_coords: Point2::new(0.0, 1.0), ..Default::default() },
+        ];
+        let vertices = [&vertices[0], &vertices[1], &vertices[2]];
+
+        let down = Vector3::new(0.0, 0.0, -1.0);
+        let ray = RayDifferential {
+            ray: Ray { origin: Point3::new(0.25, 0.25, 1.0), direction: down },
+            offsets: Some((
+                Ray { origin: Point3::new(0.35, 0.25, 1.0), direction: down },
+                Ray { origin: Point3::new(0.25, 0.25, 1.0), direction: Vector3::new(0.0, 0.1, -1.0) },
+            )),
+        };
+
+        let material = LambertianMaterial::flat(Spectrum::constant(1.0));
+        let mut p = SurfacePoint::new(&Vector3::new(0.5, 0.25, 0.25), &vertices, &material);
+        p.compute_differentials(&ray, &vertices);
+
+        assert!((p.duvdx - Vector2::new(0.1, 0.0)).norm() < 1e-5);
+        assert!((p.duvdy - Vector2::new(0.0, 0.1)).norm() < 1e-5);
+        assert!((p.dpdx - Vector3::new(0.1, 0.0, 0.0)).norm() < 1e-5);
+
+        // diffuse bounces drop the differentials.
+        let next = p.spawn_ray(&ray, &Vector3::new(0.0, 0.0, 1.0), &Vector3::new(0.0, 0.0, 1.0));
+        assert!(next.offsets.is_none());
+    }
+
+    #[test]
+    fn surface_point_offset_sides() {
+        let material = LambertianMaterial::flat(Spectrum::constant(1.0));
+        let vertex = Vertex { normal: Vector3::new(0.0, 0.0, 1.0), tangent: Vector3::new(1.0, 0.0, 0.0), ..Default::default() };
+        let p = SurfacePoint::from_vertex(&vertex, &material);
 
+        assert!(p.offset_towards(&Vector3::new(0.3, 0.0, 1.0)).z > 0.0);
+        assert!(p.offset_towards(&Vector3::new(0.3, 0.0, -1.0)).z < 0.0);
 
+        let ray = RayDifferential { ray: Ray { origin: Point3::new(0.0, 0.0, -1.0), direction: Vector3::new(0.0, 0.0, 1.0) }, offsets: None };
+        let next = p.spawn_ray(&ray, &Vector3::new(0.0, 0.0, -1.0), &Vector3::new(0.0, 0.0, -1.0));
+        assert!(next.ray.origin.z < 0.0);
+    }
 }
@@ -4,7 +4,7 @@ mod path_tracer;
 pub use brute_forcer::BruteForcer;
 use nalgebra::Point2;
 pub use path_tracer::PathTracer;
-use rayon::prelude::ParallelIterator;
+use rayon::prelude::{ParallelIterator, IndexedParallelIterator};
 
 use crate::accelerator::Accelerator;
 use crate::spectrum::Spectrum;
@@ -12,17 +12,19 @@ use crate::scene::Scene;
 use crate::texture::Texture;
 
 pub trait Integrator {
+    // returns the image along with the fraction of camera rays through each pixel that hit the scene, usable as alpha.
     fn render<A: Accelerator>(
         &self,
         scene: &Scene<A>,
         img_size: (u32, u32),
         report_progress: impl Fn(&Texture<Spectrum<f32>>)
-    ) -> Texture<Spectrum<f32>>;
+    ) -> (Texture<Spectrum<f32>>, Texture<f32>);
 }
 
 trait SamplingIntegrator: Sync {
     fn get_spp(&self) -> u32;
-    fn sample<A: Accelerator>(&self, scene: &Scene<A>, xy: Point2<u32>, size: (u32, u32)) -> Spectrum<f32>;
+    // the radiance along one camera ray through the pixel and whether that ray hit the scene.
+    fn sample<A: Accelerator>(&self, scene: &Scene<A>, xy: Point2<u32>, size: (u32, u32)) -> (Spectrum<f32>, bool);
 }
 
 impl<T> Integrator for T
@@ -34,21 +36,26 @@ where
         scene: &Scene<A>,
         img_size: (u32, u32),
         report_progress: impl Fn(&Texture<Spectrum<f32>>)
-    ) -> Texture<Spectrum<f32>> {
+    ) -> (Texture<Spectrum<f32>>, Texture<f32>) {
         let mut render_target = Texture::new(img_size.0, img_size.1, &Spectrum::black());   
+        let mut coverage = Texture::new(img_size.0, img_size.1, &0.0);
 
         for sample_idx in 0..self.get_spp() {
             render_target.par_pixels_mut()
-                .for_each(|(xy, pixel)| {
-                    let new_sample = self.sample(scene, xy, img_size);
+                .zip(coverage.par_pixels_mut())
+                .for_each(|((xy, pixel), (_, covered))| {
+                    let (new_sample, hit) = self.sample(scene, xy, img_size);
                     let prev_sum = *pixel * sample_idx as f32;
                     let new_sum = prev_sum + new_sample;
                     *pixel = new_sum / (sample_idx as f32 + 1.0);
+
+                    let hits = *covered * sample_idx as f32 + if hit { 1.0 } else { 0.0 };
+                    *covered = hits / (sample_idx as f32 + 1.0);
                 });
 
             report_progress(&render_target);
         }
 
-        render_target
+        (render_target, coverage)
     }
 }
\ No newline at end of file
@@ -2,7 +2,7 @@
 use nalgebra::Point2;
 
 use crate::accelerator::Accelerator;
-use crate::geometry::Ray;
+use crate::geometry::RayDifferential;
 use crate::light::Emitter;
 use crate::scene::Scene;
 use crate::spectrum::Spectrum;
@@ -22,39 +22,39 @@ impl BruteForcer
         }
     }
 
-    fn sample_recursive<A: Accelerator>(&self, ray: Ray, scene: &Scene<A>, depth: u32) -> Spectrum<f32> {
+    // returns the radiance along the ray and whether the ray hit the scene.
+    fn sample_recursive<A: Accelerator>(&self, ray: RayDifferential, scene: &Scene<A>, depth: u32) -> (Spectrum<f32>, bool) {
         if depth == self.depth {
-            return Spectrum::black();
+            return (Spectrum::black(), false);
         }
         
-        if let Some(p) = scene.intersect(&ray) {
-            let t2w = p.tangent_to_world();
-            let w2t = t2w.transpose();
+        if let Some(p) = scene.intersect_differential(&ray) {
+            let w2t = p.tangent_to_world().transpose();
 
-            let wo = w2t * -ray.direction;
+            let wo = w2t * -ray.ray.direction;
             let sample = p.sample_brdf(&wo);
-            
-            let next_ray = Ray {
-                origin: p.position + 0.0001 * p.normal,
-                direction: t2w * sample.wi,
-            };
+            if sample.pdf <= 0.0 || !sample.pdf.is_finite() {
+                return (p.emission(&wo), true);
+            }
+
+            let next_ray = p.spawn_ray(&ray, &sample.wi, &wo);
 
-            let sample_radiance = self.sample_recursive(next_ray, scene, depth+1);
+            let (sample_radiance, _) = self.sample_recursive(next_ray, scene, depth+1);
             
             if sample.brdf.any_nan() {
                 println!("NaN BRDF: {:?}", sample.brdf);
             }
 
-            return sample.brdf * sample_radiance * (sample.wi.z / sample.pdf);
+            return (p.emission(&wo) + sample.brdf * sample_radiance * (sample.wi.z.abs() / sample.pdf), true);
 
         } else {
             let mut radiance = Spectrum::black();
             
             for bg_light in scene.background_lights() {
-                radiance += bg_light.emission(&ray.direction);
+                radiance += bg_light.emission(&ray.ray.direction);
             }
 
-            return radiance;
+            return (radiance, false);
         }
         
     }
@@ -66,7 +66,7 @@ impl SamplingIntegrator for BruteForcer
         self.spp
     }
 
-    fn sample<A: Accelerator>(&self, scene: &Scene<A>, xy: Point2<u32>, size: (u32, u32)) -> Spectrum<f32> {
+    fn sample<A: Accelerator>(&self, scene: &Scene<A>, xy: Point2<u32>, size: (u32, u32)) -> (Spectrum<f32>, bool) {
         let ray = scene.get_camera().get_ray(xy, size);
         self.sample_recursive(ray, scene, 0)
     }
@@ -34,7 +34,7 @@ impl PathTracer
 
             let brdf = p.brdf(&wi, &wo);
 
-            sample.radiance * brdf * (wi.z / pdf)
+            sample.radiance * brdf * (wi.z.abs() / pdf)
         } else {
             Spectrum::black()
         }
@@ -49,47 +49,86 @@ impl SamplingIntegrator for PathTracer {
         self.spp
     }
 
-    fn sample<A: Accelerator>(&self, scene: &Scene<A>, xy: Point2<u32>, size: (u32, u32)) -> Spectrum<f32> {
+    fn sample<A: Accelerator>(&self, scene: &Scene<A>, xy: Point2<u32>, size: (u32, u32)) -> (Spectrum<f32>, bool) {
 
 
         let mut ray = scene.get_camera().get_ray(xy, size);
 
         let mut radiance = Spectrum::black();
         let mut throughput = Spectrum::constant(1.0);
+        let mut hit = false;
+        // the camera ray and rays leaving delta materials can't have been found by sampling the lights.
+        let mut specular = true;
 
         for bounce in 0..self.depth {
             
-            let isect = scene.intersect(&ray);
+            let isect = scene.intersect_differential(&ray);
             
             // if there was no intersection stop bouncing.
             if isect.is_none() { 
-                // if this was the camera ray, add the emission from the background (since it wasn't directly sampled)
-                if bounce == 0 {
+                // add the emission from the background when it wasn't directly sampled at the last hit.
+                if specular {
                     for bgl in scene.background_lights() {
-                        radiance += bgl.emission(&ray.direction) * throughput;
+                        radiance += bgl.emission(&ray.ray.direction) * throughput;
                     }
                 }
                 break;
             }
 
             let p = isect.unwrap();
+            hit |= bounce == 0;
 
-            radiance += self.sample_direct(&ray, &p, scene) * throughput;
+            let w2t = p.tangent_to_world().transpose();
+            let wo = w2t * -ray.ray.direction;
+
+            // emissive surfaces are not sampled as lights, so their light is only picked up here.
+            radiance += p.emission(&wo) * throughput;
+
+            if scene.has_lights() {
+                radiance += self.sample_direct(&ray.ray, &p, scene) * throughput;
+            }
 
-            let t2w = p.tangent_to_world();
-            let w2t = t2w.transpose();
-            let wo = w2t * -ray.direction;
             let sample = p.sample_brdf(&wo);
+            if sample.pdf <= 0.0 || !sample.pdf.is_finite() {
+                break;
+            }
 
-            throughput = throughput * sample.brdf * (sample.wi.z / sample.pdf);
+            throughput = throughput * sample.brdf * (sample.wi.z.abs() / sample.pdf);
+            specular = p.is_delta();
             
-            ray = Ray {
-                origin: p.position + 0.0001 * p.normal,
-                direction: t2w * sample.wi,
-            };
+            ray = p.spawn_ray(&ray, &sample.wi, &wo);
 
         }
 
-        radiance
+        (radiance, hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::Arc};
+
+    use nalgebra::{Point2, Point3, Vector3};
+
+    use crate::{accelerator::Bvh, camera::Camera, integrator::SamplingIntegrator, light::{LightSource, SkySphere}, material::ThinDielectricMaterial, scene::{SceneBuilder, loader::{Loader, LoadOptions, Ply}}, spectrum::Spectrum, texture::Texture};
+
+    use super::PathTracer;
+
+    const QUAD: &str = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n-1 -1 0\n1 -1 0\n1 1 0\n-1 1 0\n4 0 1 2 3\n";
+
+    // a glass pane in front of a uniform sky lets all of it through, either reflected or transmitted.
+    #[test]
+    fn delta_background_test() {
+        let mut builder = SceneBuilder::new()
+            .camera(Camera::perspective_look_at(&Point3::new(0.0, 0.0, 3.0), &Point3::origin(), &Vector3::y(), 30.0, 1.0))
+            .add_light(LightSource::SkySphere(SkySphere::new(Texture::new(1, 1, &Spectrum::constant(1.0)))));
+
+        let options = LoadOptions { default_material: Some(Arc::new(|| Box::new(ThinDielectricMaterial::new(1.5)))), ..LoadOptions::default() };
+        Ply::load_from_reader(&mut Cursor::new(QUAD.as_bytes()), &mut builder, &options).unwrap();
+        let scene = builder.build::<Bvh>();
+
+        let (radiance, hit) = PathTracer::new(4, 1).sample(&scene, Point2::new(4, 4), (8, 8));
+        assert!(hit);
+        assert!((radiance.g - 1.0).abs() < 1e-3, "radiance: {:?}", radiance);
     }
 }
@@ -3,6 +3,7 @@
 pub use nalgebra;
 
 
+pub mod error;
 pub mod geometry;
 pub mod spectrum;
 pub mod texture;
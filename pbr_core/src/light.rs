@@ -4,7 +4,7 @@ use enum_dispatch::enum_dispatch;
 use nalgebra::{Vector3, Point3, Point2, Matrix3};
 use rand::{thread_rng, Rng};
 
-use crate::{geometry::{SurfacePoint, Ray, uniform_hemisphere_map}, accelerator::Accelerator, texture::Texture, scene::Scene, spectrum::Spectrum};
+use crate::{geometry::{SurfacePoint, Ray, uniform_hemisphere_map, uniform_sphere_map}, accelerator::Accelerator, texture::Texture, scene::Scene, spectrum::Spectrum};
 
 pub enum VisibilityTest {
     PointToPoint {
@@ -29,7 +29,7 @@ impl VisibilityTest {
                 let dist = (p2 - p1).norm();
         
                 match scene.intersect_dist(&ray) {
-                    Some(t) => t <= dist,
+                    Some(t) => t >= dist,
                     None => true,
                 }
             },
@@ -66,6 +66,7 @@ pub trait Emitter {
 pub enum LightSource {
     Test(TestLight),
     Directional(DirectionalLight),
+    Point(PointLight),
     SkySphere(SkySphere),
 }
 
@@ -84,7 +85,7 @@ impl Emitter for DirectionalLight {
             radiance: self.irradiance,
             direction: self.neg_direction,
             pdf: 1.0,
-            visibility_test: VisibilityTest::PointInDirection { p: p.position + 0.0001 * p.normal, d: self.neg_direction },
+            visibility_test: VisibilityTest::PointInDirection { p: p.offset_towards(&self.neg_direction), d: self.neg_direction },
         }
     }
 
@@ -97,6 +98,38 @@ impl Emitter for DirectionalLight {
     }
 }
 
+// an infinitely small light giving off the same intensity in every direction.
+pub struct PointLight {
+    pub position: Point3<f32>,
+    pub intensity: Spectrum<f32>,
+}
+
+impl Emitter for PointLight {
+    fn emission(&self, _dir: &Vector3<f32>) -> Spectrum<f32> {
+        Spectrum::black()
+    }
+
+    fn sample(&self, p: &SurfacePoint) -> RadianceSample {
+        let origin = p.offset_towards(&(self.position - p.position));
+        let to_light = self.position - origin;
+
+        RadianceSample {
+            radiance: self.intensity / to_light.norm_squared(),
+            direction: to_light.normalize(),
+            pdf: 1.0,
+            visibility_test: VisibilityTest::PointToPoint { p1: origin, p2: self.position },
+        }
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn is_background(&self) -> bool {
+        false
+    }
+}
+
 pub struct SkySphere {
     texture: Texture<Spectrum<f32>>,
 }
@@ -117,17 +150,17 @@ impl Emitter for SkySphere {
         self.texture.sample(&uv)
     }
 
+    // surfaces are lit from both sides, so the whole sphere is sampled instead of the hemisphere above the normal.
     fn sample(&self, p: &SurfacePoint) -> RadianceSample {
-        let t2w = p.tangent_to_world();
         let mut rng = thread_rng();
         let u = Point2::new(rng.gen(), rng.gen());
-        let direction = t2w * uniform_hemisphere_map(&u);
-        let pdf = 1.0 / (2.0 * PI);
+        let direction = uniform_sphere_map(&u);
+        let pdf = 1.0 / (4.0 * PI);
         
         let radiance = self.emission(&direction);
         
         let visibility_test = VisibilityTest::PointInDirection {
-            p: p.position + 0.0001 * p.normal,
+            p: p.offset_towards(&direction),
             d: direction
         };
 
@@ -172,7 +205,7 @@ impl Emitter for TestLight {
         let light_dir = Vector3::new(1.0, 1.0, 1.0).normalize();
         let light_col = Spectrum::new(1.0, 1.0, 1.0);
         let irradiance = light_col * light_dir.dot(&sample_dir).max(0.0);
-        let vis_test = VisibilityTest::PointInDirection { p: p.position + 0.0001 * p.normal, d: sample_dir, };
+        let vis_test = VisibilityTest::PointInDirection { p: p.offset_towards(&sample_dir), d: sample_dir, };
         
         RadianceSample {
             radiance: irradiance,
@@ -189,4 +222,43 @@ impl Emitter for TestLight {
     fn is_background(&self) ->bool {
         true
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use nalgebra::{Point3, Vector3};
+
+    use crate::{accelerator::Bvh, geometry::Ray, scene::{SceneBuilder, loader::{Loader, LoadOptions, Ply}}, spectrum::Spectrum, texture::Texture};
+
+    use super::{Emitter, DirectionalLight, PointLight, SkySphere};
+
+    const QUAD: &str = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n-1 -1 0\n1 -1 0\n1 1 0\n-1 1 0\n4 0 1 2 3\n";
+
+    // shadow rays leave from the side of the surface the light is on, so a point hit from behind
+    // doesn't shadow itself from the lights on either side.
+    #[test]
+    fn back_face_visibility_test() {
+        let mut builder = SceneBuilder::new();
+        Ply::load_from_reader(&mut Cursor::new(QUAD.as_bytes()), &mut builder, &LoadOptions::default()).unwrap();
+        let scene = builder.build::<Bvh>();
+
+        let p = scene.intersect(&Ray { origin: Point3::new(0.3, 0.2, -1.0), direction: Vector3::new(0.0, 0.0, 1.0) }).unwrap();
+        assert!(p.normal.z > 0.0);
+
+        let directional = |neg_direction: Vector3<f32>| DirectionalLight { neg_direction, irradiance: Spectrum::constant(1.0) };
+        assert!(directional(-Vector3::z()).sample(&p).visibility_test.eval(&scene));
+        assert!(directional(Vector3::z()).sample(&p).visibility_test.eval(&scene));
+
+        let point = |z: f32| PointLight { position: Point3::new(0.3, 0.2, z), intensity: Spectrum::constant(1.0) };
+        assert!(point(-1.0).sample(&p).visibility_test.eval(&scene));
+        assert!(point(1.0).sample(&p).visibility_test.eval(&scene));
+
+        // the sky is sampled on both sides of the surface.
+        let sky = SkySphere::new(Texture::new(1, 1, &Spectrum::constant(1.0)));
+        let samples: Vec<_> = (0..100).map(|_| sky.sample(&p)).collect();
+        assert!(samples.iter().any(|sample| sample.direction.z < 0.0));
+        assert!(samples.into_iter().all(|sample| sample.visibility_test.eval(&scene)));
+    }
+}
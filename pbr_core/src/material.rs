@@ -1,15 +1,31 @@
 mod lambert;
+mod oren_nayar;
 mod micro;
 mod metal;
+mod plastic;
+mod principled;
+mod glass;
+mod mix;
+mod normal_map;
+mod emissive;
+mod energy;
 
-use crate::spectrum::Spectrum;
+use crate::{spectrum::Spectrum, geometry::SurfacePoint};
 
 pub use self::lambert::LambertianMaterial;
+pub use self::oren_nayar::OrenNayarMaterial;
 pub use micro::MicrofacetMaterial;
-pub use self::metal::MetalMaterial;
+pub use self::metal::{MetalMaterial, Metal};
+pub use self::plastic::PlasticMaterial;
+pub use self::principled::PrincipledMaterial;
+pub use self::glass::ThinDielectricMaterial;
+pub use self::mix::MixMaterial;
+pub use self::normal_map::NormalMappedMaterial;
+pub use self::emissive::EmissiveMaterial;
+pub use self::energy::AlbedoTable;
 
 
-use std::f32::consts::PI;
+use std::{f32::consts::PI, sync::OnceLock};
 use nalgebra::{Vector3, Point2};
 use rand::{thread_rng, Rng};
 
@@ -21,9 +37,14 @@ pub struct BrdfSample {
 }
 
 pub trait Material: Sync + Send {
-    fn brdf(&self, uv: &Point2<f32>, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32>;
-    fn sample_brdf(&self, uv: &Point2<f32>, wo: &Vector3<f32>) -> BrdfSample;
-    fn is_delta(&self, uv: &Point2<f32>) -> bool;
+    fn brdf(&self, p: &SurfacePoint, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32>;
+    fn sample_brdf(&self, p: &SurfacePoint, wo: &Vector3<f32>) -> BrdfSample;
+    fn is_delta(&self, p: &SurfacePoint) -> bool;
+
+    // radiance leaving the surface towards wo, only emissive materials give off light.
+    fn emission(&self, _p: &SurfacePoint, _wo: &Vector3<f32>) -> Spectrum<f32> {
+        Spectrum::black()
+    }
 }
 
 pub trait MicrofacetDistribution {
@@ -31,13 +52,23 @@ pub trait MicrofacetDistribution {
     
     fn facet_density(&self, m: &Vector3<f32>) -> f32;
     fn shadowing(&self, v: &Vector3<f32>, m: &Vector3<f32>) -> f32;
-    fn sample_facet(&self, o: &Vector3<f32>) -> (Vector3<f32>, f32);
+    // maps a point of the unit square to a facet normal distributed by the projected facet density.
+    fn map_facet(&self, u: &Point2<f32>) -> (Vector3<f32>, f32);
+
+    fn sample_facet(&self, _o: &Vector3<f32>) -> (Vector3<f32>, f32) {
+        let mut rng = thread_rng();
+        self.map_facet(&Point2::new(rng.gen(), rng.gen()))
+    }
+
+    fn albedo_table() -> &'static AlbedoTable where Self: Sized;
 }
 
 
 // Source: https://www.cs.cornell.edu/~srm/publications/EGSR07-btdf.pdf
+// Anisotropic form from: https://jcgt.org/published/0003/02/03/paper.pdf
 pub struct Ggx {
-    alpha: f32,    
+    alpha_x: f32,
+    alpha_y: f32,
 }
 
 fn ndot(v: &Vector3<f32>) -> f32 {
@@ -48,19 +79,99 @@ fn heavi(a: f32) -> f32 {
     if a > 0.0 { 1.0 } else { 0.0 }
 } 
 
+// mirrors v into the hemisphere of wo, so lobes can be evaluated as if wo was above the surface.
+fn flip(v: &Vector3<f32>, wo: &Vector3<f32>) -> Vector3<f32> {
+    if ndot(wo) < 0.0 { Vector3::new(v.x, v.y, -v.z) } else { *v }
+}
+
+// exact fresnel reflectance of an unpolarized dielectric interface, eta is the ratio of the
+// refractive index on the far side to the one on the incident side.
+fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+
+    0.5 * (r_parl * r_parl + r_perp * r_perp)
+}
+
+// the share of light from wo a material reflects back to the side of wo and transmits through
+// the surface, estimated from its own samples. every sample has to be finite.
+#[cfg(test)]
+fn albedo(material: &dyn Material, wo: &Vector3<f32>, n: usize) -> (f32, f32) {
+    let p = SurfacePoint::from_vertex(&crate::scene::Vertex::default(), material);
+
+    let (mut reflected, mut transmitted) = (0.0, 0.0);
+    for _ in 0..n {
+        let sample = material.sample_brdf(&p, wo);
+        let value = sample.brdf.g * sample.wi.z.abs() / sample.pdf;
+        assert!(value.is_finite(), "wo: {:?}, wi: {:?}, brdf: {:?}, pdf: {}", wo, sample.wi, sample.brdf, sample.pdf);
+
+        if sample.wi.z * wo.z > 0.0 {
+            reflected += value;
+        } else {
+            transmitted += value;
+        }
+    }
+
+    (reflected / n as f32, transmitted / n as f32)
+}
+
+// the share of light from wo a material scatters in total, 1 without absorption.
+#[cfg(test)]
+fn white_furnace(material: &dyn Material, wo: &Vector3<f32>, n: usize) -> f32 {
+    let (reflected, transmitted) = albedo(material, wo, n);
+    reflected + transmitted
+}
+
+// refracts v around the facet normal m, returns none on total internal reflection.
+fn refract(v: &Vector3<f32>, m: &Vector3<f32>, eta: f32) -> Option<Vector3<f32>> {
+    let cos_i = v.dot(m);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+
+    if sin2_t >= 1.0 {
+        return None;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-v / eta + (cos_i / eta - cos_t) * m)
+}
+
+impl Ggx {
+    // stretches the distribution along the tangent for positive anisotropy and along the bitangent for negative.
+    pub fn new_anisotropic(roughness: f32, anisotropy: f32) -> Self {
+        let alpha = roughness * roughness;
+        let aspect = (1.0 - 0.9 * anisotropy.abs()).sqrt();
+
+        let (alpha_x, alpha_y) = if anisotropy >= 0.0 {
+            (alpha / aspect, alpha * aspect)
+        } else {
+            (alpha * aspect, alpha / aspect)
+        };
+
+        Self { alpha_x: alpha_x.max(1e-3), alpha_y: alpha_y.max(1e-3) }
+    }
+}
+
 impl MicrofacetDistribution for Ggx {
 
+    // a roughness of 0 is kept as a very narrow lobe, the density is not defined for alpha 0.
     fn new_isotropic(roughness: f32) -> Self {
-        Self { alpha: roughness * roughness }
+        let alpha = (roughness * roughness).max(1e-3);
+        Self { alpha_x: alpha, alpha_y: alpha }
     }
 
     fn facet_density(&self, m: &Vector3<f32>) -> f32 {
-        let alpha2 = self.alpha * self.alpha;
-        
         let mdotn = ndot(m);
-        let paren = (alpha2 - 1.0) * mdotn * mdotn + 1.0;
-        
-        let density = alpha2 * heavi(mdotn) / (PI * paren * paren);
+        let paren = (m.x / self.alpha_x).powi(2) + (m.y / self.alpha_y).powi(2) + mdotn * mdotn;
+
+        let density = heavi(mdotn) / (PI * self.alpha_x * self.alpha_y * paren * paren);
 
         if density.is_nan() {
             println!("NaN density, m: {:?}", m);
@@ -76,9 +187,9 @@ impl MicrofacetDistribution for Ggx {
         let vdotn = ndot(v);
 
         let s = if (vdotm < 0.0) == (vdotn < 0.0) {
-            let alpha2 = self.alpha * self.alpha;
-            let paren = alpha2 + (1.0 - alpha2) * vdotn * vdotn;
-            2.0 * vdotn / (vdotn + paren.sqrt())
+            let cos = vdotn.abs();
+            let paren = (v.x * self.alpha_x).powi(2) + (v.y * self.alpha_y).powi(2) + cos * cos;
+            2.0 * cos / (cos + paren.sqrt())
         } else {
             0.0
         };
@@ -90,11 +201,81 @@ impl MicrofacetDistribution for Ggx {
         s
     }
 
-    fn sample_facet(&self, _o: &Vector3<f32>) -> (Vector3<f32>, f32) {
-        let mut rng = thread_rng();
+    fn map_facet(&self, u: &Point2<f32>) -> (Vector3<f32>, f32) {
+        // sample the slope of the facet in the stretched configuration and unstretch it.
+        let phi = 2.0 * PI * u.y;
+        let slope = (u.x / (1.0 - u.x)).sqrt();
+        let m = Vector3::new(
+            -slope * self.alpha_x * phi.cos(),
+            -slope * self.alpha_y * phi.sin(),
+            1.0
+        ).normalize();
 
-        let u: Point2<f32> = Point2::new(rng.gen(), rng.gen());
-        let theta_m = (self.alpha * (u.x / (1.0 - u.x)).sqrt()).atan();
+        if m.x.is_nan() || m.y.is_nan() || m.z.is_nan() {
+            println!("Bad facet sampled, u: {:?}", u);
+        }
+
+        let pdf = self.facet_density(&m) * ndot(&m);
+
+        (m, pdf)
+    }
+
+    fn albedo_table() -> &'static AlbedoTable {
+        static TABLE: OnceLock<AlbedoTable> = OnceLock::new();
+        TABLE.get_or_init(AlbedoTable::new::<Self>)
+    }
+}
+
+// Source: https://www.cs.cornell.edu/~srm/publications/EGSR07-btdf.pdf
+pub struct Beckmann {
+    alpha: f32,
+}
+
+impl MicrofacetDistribution for Beckmann {
+
+    fn new_isotropic(roughness: f32) -> Self {
+        Self { alpha: (roughness * roughness).max(1e-3) }
+    }
+
+    fn facet_density(&self, m: &Vector3<f32>) -> f32 {
+        let alpha2 = self.alpha * self.alpha;
+
+        let mdotn = ndot(m);
+        if mdotn <= 0.0 {
+            return 0.0;
+        }
+
+        let cos2 = mdotn * mdotn;
+        let tan2 = (1.0 - cos2) / cos2;
+
+        (-tan2 / alpha2).exp() / (PI * alpha2 * cos2 * cos2)
+    }
+
+    fn shadowing(&self, v: &Vector3<f32>, m: &Vector3<f32>) -> f32 {
+
+        let vdotm = m.dot(v);
+        let vdotn = ndot(v);
+
+        if (vdotm < 0.0) != (vdotn < 0.0) {
+            return 0.0;
+        }
+
+        let cos2 = vdotn * vdotn;
+        let tan = ((1.0 - cos2).max(0.0) / cos2).sqrt();
+        let a = 1.0 / (self.alpha * tan);
+
+        // rational approximation of the smith shadowing term
+        if a < 1.6 {
+            let a2 = a * a;
+            (3.535 * a + 2.181 * a2) / (1.0 + 2.276 * a + 2.577 * a2)
+        } else {
+            1.0
+        }
+    }
+
+    fn map_facet(&self, u: &Point2<f32>) -> (Vector3<f32>, f32) {
+        let alpha2 = self.alpha * self.alpha;
+        let theta_m = (-alpha2 * (1.0 - u.x).ln()).sqrt().atan();
         let phi_m = 2.0 * PI * u.y;
 
         let m = Vector3::new(
@@ -103,14 +284,15 @@ impl MicrofacetDistribution for Ggx {
             theta_m.cos()
         );
 
-        if m.x.is_nan() || m.y.is_nan() || m.z.is_nan() {
-            println!("Bad facet sampled, theta: {}, phi: {}", theta_m, phi_m);
-        }
-
         let pdf = self.facet_density(&m) * ndot(&m);
 
         (m, pdf)
     }
+
+    fn albedo_table() -> &'static AlbedoTable {
+        static TABLE: OnceLock<AlbedoTable> = OnceLock::new();
+        TABLE.get_or_init(AlbedoTable::new::<Self>)
+    }
 }
 
 #[cfg(test)]
@@ -120,7 +302,51 @@ mod tests {
     use nalgebra::Vector3;
     use rand::Rng;
 
-    use super::{Ggx, MicrofacetDistribution};
+    use crate::{geometry::SurfacePoint, scene::Vertex, spectrum::Spectrum};
+
+    use super::{Ggx, Beckmann, MicrofacetDistribution, Material, LambertianMaterial, PlasticMaterial};
+
+    // integrates D(m) (m.n) over the hemisphere, which is 1 for any valid distribution.
+    fn projected_area<T: MicrofacetDistribution>(distr: &T) -> f32 {
+        let steps = 4096;
+        let dtheta = 0.5 * PI / steps as f32;
+
+        (0..steps)
+            .map(|i| (i as f32 + 0.5) * dtheta)
+            .map(|theta| {
+                let m = Vector3::new(theta.sin(), 0.0, theta.cos());
+                distr.facet_density(&m) * theta.cos() * theta.sin() * 2.0 * PI * dtheta
+            })
+            .sum()
+    }
+
+    // compares the mean facet cosine of sample_facet against quadrature of the density.
+    fn check_sampling<T: MicrofacetDistribution>(distr: &T) {
+        let steps = 4096;
+        let dtheta = 0.5 * PI / steps as f32;
+
+        let expected: f32 = (0..steps)
+            .map(|i| (i as f32 + 0.5) * dtheta)
+            .map(|theta| {
+                let m = Vector3::new(theta.sin(), 0.0, theta.cos());
+                let cos = theta.cos();
+                cos * distr.facet_density(&m) * cos * theta.sin() * 2.0 * PI * dtheta
+            })
+            .sum();
+
+        let n = 100000;
+        let o = Vector3::new(0.0, 0.0, 1.0);
+        let mean = (0..n)
+            .map(|_| {
+                let (m, pdf) = distr.sample_facet(&o);
+                assert!(pdf > 0.0);
+                assert!((pdf - distr.facet_density(&m) * m.z).abs() <= 1e-3 * pdf);
+                m.z
+            })
+            .sum::<f32>() / n as f32;
+
+        assert!((mean - expected).abs() < 0.01, "mean: {}, expected: {}", mean, expected);
+    }
 
     #[test]
     fn ggx_facet_density_test() {
@@ -142,4 +368,85 @@ mod tests {
         
     }
 
+    // the isotropic density only depends on theta, falls off away from the normal and vanishes below the surface.
+    #[test]
+    fn beckmann_facet_density_test() {
+        let mut rng = rand::thread_rng();
+
+        let distr = Beckmann::new_isotropic(f32::sqrt(0.2));
+        let mut prev = f32::INFINITY;
+
+        for theta in (0..100).map(|x| x as f32 * PI / 100.0) {
+            let facet = |phi: f32| Vector3::new(
+                phi.cos() * theta.sin(),
+                phi.sin() * theta.sin(),
+                theta.cos()
+            );
+
+            let density = distr.facet_density(&facet(0.0));
+            let rotated = distr.facet_density(&facet(2.0 * PI * rng.gen::<f32>()));
+            assert!((density - rotated).abs() <= 1e-4 * density, "theta: {}, density: {}, rotated: {}", theta, density, rotated);
+
+            if theta.cos() <= 0.0 {
+                assert_eq!(density, 0.0, "theta: {}", theta);
+            } else {
+                assert!(density <= prev, "theta: {}, density: {}, previous: {}", theta, density, prev);
+            }
+            prev = density;
+        }
+    }
+
+    // surfaces are shaded on whichever side wo is on, as if it was the front.
+    #[test]
+    fn two_sided_test() {
+        let materials: [Box<dyn Material>; 2] = [
+            Box::new(LambertianMaterial::flat(Spectrum::constant(0.5))),
+            Box::new(PlasticMaterial::<Ggx>::flat(0.5, Spectrum::constant(0.5))),
+        ];
+        let mirror = |v: Vector3<f32>| Vector3::new(v.x, v.y, -v.z);
+
+        for material in &materials {
+            let p = SurfacePoint::from_vertex(&Vertex::default(), material.as_ref());
+            let (wi, wo) = (Vector3::new(0.6, 0.0, 0.8), Vector3::new(0.0, 0.28, 0.96));
+
+            assert_eq!(material.brdf(&p, &mirror(wi), &mirror(wo)), material.brdf(&p, &wi, &wo));
+            assert_eq!(material.brdf(&p, &mirror(wi), &wo), Spectrum::black());
+
+            for _ in 0..100 {
+                let sample = material.sample_brdf(&p, &mirror(wo));
+                assert!(sample.wi.z < 0.0 || sample.brdf == Spectrum::black());
+            }
+        }
+    }
+
+    #[test]
+    fn ggx_projected_area_test() {
+        for roughness in [0.3, 0.5, 0.8, 1.0] {
+            let area = projected_area(&Ggx::new_isotropic(roughness));
+            assert!((area - 1.0).abs() < 1e-2, "roughness: {}, area: {}", roughness, area);
+        }
+    }
+
+    #[test]
+    fn beckmann_projected_area_test() {
+        for roughness in [0.3, 0.5, 0.8, 1.0] {
+            let area = projected_area(&Beckmann::new_isotropic(roughness));
+            assert!((area - 1.0).abs() < 1e-2, "roughness: {}, area: {}", roughness, area);
+        }
+    }
+
+    #[test]
+    fn ggx_sampling_test() {
+        for roughness in [0.3, 0.5, 0.8] {
+            check_sampling(&Ggx::new_isotropic(roughness));
+        }
+    }
+
+    #[test]
+    fn beckmann_sampling_test() {
+        for roughness in [0.3, 0.5, 0.8] {
+            check_sampling(&Beckmann::new_isotropic(roughness));
+        }
+    }
+
 }
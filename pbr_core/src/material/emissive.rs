@@ -0,0 +1,70 @@
+use nalgebra::Vector3;
+
+use crate::{geometry::SurfacePoint, spectrum::Spectrum};
+
+use super::{Material, BrdfSample};
+
+// Makes a surface glow with a constant radiance on top of reflecting like the wrapped material.
+// Only the side the normal points to emits unless it is two sided. Surfaces are found by the
+// integrators when paths hit them, they are not sampled like light sources.
+pub struct EmissiveMaterial {
+    material: Box<dyn Material>,
+    radiance: Spectrum<f32>,
+    two_sided: bool,
+}
+
+impl EmissiveMaterial {
+    pub fn new(material: Box<dyn Material>, radiance: Spectrum<f32>, two_sided: bool) -> Self {
+        Self { material, radiance, two_sided }
+    }
+}
+
+impl Material for EmissiveMaterial {
+    fn brdf(&self, p: &SurfacePoint, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32> {
+        self.material.brdf(p, wi, wo)
+    }
+
+    fn sample_brdf(&self, p: &SurfacePoint, wo: &Vector3<f32>) -> BrdfSample {
+        self.material.sample_brdf(p, wo)
+    }
+
+    fn is_delta(&self, p: &SurfacePoint) -> bool {
+        self.material.is_delta(p)
+    }
+
+    fn emission(&self, _p: &SurfacePoint, wo: &Vector3<f32>) -> Spectrum<f32> {
+        if wo.z > 0.0 || self.two_sided {
+            self.radiance
+        } else {
+            Spectrum::black()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use crate::{geometry::SurfacePoint, material::{Material, LambertianMaterial}, scene::Vertex, spectrum::Spectrum};
+
+    use super::EmissiveMaterial;
+
+    #[test]
+    fn emission_side_test() {
+        let radiance = Spectrum::new(1.0, 2.0, 3.0);
+        let vertex = Vertex { normal: Vector3::z(), tangent: Vector3::x(), ..Default::default() };
+        let base = LambertianMaterial::flat(Spectrum::constant(0.5));
+        let p = SurfacePoint::from_vertex(&vertex, &base);
+
+        let one_sided = EmissiveMaterial::new(Box::new(LambertianMaterial::flat(Spectrum::constant(0.5))), radiance, false);
+        assert_eq!(one_sided.emission(&p, &Vector3::new(0.0, 0.6, 0.8)), radiance);
+        assert_eq!(one_sided.emission(&p, &Vector3::new(0.0, 0.6, -0.8)), Spectrum::black());
+
+        let two_sided = EmissiveMaterial::new(Box::new(LambertianMaterial::flat(Spectrum::constant(0.5))), radiance, true);
+        assert_eq!(two_sided.emission(&p, &Vector3::new(0.0, 0.6, -0.8)), radiance);
+
+        let wi = Vector3::new(0.0, 0.0, 1.0);
+        assert_eq!(one_sided.brdf(&p, &wi, &wi), base.brdf(&p, &wi, &wi));
+        assert_eq!(base.emission(&p, &wi), Spectrum::black());
+    }
+}
@@ -0,0 +1,227 @@
+use std::f32::consts::PI;
+
+use nalgebra::{Point2, Vector3};
+use rand::{thread_rng, Rng};
+
+use crate::{spectrum::Spectrum, geometry::cosine_hemisphere_map};
+
+use super::{MicrofacetDistribution, ndot};
+
+const ROUGHNESS_STEPS: usize = 32;
+const COS_STEPS: usize = 32;
+const SAMPLES: usize = 4096;
+
+// Source: https://blog.selfshadow.com/publications/s2017-shading-course/imageworks/s2017_pbs_imageworks_slides_v2.pdf
+pub struct AlbedoTable {
+    // directional albedo E(mu) of the single scattering lobe with a fresnel of 1.
+    albedo: Box<[f32]>,
+    // hemispherical average of the albedo for each roughness.
+    average: Box<[f32]>,
+}
+
+impl AlbedoTable {
+    pub fn new<T: MicrofacetDistribution>() -> Self {
+        let mut albedo = vec![0.0; ROUGHNESS_STEPS * COS_STEPS].into_boxed_slice();
+        let mut average = vec![0.0; ROUGHNESS_STEPS].into_boxed_slice();
+
+        for i in 0..ROUGHNESS_STEPS {
+            let roughness = i as f32 / (ROUGHNESS_STEPS - 1) as f32;
+            let row = &mut albedo[i * COS_STEPS..(i + 1) * COS_STEPS];
+
+            if roughness == 0.0 {
+                // a perfect mirror with a fresnel of 1 does not lose any energy.
+                row.fill(1.0);
+            } else {
+                let distribution = T::new_isotropic(roughness);
+
+                for (j, e) in row.iter_mut().enumerate() {
+                    let mu = (j as f32 + 0.5) / COS_STEPS as f32;
+                    let wo = Vector3::new((1.0 - mu * mu).sqrt(), 0.0, mu);
+                    *e = directional_albedo(&distribution, &wo).min(1.0);
+                }
+            }
+
+            average[i] = row.iter()
+                .enumerate()
+                .map(|(j, e)| 2.0 * e * (j as f32 + 0.5) / COS_STEPS as f32 / COS_STEPS as f32)
+                .sum();
+        }
+
+        Self { albedo, average }
+    }
+
+    pub fn albedo(&self, roughness: f32, mu: f32) -> f32 {
+        let (i0, i1, s) = lerp_index(roughness * (ROUGHNESS_STEPS - 1) as f32, ROUGHNESS_STEPS);
+        let (j0, j1, t) = lerp_index(mu * COS_STEPS as f32 - 0.5, COS_STEPS);
+
+        let at = |i: usize, j: usize| self.albedo[i * COS_STEPS + j];
+        let e0 = at(i0, j0) * (1.0 - t) + at(i0, j1) * t;
+        let e1 = at(i1, j0) * (1.0 - t) + at(i1, j1) * t;
+
+        e0 * (1.0 - s) + e1 * s
+    }
+
+    pub fn average(&self, roughness: f32) -> f32 {
+        let (i0, i1, s) = lerp_index(roughness * (ROUGHNESS_STEPS - 1) as f32, ROUGHNESS_STEPS);
+        self.average[i0] * (1.0 - s) + self.average[i1] * s
+    }
+
+    // the multiple scattering lobe for a fresnel of 1, which makes up for the energy lost by the single scattering lobe.
+    pub fn multiple_scattering(&self, roughness: f32, wi: &Vector3<f32>, wo: &Vector3<f32>) -> f32 {
+        let idotn = ndot(wi);
+        let odotn = ndot(wo);
+        if idotn <= 0.0 || odotn <= 0.0 {
+            return 0.0;
+        }
+
+        let e_avg = self.average(roughness);
+        if e_avg >= 1.0 {
+            return 0.0;
+        }
+
+        let e_i = self.albedo(roughness, idotn);
+        let e_o = self.albedo(roughness, odotn);
+
+        (1.0 - e_i) * (1.0 - e_o) / (PI * (1.0 - e_avg))
+    }
+
+    // scales the multiple scattering lobe to account for the energy absorbed at each bounce, given the average fresnel.
+    pub fn fresnel_scale(&self, roughness: f32, f_avg: &Spectrum<f32>) -> Spectrum<f32> {
+        let e_avg = self.average(roughness);
+        f_avg.apply_into(|f| f * f * e_avg / (1.0 - f * (1.0 - e_avg)))
+    }
+}
+
+// the hemispherical average of the schlick fresnel approximation.
+pub fn schlick_average(r0: &Spectrum<f32>) -> Spectrum<f32> {
+    r0.apply_into(|r0| r0 + (1.0 - r0) / 21.0)
+}
+
+// samples either a facet reflection or, with the share of energy the single scattering lobe loses,
+// a cosine weighted direction for the multiple scattering lobe. wo has to be above the surface,
+// returns wi and the pdf of the mixture, or none when the facet reflects wo below the surface.
+pub fn sample_microfacet<T: MicrofacetDistribution>(distribution: &T, roughness: f32, wo: &Vector3<f32>) -> Option<(Vector3<f32>, f32)> {
+    let mut rng = thread_rng();
+    let ms_probability = 1.0 - T::albedo_table().albedo(roughness, ndot(wo));
+
+    let wi = if rng.gen::<f32>() < ms_probability {
+        cosine_hemisphere_map(&Point2::new(rng.gen(), rng.gen()))
+    } else {
+        let (m, _) = distribution.sample_facet(wo);
+        (2.0 * m.dot(wo)) * m - wo
+    };
+
+    let pdf = microfacet_pdf(distribution, ms_probability, &wi, wo);
+    Some((wi, pdf)).filter(|_| pdf > 0.0 && pdf.is_finite())
+}
+
+fn microfacet_pdf<T: MicrofacetDistribution>(distribution: &T, ms_probability: f32, wi: &Vector3<f32>, wo: &Vector3<f32>) -> f32 {
+    let idotn = ndot(wi);
+    if idotn <= 0.0 {
+        return 0.0;
+    }
+
+    let m = wi + wo;
+    let facet_pdf = if m.norm_squared() == 0.0 {
+        0.0
+    } else {
+        let m = m.normalize();
+        distribution.facet_density(&m) * ndot(&m) / (4.0 * m.dot(wo))
+    };
+
+    (1.0 - ms_probability) * facet_pdf + ms_probability * idotn / PI
+}
+
+fn directional_albedo<T: MicrofacetDistribution>(distribution: &T, wo: &Vector3<f32>) -> f32 {
+    let odotn = ndot(wo);
+
+    let sum: f32 = (0..SAMPLES)
+        .map(|k| {
+            let (m, pdf_m) = distribution.map_facet(&hammersley(k, SAMPLES));
+            let mdoto = m.dot(wo);
+            if mdoto <= 0.0 || pdf_m <= 0.0 {
+                return 0.0;
+            }
+
+            let wi = (2.0 * mdoto) * m - wo;
+            let idotn = ndot(&wi);
+            if idotn <= 0.0 {
+                return 0.0;
+            }
+
+            // brdf * cos / pdf with the facet jacobian folded in.
+            let shadowing = distribution.shadowing(&wi, &m) * distribution.shadowing(wo, &m);
+            let value = distribution.facet_density(&m) * shadowing * mdoto / (odotn * pdf_m);
+
+            if value.is_finite() { value } else { 0.0 }
+        })
+        .sum();
+
+    sum / SAMPLES as f32
+}
+
+// the k-th of n points of the hammersley set, the second coordinate is the base 2 radical inverse of k.
+fn hammersley(k: usize, n: usize) -> Point2<f32> {
+    let radical_inverse = (k as u32).reverse_bits() as f64 / (1u64 << 32) as f64;
+    Point2::new((k as f32 + 0.5) / n as f32, radical_inverse as f32)
+}
+
+fn lerp_index(x: f32, len: usize) -> (usize, usize, f32) {
+    let x = x.clamp(0.0, (len - 1) as f32);
+    let i0 = x.floor() as usize;
+    let i1 = (i0 + 1).min(len - 1);
+    (i0, i1, x - i0 as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use crate::{material::{Ggx, Beckmann, MicrofacetDistribution, MetalMaterial, white_furnace}, spectrum::Spectrum};
+
+    use super::AlbedoTable;
+
+    fn check_table<T: MicrofacetDistribution>() {
+        let table = AlbedoTable::new::<T>();
+
+        for roughness in [0.0, 0.25, 0.5, 0.75, 1.0] {
+            for mu in [0.1, 0.5, 0.9] {
+                let e = table.albedo(roughness, mu);
+                assert!(e > 0.0 && e <= 1.0, "roughness: {}, mu: {}, albedo: {}", roughness, mu, e);
+            }
+        }
+
+        // smooth surfaces keep nearly all their energy while rough ones lose a noticable part.
+        assert!(table.average(0.0) > 0.99);
+        assert!(table.average(1.0) < 0.8);
+        assert!(table.average(0.2) > table.average(0.8));
+
+        // the table is integrated over a fixed point set, so it is the same for every run.
+        let again = AlbedoTable::new::<T>();
+        assert_eq!(table.albedo, again.albedo);
+        assert_eq!(table.average, again.average);
+    }
+
+    #[test]
+    fn ggx_albedo_table_test() {
+        check_table::<Ggx>();
+    }
+
+    #[test]
+    fn beckmann_albedo_table_test() {
+        check_table::<Beckmann>();
+    }
+
+    #[test]
+    fn white_furnace_test() {
+        for roughness in [0.5, 1.0] {
+            let material = MetalMaterial::<Ggx>::new(roughness, Spectrum::constant(1.0));
+
+            for mu in [0.1f32, 0.3, 0.7, 1.0] {
+                let wo = Vector3::new((1.0 - mu * mu).sqrt(), 0.0, mu);
+                let albedo = white_furnace(&material, &wo, 50000);
+                assert!((albedo - 1.0).abs() < 0.05, "roughness: {}, mu: {}, albedo: {}", roughness, mu, albedo);
+            }
+        }
+    }
+}
@@ -1,5 +1,96 @@
+use nalgebra::Vector3;
+use rand::{thread_rng, Rng};
 
+use crate::{spectrum::Spectrum, texture::SurfaceTexture, geometry::SurfacePoint};
 
-struct Glass {
-    
+use super::{Material, BrdfSample, ndot, fresnel_dielectric};
+
+// A smooth dielectric sheet of negligible thickness, like a window pane or a soap bubble.
+// Light bouncing back and forth inside the sheet is summed up analytically, and transmitted
+// light leaves in the same direction it entered because the two interfaces are parallel.
+pub struct ThinDielectricMaterial {
+    ior: f32,
+    tint: Box<dyn SurfaceTexture<Spectrum<f32>>>,
+}
+
+impl ThinDielectricMaterial {
+    pub fn new(ior: f32) -> Self {
+        Self::tinted(ior, Spectrum::constant(1.0))
+    }
+
+    pub fn tinted(ior: f32, tint: impl SurfaceTexture<Spectrum<f32>> + 'static) -> Self {
+        Self { ior, tint: Box::new(tint) }
+    }
+
+    // total reflectance of the sheet, including all inter-reflections between the two interfaces.
+    fn reflectance(&self, cos: f32) -> f32 {
+        let r = fresnel_dielectric(cos, self.ior);
+        let t = 1.0 - r;
+
+        if r < 1.0 {
+            r + t * t * r / (1.0 - r * r)
+        } else {
+            r
+        }
+    }
+}
+
+impl Material for ThinDielectricMaterial {
+    fn brdf(&self, _p: &SurfacePoint, _wi: &Vector3<f32>, _wo: &Vector3<f32>) -> Spectrum<f32> {
+        Spectrum::black()
+    }
+
+    fn sample_brdf(&self, p: &SurfacePoint, wo: &Vector3<f32>) -> BrdfSample {
+        let cos = ndot(wo).abs();
+        if cos == 0.0 {
+            return BrdfSample { wi: -wo, brdf: Spectrum::black(), pdf: 1.0 };
+        }
+
+        let r = self.reflectance(cos);
+        let t = 1.0 - r;
+
+        // the brdf of the discrete lobes is divided by the cosine, so it cancels in the estimator.
+        if thread_rng().gen::<f32>() < r {
+            let wi = Vector3::new(-wo.x, -wo.y, wo.z);
+            BrdfSample { wi, brdf: Spectrum::constant(r / cos), pdf: r }
+        } else {
+            BrdfSample { wi: -wo, brdf: self.tint.evaluate(p) * (t / cos), pdf: t }
+        }
+    }
+
+    fn is_delta(&self, _p: &SurfacePoint) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use crate::{geometry::SurfacePoint, material::{Material, albedo}, scene::Vertex};
+
+    use super::ThinDielectricMaterial;
+
+    #[test]
+    fn thin_dielectric_test() {
+        let material = ThinDielectricMaterial::new(1.5);
+        let p = SurfacePoint::from_vertex(&Vertex::default(), &material);
+
+        // a single interface reflects 4% at normal incidence, the sheet roughly twice that.
+        let r = material.reflectance(1.0);
+        assert!((r - 0.0769).abs() < 1e-3, "reflectance: {}", r);
+
+        for wo in [Vector3::new(0.0, 0.6, 0.8), Vector3::new(0.0, 0.6, -0.8)] {
+            // light is either mirrored or passes straight through.
+            for _ in 0..100 {
+                let sample = material.sample_brdf(&p, &wo);
+                let mirrored = Vector3::new(-wo.x, -wo.y, wo.z);
+                assert!((sample.wi - mirrored).norm() < 1e-6 || (sample.wi + wo).norm() < 1e-6, "wi: {:?}", sample.wi);
+            }
+
+            let (reflected, transmitted) = albedo(&material, &wo, 10000);
+            assert!((reflected - material.reflectance(0.8)).abs() < 0.02);
+            assert!((reflected + transmitted - 1.0).abs() < 1e-3);
+        }
+    }
 }
@@ -3,19 +3,17 @@ use std::f32::consts::FRAC_1_PI;
 use nalgebra::{Vector3, Point2};
 use rand::{thread_rng, Rng};
 
-use crate::{texture::{FactoredTexture}, geometry::cosine_hemisphere_map, spectrum::Spectrum};
+use crate::{texture::{FactoredTexture, SurfaceTexture}, geometry::{cosine_hemisphere_map, SurfacePoint}, spectrum::Spectrum};
 
-use super::{Material, BrdfSample};
+use super::{Material, BrdfSample, ndot, flip};
 
 pub struct LambertianMaterial {
-    texture: FactoredTexture<Spectrum<f32>>,
+    texture: Box<dyn SurfaceTexture<Spectrum<f32>>>,
 }
 
 impl LambertianMaterial {
-    pub fn new(texture: FactoredTexture<Spectrum<f32>>) -> Self {
-        let mut texture = texture;
-        texture.factor *= FRAC_1_PI;
-        Self { texture, }
+    pub fn new(texture: impl SurfaceTexture<Spectrum<f32>> + 'static) -> Self {
+        Self { texture: Box::new(texture), }
     }
 
     pub fn flat(color: Spectrum<f32>) -> Self {
@@ -25,21 +23,25 @@ impl LambertianMaterial {
 }
 
 impl Material for LambertianMaterial {
-    fn brdf(&self, uv: &Point2<f32>,  _wi: &Vector3<f32>, _wo: &Vector3<f32>) -> Spectrum<f32> {
-        self.texture.sample(uv)
+    fn brdf(&self, p: &SurfacePoint,  wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32> {
+        if ndot(wi) * ndot(wo) < 0.0 {
+            return Spectrum::black();
+        }
+
+        self.texture.evaluate(p) * FRAC_1_PI
     } 
 
-    fn sample_brdf(&self, uv: &Point2<f32>, wo: &Vector3<f32>) -> BrdfSample {
+    fn sample_brdf(&self, p: &SurfacePoint, wo: &Vector3<f32>) -> BrdfSample {
         let mut rng = thread_rng();
         let u = Point2::new(rng.gen(), rng.gen());
-        let wi = cosine_hemisphere_map(&u);
-        let pdf = wi.z * FRAC_1_PI;
-        let brdf = self.brdf(uv, &wi, wo);
+        let wi = flip(&cosine_hemisphere_map(&u), wo);
+        let pdf = wi.z.abs() * FRAC_1_PI;
+        let brdf = self.brdf(p, &wi, wo);
 
         BrdfSample { wi, brdf, pdf }
     }
 
-    fn is_delta(&self, _uv: &Point2<f32>) -> bool {
+    fn is_delta(&self, _p: &SurfacePoint) -> bool {
         false
     }
 }
@@ -1,39 +1,184 @@
 use std::{marker::PhantomData};
 
-use nalgebra::{Vector3, Point2};
+use nalgebra::Vector3;
 
-use crate::spectrum::Spectrum;
+use crate::{spectrum::Spectrum, texture::SurfaceTexture, geometry::SurfacePoint};
 
-use super::{MicrofacetDistribution, Material, BrdfSample, ndot};
+use super::{MicrofacetDistribution, Material, BrdfSample, ndot, flip, energy::{schlick_average, sample_microfacet}};
 
+// Measured metals with their complex index of refraction sampled at the red, green and blue wavelengths.
+// Source: https://refractiveindex.info
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metal {
+    Gold,
+    Copper,
+    Silver,
+    Aluminium,
+    Chrome,
+}
+
+impl Metal {
+    pub fn from_name(name: &str) -> Option<Metal> {
+        match name.to_lowercase().as_str() {
+            "gold" | "au" => Some(Metal::Gold),
+            "copper" | "cu" => Some(Metal::Copper),
+            "silver" | "ag" => Some(Metal::Silver),
+            "aluminium" | "aluminum" | "al" => Some(Metal::Aluminium),
+            "chrome" | "chromium" | "cr" => Some(Metal::Chrome),
+            _ => None,
+        }
+    }
+
+    pub fn eta(&self) -> Spectrum<f32> {
+        match self {
+            Metal::Gold => Spectrum::new(0.143, 0.374, 1.442),
+            Metal::Copper => Spectrum::new(0.200, 0.924, 1.102),
+            Metal::Silver => Spectrum::new(0.155, 0.117, 0.138),
+            Metal::Aluminium => Spectrum::new(1.657, 0.880, 0.521),
+            Metal::Chrome => Spectrum::new(3.180, 3.180, 2.010),
+        }
+    }
+
+    pub fn k(&self) -> Spectrum<f32> {
+        match self {
+            Metal::Gold => Spectrum::new(3.983, 2.385, 1.603),
+            Metal::Copper => Spectrum::new(3.912, 2.452, 2.142),
+            Metal::Silver => Spectrum::new(4.828, 3.122, 2.147),
+            Metal::Aluminium => Spectrum::new(9.224, 6.270, 4.837),
+            Metal::Chrome => Spectrum::new(3.300, 3.330, 3.040),
+        }
+    }
+}
+
+enum Fresnel {
+    Schlick(Box<dyn SurfaceTexture<Spectrum<f32>>>),
+    Conductor { eta: Spectrum<f32>, k: Spectrum<f32>, average: Spectrum<f32> },
+}
+
+// the fresnel term at a single point of the surface.
+enum PointFresnel {
+    Schlick(Spectrum<f32>),
+    Conductor { eta: Spectrum<f32>, k: Spectrum<f32>, average: Spectrum<f32> },
+}
+
+impl Fresnel {
+    fn conductor(eta: Spectrum<f32>, k: Spectrum<f32>) -> Fresnel {
+        let average = conductor_average(&eta, &k);
+        Fresnel::Conductor { eta, k, average }
+    }
+
+    fn at(&self, p: &SurfacePoint) -> PointFresnel {
+        match self {
+            Fresnel::Schlick(r0) => PointFresnel::Schlick(r0.evaluate(p)),
+            Fresnel::Conductor { eta, k, average } => PointFresnel::Conductor { eta: *eta, k: *k, average: *average },
+        }
+    }
+}
+
+impl PointFresnel {
+    fn evaluate(&self, cos: f32) -> Spectrum<f32> {
+        match self {
+            PointFresnel::Schlick(r0) => {
+                let pow5 = |x: f32| (x * x) * (x * x) * x;
+                r0 + (Spectrum::constant(1.0) - r0) * pow5(1.0 - cos)
+            }
+            PointFresnel::Conductor { eta, k, .. } => conductor_fresnel_spectrum(cos, eta, k),
+        }
+    }
+
+    // the hemispherical average of the fresnel term, used to scale the multiple scattering lobe.
+    fn average(&self) -> Spectrum<f32> {
+        match self {
+            PointFresnel::Schlick(r0) => schlick_average(r0),
+            PointFresnel::Conductor { average, .. } => *average,
+        }
+    }
+}
+
+fn conductor_fresnel_spectrum(cos: f32, eta: &Spectrum<f32>, k: &Spectrum<f32>) -> Spectrum<f32> {
+    Spectrum::new(
+        conductor_fresnel(cos, eta.r, k.r),
+        conductor_fresnel(cos, eta.g, k.g),
+        conductor_fresnel(cos, eta.b, k.b),
+    )
+}
+
+fn conductor_average(eta: &Spectrum<f32>, k: &Spectrum<f32>) -> Spectrum<f32> {
+    let steps = 64;
+    (0..steps)
+        .map(|i| {
+            let mu = (i as f32 + 0.5) / steps as f32;
+            conductor_fresnel_spectrum(mu, eta, k) * (2.0 * mu / steps as f32)
+        })
+        .fold(Spectrum::black(), |acc, f| acc + f)
+}
+
+// the exact fresnel reflectance of a conductor with complex index of refraction eta + ik.
+// Source: https://seblagarde.wordpress.com/2013/04/29/memo-on-fresnel-equations/
+fn conductor_fresnel(cos: f32, eta: f32, k: f32) -> f32 {
+    let cos = cos.clamp(0.0, 1.0);
+    let cos2 = cos * cos;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * a * cos;
+    let r_s = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let r_p = r_s * (t3 - t4) / (t3 + t4);
+
+    0.5 * (r_p + r_s)
+}
 
 pub struct MetalMaterial<T> {
-    roughness: f32,
-    base_reflectance: Spectrum<f32>,
+    roughness: Box<dyn SurfaceTexture<f32>>,
+    fresnel: Fresnel,
     _marker: PhantomData<T>
 }
 
 impl<T> MetalMaterial<T> {
-    pub fn new(roughness: f32, base_reflectance: Spectrum<f32>) -> MetalMaterial<T> {
+    pub fn new(roughness: impl SurfaceTexture<f32> + 'static, base_reflectance: impl SurfaceTexture<Spectrum<f32>> + 'static) -> MetalMaterial<T> {
+        Self::with_fresnel(roughness, Fresnel::Schlick(Box::new(base_reflectance)))
+    }
+
+    pub fn conductor(roughness: impl SurfaceTexture<f32> + 'static, eta: Spectrum<f32>, k: Spectrum<f32>) -> MetalMaterial<T> {
+        Self::with_fresnel(roughness, Fresnel::conductor(eta, k))
+    }
+
+    pub fn measured(roughness: impl SurfaceTexture<f32> + 'static, metal: Metal) -> MetalMaterial<T> {
+        Self::conductor(roughness, metal.eta(), metal.k())
+    }
+
+    pub fn named(roughness: impl SurfaceTexture<f32> + 'static, name: &str) -> Option<MetalMaterial<T>> {
+        Metal::from_name(name).map(|metal| Self::measured(roughness, metal))
+    }
+
+    fn with_fresnel(roughness: impl SurfaceTexture<f32> + 'static, fresnel: Fresnel) -> MetalMaterial<T> {
         MetalMaterial {
-            roughness,
-            base_reflectance,
+            roughness: Box::new(roughness),
+            fresnel,
             _marker: Default::default()
         }
     }
 }
 
-fn conductor_fresnel(r0: &Spectrum<f32>, i: &Vector3<f32>, m: &Vector3<f32>) -> Spectrum<f32> {
-    let pow5 = |x: f32| (x * x) * (x * x) * x;
-
-    r0 + (Spectrum::constant(1.0) - r0) * pow5(1.0 - i.dot(&m))
-}
-
 impl<T> Material for MetalMaterial<T> where
     T: MicrofacetDistribution + Send + Sync,
 {
-    fn brdf(&self, _uv: &Point2<f32>, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32> {
-        let distribution = T::new_isotropic(self.roughness);
+    fn brdf(&self, p: &SurfacePoint, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32> {
+        let roughness = self.roughness.evaluate(p);
+        let distribution = T::new_isotropic(roughness);
+        let fresnel = self.fresnel.at(p);
+
+        // shade whichever side of the surface wo is on.
+        let (wi, wo) = (&flip(wi, wo), &flip(wo, wo));
 
         let idotn = ndot(&wi);
         let odotn = ndot(&wo);
@@ -47,29 +192,78 @@ impl<T> Material for MetalMaterial<T> where
         }
         let m = m.normalize();
 
-        let fresnel = conductor_fresnel(&self.base_reflectance, wi, &m);
+        let single_fresnel = fresnel.evaluate(wi.dot(&m));
         let density = distribution.facet_density(&m);
         let shadowing = distribution.shadowing(wi, &m) * distribution.shadowing(wo, &m);
 
-        fresnel * (density * shadowing / (4.0 * idotn * odotn))
-    }
+        let single_scattering = single_fresnel * (density * shadowing / (4.0 * idotn * odotn));
 
-    fn sample_brdf(&self, uv: &Point2<f32>, wo: &Vector3<f32>) -> BrdfSample {
-        let distribution = T::new_isotropic(self.roughness);
+        let table = T::albedo_table();
+        let multiple_scattering = table.fresnel_scale(roughness, &fresnel.average()) * table.multiple_scattering(roughness, wi, wo);
 
-        let (m, pdf_m) = distribution.sample_facet(wo);
-        let mdoto = m.dot(wo);
+        single_scattering + multiple_scattering
+    }
 
-        let wi = (2.0 * mdoto) * m - wo;
+    fn sample_brdf(&self, p: &SurfacePoint, wo: &Vector3<f32>) -> BrdfSample {
+        let roughness = self.roughness.evaluate(p);
+        let distribution = T::new_isotropic(roughness);
 
-        let pdf = pdf_m / (4.0 * mdoto);
-        let brdf = self.brdf(uv, &wi, wo);
-        
-        BrdfSample { wi, brdf, pdf }
+        match sample_microfacet(&distribution, roughness, &flip(wo, wo)) {
+            Some((wi, pdf)) => {
+                let wi = flip(&wi, wo);
+                let brdf = self.brdf(p, &wi, wo);
+                BrdfSample { wi, brdf, pdf }
+            },
+            // a facet can reflect wo below the surface, which carries no light.
+            None => BrdfSample { wi: Vector3::new(0.0, 0.0, 1.0), brdf: Spectrum::black(), pdf: 1.0 },
+        }
     }
 
-    fn is_delta(&self, _uv: &Point2<f32>) -> bool {
-        self.roughness == 0.0
+    fn is_delta(&self, p: &SurfacePoint) -> bool {
+        self.roughness.evaluate(p) == 0.0
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use crate::material::fresnel_dielectric;
+
+    use super::{Metal, conductor_fresnel, conductor_fresnel_spectrum, conductor_average};
+
+    #[test]
+    fn conductor_fresnel_test() {
+        // without absorption the conductor fresnel is the dielectric one.
+        for cos in [0.1, 0.5, 1.0] {
+            let a = conductor_fresnel(cos, 1.5, 0.0);
+            let b = fresnel_dielectric(cos, 1.5);
+            assert!((a - b).abs() < 1e-4, "cos: {}, {} != {}", cos, a, b);
+        }
+
+        // at normal incidence the reflectance is ((eta - 1)^2 + k^2) / ((eta + 1)^2 + k^2).
+        let (eta, k) = (0.143, 3.983);
+        let expected = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+        assert!((conductor_fresnel(1.0, eta, k) - expected).abs() < 1e-4);
+
+        // every conductor becomes a perfect mirror at grazing angles.
+        assert!((conductor_fresnel(0.0, eta, k) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn measured_metals_test() {
+        assert_eq!(Metal::from_name("Gold"), Some(Metal::Gold));
+        assert_eq!(Metal::from_name("aluminum"), Some(Metal::Aluminium));
+        assert_eq!(Metal::from_name("unobtainium"), None);
+
+        // gold reflects more red than blue, silver is nearly white.
+        let gold = conductor_fresnel_spectrum(1.0, &Metal::Gold.eta(), &Metal::Gold.k());
+        assert!(gold.r > 0.9 && gold.b < 0.5);
+
+        let silver = conductor_fresnel_spectrum(1.0, &Metal::Silver.eta(), &Metal::Silver.k());
+        assert!(silver.r > 0.9 && silver.g > 0.9 && silver.b > 0.9);
+
+        // the average over the hemisphere stays close to the reflectance at normal incidence.
+        let average = conductor_average(&Metal::Gold.eta(), &Metal::Gold.k());
+        assert!((average.r - gold.r).abs() < 0.05 && average.b > gold.b);
+    }
+}
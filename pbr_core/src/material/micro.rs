@@ -1,26 +1,28 @@
 use std::marker::PhantomData;
 
-use nalgebra::{Vector3, Point2};
+use nalgebra::Vector3;
 
-use crate::spectrum::Spectrum;
+use crate::{spectrum::Spectrum, texture::SurfaceTexture, geometry::SurfacePoint};
 
-use super::{Material, BrdfSample, MicrofacetDistribution, ndot};
+use super::{Material, BrdfSample, MicrofacetDistribution, ndot, flip, energy::{schlick_average, sample_microfacet}};
+
+const R0: f32 = 0.04;
 
 fn fresnel_schlick(i: &Vector3<f32>, m: &Vector3<f32>) -> f32 {
     let pow5 = |x: f32| (x * x) * (x * x) * x;
-    let r0 = 0.04;
+    let r0 = R0;
 
     r0 + (1.0 - r0) * pow5(1.0 - i.dot(m)) 
 }
 
 pub struct MicrofacetMaterial<T> {
-    roughness: f32,
+    roughness: Box<dyn SurfaceTexture<f32>>,
     _marker: PhantomData<T>,
 }
 
 impl<T> MicrofacetMaterial<T> {
-    pub fn new(roughness: f32) -> MicrofacetMaterial<T> {
-        MicrofacetMaterial { roughness, _marker: Default::default() }
+    pub fn new(roughness: impl SurfaceTexture<f32> + 'static) -> MicrofacetMaterial<T> {
+        MicrofacetMaterial { roughness: Box::new(roughness), _marker: Default::default() }
     }
 }
 
@@ -28,10 +30,14 @@ impl<T> Material for MicrofacetMaterial<T> where
     T: MicrofacetDistribution + Send + Sync
 {
 
-    fn brdf(&self, _uv: &Point2<f32>, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32> {
+    fn brdf(&self, p: &SurfacePoint, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32> {
 
-        let distribution = T::new_isotropic(self.roughness);
+        let roughness = self.roughness.evaluate(p);
+        let distribution = T::new_isotropic(roughness);
         
+        // shade whichever side of the surface wo is on.
+        let (wi, wo) = (&flip(wi, wo), &flip(wo, wo));
+
         let idotn = ndot(&wi);
         let odotn = ndot(&wo);
         if idotn == 0.0 || odotn == 0.0 {
@@ -48,28 +54,32 @@ impl<T> Material for MicrofacetMaterial<T> where
         let density   = distribution.facet_density(&m);
         let shadowing = distribution.shadowing(wi, &m) * distribution.shadowing(wo, &m);
 
-        let brdf_value = fresnel * density * shadowing / (4.0 * idotn * odotn);
+        let single_scattering = fresnel * density * shadowing / (4.0 * idotn * odotn);
 
-        Spectrum::constant(brdf_value)
-    }
-
-    fn sample_brdf(&self, uv: &Point2<f32>, wo: &Vector3<f32>) -> BrdfSample {
-        
-        let distribution = T::new_isotropic(self.roughness);
+        let table = T::albedo_table();
+        let f_avg = schlick_average(&Spectrum::constant(R0));
+        let multiple_scattering = table.fresnel_scale(roughness, &f_avg) * table.multiple_scattering(roughness, wi, wo);
 
-        let (m, pdf_m) = distribution.sample_facet(wo);
-        let mdoto = m.dot(wo);
-
-        let wi = (2.0 * mdoto) * m - wo;
+        Spectrum::constant(single_scattering) + multiple_scattering
+    }
 
-        let pdf = pdf_m / (4.0 * mdoto);
-        let brdf = self.brdf(uv, &wi, wo);
-        
-        BrdfSample { wi, brdf, pdf }
+    fn sample_brdf(&self, p: &SurfacePoint, wo: &Vector3<f32>) -> BrdfSample {
+        let roughness = self.roughness.evaluate(p);
+        let distribution = T::new_isotropic(roughness);
+
+        match sample_microfacet(&distribution, roughness, &flip(wo, wo)) {
+            Some((wi, pdf)) => {
+                let wi = flip(&wi, wo);
+                let brdf = self.brdf(p, &wi, wo);
+                BrdfSample { wi, brdf, pdf }
+            },
+            // a facet can reflect wo below the surface, which carries no light.
+            None => BrdfSample { wi: Vector3::new(0.0, 0.0, 1.0), brdf: Spectrum::black(), pdf: 1.0 },
+        }
     }
 
-    fn is_delta(&self, _uv: &nalgebra::Point2<f32>) -> bool {
-        self.roughness == 0.0
+    fn is_delta(&self, p: &SurfacePoint) -> bool {
+        self.roughness.evaluate(p) == 0.0
     }
     
 }
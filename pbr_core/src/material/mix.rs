@@ -0,0 +1,65 @@
+use nalgebra::Vector3;
+use rand::{thread_rng, Rng};
+
+use crate::{geometry::SurfacePoint, spectrum::Spectrum};
+
+use super::{Material, BrdfSample};
+
+// Blends two materials, like a partly transmissive surface made of an opaque and a clear part.
+// Each sample is taken from one of them picked by its weight, so the weight cancels out and the
+// sample is returned as that material produced it.
+pub struct MixMaterial {
+    first: Box<dyn Material>,
+    second: Box<dyn Material>,
+    // the share of the second material.
+    weight: f32,
+}
+
+impl MixMaterial {
+    pub fn new(first: Box<dyn Material>, second: Box<dyn Material>, weight: f32) -> Self {
+        Self { first, second, weight: weight.clamp(0.0, 1.0) }
+    }
+}
+
+impl Material for MixMaterial {
+    fn brdf(&self, p: &SurfacePoint, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32> {
+        self.first.brdf(p, wi, wo) * (1.0 - self.weight) + self.second.brdf(p, wi, wo) * self.weight
+    }
+
+    fn sample_brdf(&self, p: &SurfacePoint, wo: &Vector3<f32>) -> BrdfSample {
+        if thread_rng().gen::<f32>() < self.weight {
+            self.second.sample_brdf(p, wo)
+        } else {
+            self.first.sample_brdf(p, wo)
+        }
+    }
+
+    fn is_delta(&self, p: &SurfacePoint) -> bool {
+        self.first.is_delta(p) && self.second.is_delta(p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use crate::{geometry::SurfacePoint, material::{Material, LambertianMaterial, ThinDielectricMaterial, albedo}, scene::Vertex, spectrum::Spectrum};
+
+    use super::MixMaterial;
+
+    #[test]
+    fn mix_test() {
+        let material = MixMaterial::new(
+            Box::new(LambertianMaterial::flat(Spectrum::constant(1.0))),
+            Box::new(ThinDielectricMaterial::new(1.5)),
+            0.25,
+        );
+        let p = SurfacePoint::from_vertex(&Vertex::default(), &material);
+        let (reflected, transmitted) = albedo(&material, &Vector3::new(0.0, 0.0, 1.0), 20000);
+
+        // only the clear quarter lets light through, the sheet transmits about 92% of it.
+        assert!((transmitted - 0.25 * 0.923).abs() < 0.02, "transmitted: {}", transmitted);
+        assert!((reflected + transmitted - 1.0).abs() < 0.02);
+        assert!(!material.is_delta(&p));
+    }
+}
@@ -0,0 +1,86 @@
+use nalgebra::{Vector3, Matrix3};
+
+use crate::{spectrum::Spectrum, texture::SurfaceTexture, geometry::SurfacePoint};
+
+use super::{Material, BrdfSample};
+
+// Shades another material in a frame tilted towards a tangent space normal, usually read from a normal map.
+pub struct NormalMappedMaterial {
+    material: Box<dyn Material>,
+    normal: Box<dyn SurfaceTexture<Vector3<f32>>>,
+}
+
+impl NormalMappedMaterial {
+    pub fn new(material: Box<dyn Material>, normal: impl SurfaceTexture<Vector3<f32>> + 'static) -> Self {
+        Self { material, normal: Box::new(normal) }
+    }
+
+    // the shading frame expressed in the tangent frame of the surface.
+    fn shading_to_tangent(&self, p: &SurfacePoint) -> Matrix3<f32> {
+        let n = self.normal.evaluate(p).normalize();
+
+        // gram-schmidt the surface tangent against the new normal.
+        let t = Vector3::new(1.0, 0.0, 0.0) - n * n.x;
+        let t = if t.norm_squared() > 1e-8 {
+            t.normalize()
+        } else {
+            Vector3::new(0.0, 1.0, 0.0).cross(&n).normalize()
+        };
+        let b = n.cross(&t);
+
+        Matrix3::from_columns(&[t, b, n])
+    }
+}
+
+impl Material for NormalMappedMaterial {
+    fn brdf(&self, p: &SurfacePoint, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32> {
+        let t2s = self.shading_to_tangent(p).transpose();
+        self.material.brdf(p, &(t2s * wi), &(t2s * wo))
+    }
+
+    fn sample_brdf(&self, p: &SurfacePoint, wo: &Vector3<f32>) -> BrdfSample {
+        let s2t = self.shading_to_tangent(p);
+        let sample = self.material.sample_brdf(p, &(s2t.transpose() * wo));
+
+        BrdfSample { wi: s2t * sample.wi, ..sample }
+    }
+
+    fn is_delta(&self, p: &SurfacePoint) -> bool {
+        self.material.is_delta(p)
+    }
+
+    fn emission(&self, p: &SurfacePoint, wo: &Vector3<f32>) -> Spectrum<f32> {
+        let t2s = self.shading_to_tangent(p).transpose();
+        self.material.emission(p, &(t2s * wo))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use crate::{geometry::SurfacePoint, material::{Material, LambertianMaterial}, scene::Vertex, spectrum::Spectrum};
+
+    use super::NormalMappedMaterial;
+
+    #[test]
+    fn normal_mapped_test() {
+        let flat = NormalMappedMaterial::new(Box::new(LambertianMaterial::flat(Spectrum::constant(1.0))), Vector3::new(0.0, 0.0, 1.0));
+        let p = SurfacePoint::from_vertex(&Vertex::default(), &flat);
+
+        let wi = Vector3::new(0.6, 0.0, 0.8);
+        let wo = Vector3::new(-0.6, 0.0, 0.8);
+        let expected = LambertianMaterial::flat(Spectrum::constant(1.0)).brdf(&p, &wi, &wo);
+        assert_eq!(flat.brdf(&p, &wi, &wo), expected);
+
+        // tilting the normal towards +x puts directions that graze the surface towards -x below the shading horizon.
+        let tilted = NormalMappedMaterial::new(Box::new(LambertianMaterial::flat(Spectrum::constant(1.0))), Vector3::new(0.8, 0.0, 0.6));
+        let grazing = Vector3::new(-0.9, 0.0, (1.0f32 - 0.81).sqrt());
+        assert_eq!(tilted.brdf(&p, &grazing, &wi), Spectrum::black());
+
+        for _ in 0..100 {
+            let sample = tilted.sample_brdf(&p, &wi);
+            assert!(sample.wi.dot(&Vector3::new(0.8, 0.0, 0.6)) >= -1e-5);
+        }
+    }
+}
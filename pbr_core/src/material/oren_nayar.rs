@@ -0,0 +1,108 @@
+use std::f32::consts::FRAC_1_PI;
+
+use nalgebra::{Vector3, Point2};
+use rand::{thread_rng, Rng};
+
+use crate::{texture::{FactoredTexture, SurfaceTexture}, geometry::{cosine_hemisphere_map, SurfacePoint}, spectrum::Spectrum};
+
+use super::{Material, BrdfSample, ndot, flip};
+
+// Source: https://www1.cs.columbia.edu/CAVE/publications/pdfs/Oren_SIGGRAPH94.pdf
+// sigma is the standard deviation of the facet slope angle in radians.
+pub struct OrenNayarMaterial {
+    texture: Box<dyn SurfaceTexture<Spectrum<f32>>>,
+    sigma: Box<dyn SurfaceTexture<f32>>,
+}
+
+impl OrenNayarMaterial {
+    pub fn new(texture: impl SurfaceTexture<Spectrum<f32>> + 'static, sigma: impl SurfaceTexture<f32> + 'static) -> Self {
+        Self { texture: Box::new(texture), sigma: Box::new(sigma) }
+    }
+
+    pub fn flat(color: Spectrum<f32>, sigma: f32) -> Self {
+        let texture = FactoredTexture::new(color, None);
+        Self::new(texture, sigma)
+    }
+}
+
+impl Material for OrenNayarMaterial {
+    fn brdf(&self, p: &SurfacePoint, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32> {
+        if ndot(wi) * ndot(wo) < 0.0 {
+            return Spectrum::black();
+        }
+
+        let sigma = self.sigma.evaluate(p);
+        let sigma2 = sigma * sigma;
+        let a = 1.0 - sigma2 / (2.0 * (sigma2 + 0.33));
+        let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+        let (wi, wo) = (flip(wi, wo), flip(wo, wo));
+
+        let sin_i = (1.0 - wi.z * wi.z).max(0.0).sqrt();
+        let sin_o = (1.0 - wo.z * wo.z).max(0.0).sqrt();
+
+        // cosine of the azimuthal angle between wi and wo.
+        let cos_phi = if sin_i > 1e-4 && sin_o > 1e-4 {
+            ((wi.x * wo.x + wi.y * wo.y) / (sin_i * sin_o)).max(0.0)
+        } else {
+            0.0
+        };
+
+        let (sin_alpha, tan_beta) = if wi.z.abs() > wo.z.abs() {
+            (sin_o, sin_i / wi.z.abs())
+        } else {
+            (sin_i, sin_o / wo.z.abs())
+        };
+
+        self.texture.evaluate(p) * (FRAC_1_PI * (a + b * cos_phi * sin_alpha * tan_beta))
+    }
+
+    fn sample_brdf(&self, p: &SurfacePoint, wo: &Vector3<f32>) -> BrdfSample {
+        let mut rng = thread_rng();
+        let u = Point2::new(rng.gen(), rng.gen());
+        let wi = flip(&cosine_hemisphere_map(&u), wo);
+        let pdf = wi.z.abs() * FRAC_1_PI;
+        let brdf = self.brdf(p, &wi, wo);
+
+        BrdfSample { wi, brdf, pdf }
+    }
+
+    fn is_delta(&self, _p: &SurfacePoint) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use crate::{geometry::SurfacePoint, material::{Material, LambertianMaterial}, scene::Vertex, spectrum::Spectrum};
+
+    use super::OrenNayarMaterial;
+
+    #[test]
+    fn oren_nayar_smooth_is_lambertian_test() {
+        let oren_nayar = OrenNayarMaterial::flat(Spectrum::constant(0.5), 0.0);
+        let lambert = LambertianMaterial::flat(Spectrum::constant(0.5));
+        let p = SurfacePoint::from_vertex(&Vertex::default(), &oren_nayar);
+
+        let wi = Vector3::new(0.6, 0.0, 0.8);
+        let wo = Vector3::new(-0.3, 0.4, (1.0f32 - 0.25).sqrt());
+
+        let a = oren_nayar.brdf(&p, &wi, &wo);
+        let b = lambert.brdf(&p, &wi, &wo);
+        assert!((a.g - b.g).abs() < 1e-6);
+    }
+
+    #[test]
+    fn oren_nayar_backscatter_test() {
+        let material = OrenNayarMaterial::flat(Spectrum::constant(1.0), 0.5);
+        let p = SurfacePoint::from_vertex(&Vertex::default(), &material);
+
+        // rough surfaces reflect more light back towards a grazing light than away from it.
+        let wi = Vector3::new(0.8, 0.0, 0.6);
+        let back = material.brdf(&p, &wi, &wi);
+        let forward = material.brdf(&p, &wi, &Vector3::new(-0.8, 0.0, 0.6));
+        assert!(back.g > forward.g);
+    }
+}
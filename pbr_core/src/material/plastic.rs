@@ -0,0 +1,199 @@
+use std::{f32::consts::FRAC_1_PI, marker::PhantomData};
+
+use nalgebra::{Vector3, Point2};
+use rand::{thread_rng, Rng};
+
+use crate::{texture::{FactoredTexture, SurfaceTexture}, geometry::{cosine_hemisphere_map, SurfacePoint}, spectrum::Spectrum};
+
+use super::{Material, BrdfSample, MicrofacetDistribution, ndot, flip, energy::schlick_average};
+
+fn fresnel_schlick(r0: f32, cos: f32) -> f32 {
+    let pow5 = |x: f32| (x * x) * (x * x) * x;
+    r0 + (1.0 - r0) * pow5(1.0 - cos.clamp(0.0, 1.0))
+}
+
+fn luminance(col: &Spectrum<f32>) -> f32 {
+    col.r * 0.2126 + col.g * 0.7152 + col.b * 0.0722
+}
+
+// A dielectric coating over a diffuse base. Light refracted into the coating is
+// reflected diffusely by the base, so the diffuse lobe is scaled by the fresnel
+// transmittance on the way in and the way out.
+pub struct PlasticMaterial<T> {
+    roughness: Box<dyn SurfaceTexture<f32>>,
+    r0: f32,
+    texture: Box<dyn SurfaceTexture<Spectrum<f32>>>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> PlasticMaterial<T> {
+    pub fn new(roughness: impl SurfaceTexture<f32> + 'static, ior: f32, texture: impl SurfaceTexture<Spectrum<f32>> + 'static) -> Self {
+        let r0 = ((ior - 1.0) / (ior + 1.0)).powi(2);
+
+        Self { roughness: Box::new(roughness), r0, texture: Box::new(texture), _marker: Default::default() }
+    }
+
+    pub fn flat(roughness: f32, color: Spectrum<f32>) -> Self {
+        let texture = FactoredTexture::new(color, None);
+        Self::new(roughness, 1.5, texture)
+    }
+}
+
+impl<T> PlasticMaterial<T> where
+    T: MicrofacetDistribution + Send + Sync,
+{
+    fn specular(&self, roughness: f32, wi: &Vector3<f32>, wo: &Vector3<f32>) -> f32 {
+        let distribution = T::new_isotropic(roughness);
+
+        let idotn = ndot(wi);
+        let odotn = ndot(wo);
+        if idotn <= 0.0 || odotn <= 0.0 {
+            return 0.0;
+        }
+
+        let m = wi + wo;
+        if m.x == 0.0 && m.y == 0.0 && m.z == 0.0 {
+            return 0.0;
+        }
+        let m = m.normalize();
+
+        let fresnel = fresnel_schlick(self.r0, wi.dot(&m));
+        let density = distribution.facet_density(&m);
+        let shadowing = distribution.shadowing(wi, &m) * distribution.shadowing(wo, &m);
+        let single_scattering = fresnel * density * shadowing / (4.0 * idotn * odotn);
+
+        let table = T::albedo_table();
+        let f_avg = schlick_average(&Spectrum::constant(self.r0));
+        let multiple_scattering = table.fresnel_scale(roughness, &f_avg).g * table.multiple_scattering(roughness, wi, wo);
+
+        single_scattering + multiple_scattering
+    }
+
+    fn diffuse(&self, albedo: &Spectrum<f32>, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32> {
+        let idotn = ndot(wi);
+        let odotn = ndot(wo);
+        if idotn <= 0.0 || odotn <= 0.0 {
+            return Spectrum::black();
+        }
+
+        let f_avg = self.r0 + (1.0 - self.r0) / 21.0;
+        let transmittance = (1.0 - fresnel_schlick(self.r0, idotn)) * (1.0 - fresnel_schlick(self.r0, odotn)) / (1.0 - f_avg);
+
+        albedo * (FRAC_1_PI * transmittance)
+    }
+
+    fn specular_pdf(&self, roughness: f32, wi: &Vector3<f32>, wo: &Vector3<f32>) -> f32 {
+        let distribution = T::new_isotropic(roughness);
+
+        let m = wi + wo;
+        if m.x == 0.0 && m.y == 0.0 && m.z == 0.0 {
+            return 0.0;
+        }
+        let m = m.normalize();
+
+        let mdoto = m.dot(wo);
+        if mdoto <= 0.0 {
+            return 0.0;
+        }
+
+        distribution.facet_density(&m) * ndot(&m) / (4.0 * mdoto)
+    }
+
+    // probability of sampling the specular lobe, proportional to the energy each lobe reflects towards wo.
+    fn specular_probability(&self, albedo: &Spectrum<f32>, wo: &Vector3<f32>) -> f32 {
+        let specular = fresnel_schlick(self.r0, ndot(wo));
+        let diffuse = (1.0 - specular) * luminance(albedo);
+
+        if specular + diffuse > 0.0 {
+            specular / (specular + diffuse)
+        } else {
+            1.0
+        }
+    }
+}
+
+impl<T> Material for PlasticMaterial<T> where
+    T: MicrofacetDistribution + Send + Sync,
+{
+    fn brdf(&self, p: &SurfacePoint, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32> {
+        // shade whichever side of the surface wo is on.
+        let (wi, wo) = (&flip(wi, wo), &flip(wo, wo));
+
+        let roughness = self.roughness.evaluate(p);
+        let albedo = self.texture.evaluate(p);
+
+        Spectrum::constant(self.specular(roughness, wi, wo)) + self.diffuse(&albedo, wi, wo)
+    }
+
+    fn sample_brdf(&self, p: &SurfacePoint, wo_unflipped: &Vector3<f32>) -> BrdfSample {
+        let mut rng = thread_rng();
+        let wo = &flip(wo_unflipped, wo_unflipped);
+
+        let roughness = self.roughness.evaluate(p);
+        let p_specular = self.specular_probability(&self.texture.evaluate(p), wo);
+
+        let wi = if rng.gen::<f32>() < p_specular {
+            let distribution = T::new_isotropic(roughness);
+            let (m, _) = distribution.sample_facet(wo);
+            (2.0 * m.dot(wo)) * m - wo
+        } else {
+            let u = Point2::new(rng.gen(), rng.gen());
+            cosine_hemisphere_map(&u)
+        };
+
+        let pdf_specular = self.specular_pdf(roughness, &wi, wo);
+        let pdf_diffuse = wi.z.max(0.0) * FRAC_1_PI;
+        let pdf = p_specular * pdf_specular + (1.0 - p_specular) * pdf_diffuse;
+
+        // a facet facing away from wo reflects it below the surface, where nothing is reflected.
+        if pdf <= 0.0 || !pdf.is_finite() {
+            return BrdfSample { wi: Vector3::new(0.0, 0.0, 1.0), brdf: Spectrum::black(), pdf: 1.0 };
+        }
+
+        let wi = flip(&wi, wo_unflipped);
+        let brdf = self.brdf(p, &wi, wo_unflipped);
+
+        BrdfSample { wi, brdf, pdf }
+    }
+
+    fn is_delta(&self, _p: &SurfacePoint) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_1_PI;
+
+    use nalgebra::Vector3;
+
+    use crate::{geometry::SurfacePoint, scene::Vertex, material::{Ggx, Material, white_furnace}, spectrum::Spectrum};
+
+    use super::PlasticMaterial;
+
+    #[test]
+    fn plastic_energy_conservation_test() {
+        let material = PlasticMaterial::<Ggx>::flat(0.5, Spectrum::constant(1.0));
+
+        for mu in [0.2f32, 0.6, 1.0] {
+            let wo = Vector3::new((1.0 - mu * mu).sqrt(), 0.0, mu);
+            let albedo = white_furnace(&material, &wo, 50000);
+            assert!(albedo > 0.7 && albedo < 1.05, "mu: {}, albedo: {}", mu, albedo);
+        }
+    }
+
+    // a perfectly smooth coating is a very narrow lobe rather than a division by zero.
+    #[test]
+    fn plastic_smooth_test() {
+        let material = PlasticMaterial::<Ggx>::flat(0.0, Spectrum::constant(0.5));
+        let p = SurfacePoint::from_vertex(&Vertex::default(), &material);
+        let wo = Vector3::new(0.6, 0.0, 0.8);
+
+        let albedo = white_furnace(&material, &wo, 10000);
+        assert!(albedo > 0.4 && albedo < 1.0, "albedo: {}", albedo);
+
+        // away from the mirror direction only the diffuse base is left.
+        let diffuse = material.brdf(&p, &Vector3::new(0.0, 0.0, 1.0), &wo);
+        assert!(diffuse.g > 0.4 * FRAC_1_PI && diffuse.g < 0.52 * FRAC_1_PI, "diffuse: {:?}", diffuse);
+    }
+}
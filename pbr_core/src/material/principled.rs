@@ -0,0 +1,434 @@
+use std::f32::consts::FRAC_1_PI;
+
+use nalgebra::{Vector3, Point2};
+use rand::{thread_rng, Rng};
+
+use crate::{texture::{FactoredTexture, SurfaceTexture}, geometry::{cosine_hemisphere_map, SurfacePoint}, spectrum::Spectrum};
+
+use super::{Material, BrdfSample, MicrofacetDistribution, Ggx, ndot, flip, fresnel_dielectric, refract};
+
+fn pow5(x: f32) -> f32 {
+    (x * x) * (x * x) * x
+}
+
+fn schlick(r0: f32, cos: f32) -> f32 {
+    r0 + (1.0 - r0) * pow5(1.0 - cos.clamp(0.0, 1.0))
+}
+
+fn luminance(col: &Spectrum<f32>) -> f32 {
+    col.r * 0.2126 + col.g * 0.7152 + col.b * 0.0722
+}
+
+fn reflect(v: &Vector3<f32>, m: &Vector3<f32>) -> Vector3<f32> {
+    (2.0 * v.dot(m)) * m - v
+}
+
+// the half vector of a refraction, oriented towards the upper hemisphere.
+fn refraction_half_vector(wi: &Vector3<f32>, wo: &Vector3<f32>, eta: f32) -> Option<Vector3<f32>> {
+    let h = -(wo + eta * wi);
+    if h.x == 0.0 && h.y == 0.0 && h.z == 0.0 {
+        return None;
+    }
+    let h = h.normalize();
+    Some(if ndot(&h) < 0.0 { -h } else { h })
+}
+
+const CLEARCOAT_R0: f32 = 0.04;
+
+enum Lobe {
+    Diffuse,
+    Specular,
+    Clearcoat,
+    Transmission,
+}
+
+// the textured inputs evaluated at a single point of the surface.
+struct Inputs {
+    base: Spectrum<f32>,
+    metallic: f32,
+    roughness: f32,
+}
+
+// Source: https://blog.selfshadow.com/publications/s2015-shading-course/burley/s2015_pbs_disney_bsdf_notes.pdf
+pub struct PrincipledMaterial {
+    pub base_color: Box<dyn SurfaceTexture<Spectrum<f32>>>,
+    pub metallic: Box<dyn SurfaceTexture<f32>>,
+    pub roughness: Box<dyn SurfaceTexture<f32>>,
+    pub anisotropy: f32,
+    pub specular: f32,
+    pub specular_color: Spectrum<f32>,
+    pub specular_tint: f32,
+    pub sheen: f32,
+    pub sheen_color: Spectrum<f32>,
+    pub sheen_roughness: f32,
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    pub transmission: f32,
+    pub ior: f32,
+    pub subsurface: f32,
+}
+
+impl PrincipledMaterial {
+    pub fn new(base_color: impl SurfaceTexture<Spectrum<f32>> + 'static) -> Self {
+        Self {
+            base_color: Box::new(base_color),
+            metallic: Box::new(0.0),
+            roughness: Box::new(0.5),
+            anisotropy: 0.0,
+            specular: 1.0,
+            specular_color: Spectrum::constant(1.0),
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_color: Spectrum::constant(1.0),
+            sheen_roughness: 1.0,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            transmission: 0.0,
+            ior: 1.5,
+            subsurface: 0.0,
+        }
+    }
+
+    pub fn flat(color: Spectrum<f32>) -> Self {
+        Self::new(FactoredTexture::new(color, None))
+    }
+
+    fn inputs(&self, p: &SurfacePoint) -> Inputs {
+        Inputs {
+            base: self.base_color.evaluate(p),
+            metallic: self.metallic.evaluate(p),
+            roughness: self.roughness.evaluate(p),
+        }
+    }
+
+    fn distribution(&self, inputs: &Inputs) -> Ggx {
+        Ggx::new_anisotropic(inputs.roughness, self.anisotropy)
+    }
+
+    fn clearcoat_distribution(&self) -> Ggx {
+        Ggx::new_anisotropic(self.clearcoat_roughness, 0.0)
+    }
+
+    // ratio of the refractive index on the far side of the surface to the one on the side of wo.
+    fn eta(&self, outside: bool) -> f32 {
+        if outside { self.ior } else { 1.0 / self.ior }
+    }
+
+    // scale applied to the fresnel reflectance of the dielectric specular lobe.
+    fn dielectric_tint(&self, base: &Spectrum<f32>) -> Spectrum<f32> {
+        let lum = luminance(base);
+        let tint = if lum > 0.0 { base / lum } else { Spectrum::constant(1.0) };
+        let tint = Spectrum::lerp(&Spectrum::constant(1.0), &tint, self.specular_tint);
+
+        tint * self.specular_color * self.specular
+    }
+
+    // burley's sheen falls off with the fifth power, smoother sheen is pushed further towards grazing angles.
+    fn sheen_falloff(&self) -> f32 {
+        5.0 / self.sheen_roughness.clamp(0.1, 1.0)
+    }
+
+    fn diffuse_weight(&self, inputs: &Inputs) -> f32 {
+        (1.0 - inputs.metallic) * (1.0 - self.transmission)
+    }
+
+    // wi and wo are expected to be flipped, so that wo is in the upper hemisphere.
+    fn reflection(&self, inputs: &Inputs, wi: &Vector3<f32>, wo: &Vector3<f32>, outside: bool) -> Spectrum<f32> {
+        let base = &inputs.base;
+        let idotn = ndot(wi);
+        let odotn = ndot(wo);
+
+        let h = wi + wo;
+        if h.x == 0.0 && h.y == 0.0 && h.z == 0.0 {
+            return Spectrum::black();
+        }
+        let h = h.normalize();
+        let hdoto = h.dot(wo);
+        let eta = self.eta(outside);
+
+        let distribution = self.distribution(inputs);
+        let density = distribution.facet_density(&h);
+        let shadowing = distribution.shadowing(wi, &h) * distribution.shadowing(wo, &h);
+
+        let dielectric_fresnel = (self.dielectric_tint(base) * fresnel_dielectric(hdoto, eta)).apply_into(|f| f.min(1.0));
+        let metal_fresnel = base.apply_into(|r0| schlick(r0, hdoto));
+        let fresnel = dielectric_fresnel * (1.0 - inputs.metallic) + metal_fresnel * inputs.metallic;
+
+        let specular = fresnel * (density * shadowing / (4.0 * idotn * odotn));
+
+        if !outside {
+            return specular;
+        }
+
+        let cos_d = wi.dot(&h);
+        let fl = pow5(1.0 - idotn);
+        let fv = pow5(1.0 - odotn);
+
+        // burley diffuse with retro-reflection, blended with the hanrahan-krueger like subsurface approximation.
+        let fd90 = 0.5 + 2.0 * inputs.roughness * cos_d * cos_d;
+        let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+
+        let fss90 = inputs.roughness * cos_d * cos_d;
+        let fss = (1.0 + (fss90 - 1.0) * fl) * (1.0 + (fss90 - 1.0) * fv);
+        let ss = 1.25 * (fss * (1.0 / (idotn + odotn) - 0.5) + 0.5);
+
+        let diffuse = base * (FRAC_1_PI * (fd + (ss - fd) * self.subsurface));
+        let sheen = self.sheen_color * (self.sheen * (1.0 - cos_d.clamp(0.0, 1.0)).powf(self.sheen_falloff()));
+
+        let clearcoat_distribution = self.clearcoat_distribution();
+        let clearcoat_density = clearcoat_distribution.facet_density(&h);
+        let clearcoat_shadowing = clearcoat_distribution.shadowing(wi, &h) * clearcoat_distribution.shadowing(wo, &h);
+        let clearcoat = self.clearcoat * schlick(CLEARCOAT_R0, hdoto) * clearcoat_density * clearcoat_shadowing / (4.0 * idotn * odotn);
+
+        (specular + (diffuse + sheen) * self.diffuse_weight(inputs)) * self.clearcoat_attenuation(idotn, odotn) + Spectrum::constant(clearcoat)
+    }
+
+    // wi and wo are expected to be flipped, so that wo is in the upper hemisphere.
+    fn transmission(&self, inputs: &Inputs, wi: &Vector3<f32>, wo: &Vector3<f32>, outside: bool) -> Spectrum<f32> {
+        let base = &inputs.base;
+        let weight = (1.0 - inputs.metallic) * self.transmission;
+        if weight == 0.0 {
+            return Spectrum::black();
+        }
+
+        let eta = self.eta(outside);
+        let h = match refraction_half_vector(wi, wo, eta) {
+            Some(h) => h,
+            None => return Spectrum::black(),
+        };
+
+        let hdoto = h.dot(wo);
+        let hdoti = h.dot(wi);
+        if hdoto <= 0.0 || hdoti >= 0.0 {
+            return Spectrum::black();
+        }
+
+        let distribution = self.distribution(inputs);
+        let density = distribution.facet_density(&h);
+        let shadowing = distribution.shadowing(wi, &h) * distribution.shadowing(wo, &h);
+        let fresnel = fresnel_dielectric(hdoto, eta);
+
+        let denom = hdoto + eta * hdoti;
+        let value = (1.0 - fresnel) * density * shadowing * (hdoti * hdoto).abs() / ((ndot(wi) * ndot(wo)).abs() * denom * denom);
+
+        let attenuation = if outside { self.clearcoat_attenuation(1.0, ndot(wo)) } else { 1.0 };
+
+        base * (weight * value * attenuation)
+    }
+
+    fn clearcoat_attenuation(&self, idotn: f32, odotn: f32) -> f32 {
+        (1.0 - self.clearcoat * schlick(CLEARCOAT_R0, idotn)) * (1.0 - self.clearcoat * schlick(CLEARCOAT_R0, odotn))
+    }
+
+    // probabilities of sampling each lobe, proportional to an estimate of the energy they reflect towards wo.
+    fn lobe_probabilities(&self, inputs: &Inputs, wo: &Vector3<f32>, outside: bool) -> [(Lobe, f32); 4] {
+        let base = &inputs.base;
+        let odotn = ndot(wo);
+        let eta = self.eta(outside);
+        let fresnel = fresnel_dielectric(odotn, eta);
+
+        let dielectric = (1.0 - inputs.metallic) * (luminance(&self.dielectric_tint(base)) * fresnel).min(1.0);
+        let metal = inputs.metallic * luminance(&base.apply_into(|r0| schlick(r0, odotn)));
+        let specular = (dielectric + metal).max(1e-3);
+
+        let transmission = (1.0 - inputs.metallic) * self.transmission * (1.0 - fresnel);
+
+        let (diffuse, clearcoat) = if outside {
+            let diffuse = self.diffuse_weight(inputs) * (luminance(base) + self.sheen * luminance(&self.sheen_color));
+            let clearcoat = self.clearcoat * schlick(CLEARCOAT_R0, odotn);
+            (diffuse, clearcoat)
+        } else {
+            (0.0, 0.0)
+        };
+
+        let total = diffuse + specular + clearcoat + transmission;
+
+        [
+            (Lobe::Diffuse, diffuse / total),
+            (Lobe::Specular, specular / total),
+            (Lobe::Clearcoat, clearcoat / total),
+            (Lobe::Transmission, transmission / total),
+        ]
+    }
+
+    // wi and wo are expected to be flipped, so that wo is in the upper hemisphere.
+    fn pdf(&self, inputs: &Inputs, wi: &Vector3<f32>, wo: &Vector3<f32>, outside: bool) -> f32 {
+        let idotn = ndot(wi);
+
+        self.lobe_probabilities(inputs, wo, outside)
+            .iter()
+            .filter(|(_, p)| *p > 0.0)
+            .map(|(lobe, p)| p * match lobe {
+                Lobe::Diffuse => idotn.max(0.0) * FRAC_1_PI,
+                Lobe::Specular => reflection_pdf(&self.distribution(inputs), wi, wo),
+                Lobe::Clearcoat => reflection_pdf(&self.clearcoat_distribution(), wi, wo),
+                Lobe::Transmission => {
+                    let eta = self.eta(outside);
+                    transmission_pdf(&self.distribution(inputs), wi, wo, eta)
+                },
+            })
+            .sum()
+    }
+}
+
+fn reflection_pdf(distribution: &Ggx, wi: &Vector3<f32>, wo: &Vector3<f32>) -> f32 {
+    let h = wi + wo;
+    if h.x == 0.0 && h.y == 0.0 && h.z == 0.0 {
+        return 0.0;
+    }
+    let h = h.normalize();
+
+    let hdoto = h.dot(wo);
+    if hdoto <= 0.0 || ndot(&h) <= 0.0 {
+        return 0.0;
+    }
+
+    distribution.facet_density(&h) * ndot(&h) / (4.0 * hdoto)
+}
+
+fn transmission_pdf(distribution: &Ggx, wi: &Vector3<f32>, wo: &Vector3<f32>, eta: f32) -> f32 {
+    let h = match refraction_half_vector(wi, wo, eta) {
+        Some(h) => h,
+        None => return 0.0,
+    };
+
+    let hdoto = h.dot(wo);
+    let hdoti = h.dot(wi);
+    if hdoto <= 0.0 || hdoti >= 0.0 {
+        return 0.0;
+    }
+
+    let denom = hdoto + eta * hdoti;
+    distribution.facet_density(&h) * ndot(&h) * eta * eta * hdoti.abs() / (denom * denom)
+}
+
+impl Material for PrincipledMaterial {
+    fn brdf(&self, p: &SurfacePoint, wi: &Vector3<f32>, wo: &Vector3<f32>) -> Spectrum<f32> {
+        let outside = ndot(wo) >= 0.0;
+        let inputs = self.inputs(p);
+
+        let wi = flip(wi, wo);
+        let wo = flip(wo, wo);
+
+        if ndot(&wo) == 0.0 || ndot(&wi) == 0.0 {
+            Spectrum::black()
+        } else if ndot(&wi) > 0.0 {
+            self.reflection(&inputs, &wi, &wo, outside)
+        } else {
+            self.transmission(&inputs, &wi, &wo, outside)
+        }
+    }
+
+    fn sample_brdf(&self, p: &SurfacePoint, wo: &Vector3<f32>) -> BrdfSample {
+        let mut rng = thread_rng();
+
+        let outside = ndot(wo) >= 0.0;
+        let inputs = self.inputs(p);
+        let wo_flipped = flip(wo, wo);
+
+        let mut u = rng.gen::<f32>();
+        let mut lobe = Lobe::Specular;
+        for (l, p) in self.lobe_probabilities(&inputs, &wo_flipped, outside) {
+            if u < p {
+                lobe = l;
+                break;
+            }
+            u -= p;
+        }
+
+        // facets facing away from wo and total internal reflection are discarded, so the
+        // directions that are generated are exactly the ones accounted for by the pdf.
+        let sample_facet = |distribution: Ggx| Some(distribution.sample_facet(&wo_flipped).0)
+            .filter(|h| h.dot(&wo_flipped) > 0.0);
+
+        let wi_flipped = match lobe {
+            Lobe::Diffuse => Some(cosine_hemisphere_map(&Point2::new(rng.gen(), rng.gen()))),
+            Lobe::Specular => sample_facet(self.distribution(&inputs)).map(|h| reflect(&wo_flipped, &h)),
+            Lobe::Clearcoat => sample_facet(self.clearcoat_distribution()).map(|h| reflect(&wo_flipped, &h)),
+            Lobe::Transmission => sample_facet(self.distribution(&inputs)).and_then(|h| refract(&wo_flipped, &h, self.eta(outside))),
+        };
+
+        let sample = wi_flipped.map(|wi_flipped| (flip(&wi_flipped, wo), self.pdf(&inputs, &wi_flipped, &wo_flipped, outside)));
+
+        match sample {
+            Some((wi, pdf)) if pdf > 0.0 && pdf.is_finite() => {
+                let brdf = self.brdf(p, &wi, wo);
+                BrdfSample { wi, brdf, pdf }
+            },
+            _ => BrdfSample { wi: Vector3::new(0.0, 0.0, 1.0), brdf: Spectrum::black(), pdf: 1.0 },
+        }
+    }
+
+    fn is_delta(&self, _p: &SurfacePoint) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use crate::{geometry::SurfacePoint, scene::Vertex, material::{Material, albedo}, spectrum::Spectrum};
+
+    use super::PrincipledMaterial;
+
+    #[test]
+    fn principled_energy_conservation_test() {
+        let mut materials = Vec::new();
+
+        materials.push(PrincipledMaterial::flat(Spectrum::constant(1.0)));
+
+        let mut metal = PrincipledMaterial::flat(Spectrum::constant(1.0));
+        metal.metallic = Box::new(1.0);
+        metal.roughness = Box::new(0.3);
+        metal.anisotropy = 0.8;
+        materials.push(metal);
+
+        let mut coated = PrincipledMaterial::flat(Spectrum::constant(0.8));
+        coated.clearcoat = 1.0;
+        coated.sheen = 1.0;
+        coated.subsurface = 0.5;
+        materials.push(coated);
+
+        // the retro-reflection of the burley diffuse lobe adds a little energy at grazing angles.
+        for material in &materials {
+            for mu in [0.3f32, 0.7, 1.0] {
+                let wo = Vector3::new((1.0 - mu * mu).sqrt(), 0.0, mu);
+                let (reflected, transmitted) = albedo(material, &wo, 50000);
+                assert!(reflected + transmitted < 1.15, "mu: {}, albedo: {}", mu, reflected + transmitted);
+                assert!(reflected + transmitted > 0.5, "mu: {}, albedo: {}", mu, reflected + transmitted);
+            }
+        }
+    }
+
+    #[test]
+    fn principled_sheen_roughness_test() {
+        let mut rough = PrincipledMaterial::flat(Spectrum::black());
+        rough.sheen = 1.0;
+        let mut smooth = PrincipledMaterial::flat(Spectrum::black());
+        smooth.sheen = 1.0;
+        smooth.sheen_roughness = 0.2;
+
+        let p = SurfacePoint::from_vertex(&Vertex::default(), &rough);
+        let wi = Vector3::new(0.96, 0.0, 0.28);
+        let wo = Vector3::new(-0.96, 0.0, 0.28);
+
+        // with a black base colour only the specular lobe and the sheen remain, the specular is the same for both.
+        let difference = rough.brdf(&p, &wi, &wo) - smooth.brdf(&p, &wi, &wo);
+        assert!(difference.g > 0.1, "difference: {:?}", difference);
+    }
+
+    #[test]
+    fn principled_transmission_test() {
+        let mut glass = PrincipledMaterial::flat(Spectrum::constant(1.0));
+        glass.transmission = 1.0;
+        glass.roughness = Box::new(0.2);
+
+        // radiance is compressed by eta^2 when it enters the denser medium and expanded when it leaves it.
+        for (wo, scale) in [(Vector3::new(0.0, 0.0, 1.0), glass.ior * glass.ior), (Vector3::new(0.0, 0.0, -1.0), 1.0 / (glass.ior * glass.ior))] {
+            let (reflected, transmitted) = albedo(&glass, &wo, 50000);
+            assert!(reflected < 0.15, "reflected: {}", reflected);
+            assert!(transmitted * scale > 0.8 && transmitted * scale < 1.05, "transmitted: {}", transmitted * scale);
+        }
+    }
+}
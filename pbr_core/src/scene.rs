@@ -1,31 +1,54 @@
 pub mod loader;
+pub mod description;
+pub mod animation;
 
-use std::{ops::Mul, path::Path, io::Result};
+use std::{ops::Mul, path::Path, sync::Arc};
 
 use nalgebra::{Point3, Vector3, Affine3, Point2};
 use rand::Rng;
 
-use crate::{camera::Camera, light::{LightSource, Emitter}, accelerator::Accelerator, material::Material, geometry::{SurfacePoint, Ray}};
-use loader::Loader;
+use crate::{error::Result, spectrum::Spectrum, camera::Camera, light::{LightSource, Emitter}, accelerator::Accelerator, material::Material, geometry::{SurfacePoint, Ray, RayDifferential}};
+use loader::{Loader, LoadOptions};
+use animation::NodeAnimation;
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy)]
 pub struct Vertex {
     pub position: Point3<f32>,
     pub normal: Vector3<f32>,
     pub tangent: Vector3<f32>,
+    // -1 where the texture is mirrored and the bitangent points along -(n x t).
+    pub bitangent_sign: f32,
     pub tex_coords: Point2<f32>,
+    pub color: Spectrum<f32>,
 }
 
+// vertices without a colour are white, so multiplying with it changes nothing.
+impl Default for Vertex {
+    fn default() -> Self {
+        Self {
+            position: Point3::origin(),
+            normal: Vector3::zeros(),
+            tangent: Vector3::zeros(),
+            bitangent_sign: 1.0,
+            tex_coords: Point2::origin(),
+            color: Spectrum::constant(1.0),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
-    pub material: Box<dyn Material>,
+    pub material: Arc<dyn Material>,
 }
 
 pub struct Node {
     children: Vec<Node>,
     transform: Affine3<f32>,
     meshes: Vec<Mesh>,
+    // replaces the transform and blends the meshes when the node is posed.
+    animation: Option<NodeAnimation>,
 }
 
 #[derive(Default)]
@@ -33,22 +56,27 @@ pub struct SceneBuilder {
     root: Node,
     camera: Camera,
     light_sources: Vec<LightSource>,
+    // the animated nodes are built in their rest pose without a time.
+    time: Option<f32>,
 }
 
 pub struct Scene<A> {
     accelerator: A,
     camera: Camera,
     light_sources: Vec<LightSource>,
-    materials: Vec<Box<dyn Material>>,
+    materials: Vec<Arc<dyn Material>>,
 }
 
 impl SceneBuilder {
     pub fn new() -> SceneBuilder {
-        SceneBuilder { root: Node::default(), camera: Camera::default(), light_sources: Vec::default() }
+        SceneBuilder { root: Node::default(), camera: Camera::default(), light_sources: Vec::default(), time: None }
     }
 
     pub fn build<A: Accelerator>(self) -> Scene<A> {
-        let meshes = self.root.flatten();
+        let meshes = match self.time {
+            Some(time) => self.root.flatten_at(time),
+            None => self.root.flatten(),
+        };
         let (geometry, materials) = meshes.into_iter()
             .map(|mesh| ((mesh.vertices, mesh.indices), mesh.material))
             .unzip();
@@ -65,8 +93,12 @@ impl SceneBuilder {
  
     }
 
-    pub fn add_file<L: Loader, P: AsRef<Path>>(mut self, path: P) -> Result<SceneBuilder> {
-        L::load_from_file(path, &mut self)?;
+    pub fn add_file<L: Loader, P: AsRef<Path>>(self, path: P) -> Result<SceneBuilder> {
+        self.add_file_with::<L, P>(path, &LoadOptions::default())
+    }
+
+    pub fn add_file_with<L: Loader, P: AsRef<Path>>(mut self, path: P, options: &LoadOptions) -> Result<SceneBuilder> {
+        L::load_from_file(path, &mut self, options)?;
         Ok(self)
     }
 
@@ -79,6 +111,26 @@ impl SceneBuilder {
         self.camera = camera;
         self
     }
+
+    // sets the time in seconds the animated nodes are posed at when the scene is built.
+    pub fn at_time(mut self, time: f32) -> SceneBuilder {
+        self.time = Some(time);
+        self
+    }
+
+    pub fn build_at<A: Accelerator>(self, time: f32) -> Scene<A> {
+        self.at_time(time).build()
+    }
+
+    // the meshes posed at the time in seconds, leaves the builder untouched so it can be posed again.
+    pub fn meshes_at(&self, time: f32) -> Vec<Mesh> {
+        self.root.flatten_at(time)
+    }
+
+    // the time of the last keyframe, zero for a scene without animations.
+    pub fn duration(&self) -> f32 {
+        self.root.duration()
+    }
 }
 
 impl Node {
@@ -97,6 +149,34 @@ impl Node {
         }
     }
 
+    // like flatten, but copies the meshes with the animations evaluated at the time in seconds.
+    pub fn flatten_at(&self, time: f32) -> Vec<Mesh> {
+        let mut meshes = Vec::new();
+        self.flatten_at_recursive(time, &Affine3::identity(), &mut meshes);
+        meshes
+    }
+
+    fn flatten_at_recursive(&self, time: f32, parent_transform: &Affine3<f32>, meshes: &mut Vec<Mesh>) {
+        let local = self.animation.as_ref().map_or(self.transform, |animation| animation.transform_at(time));
+        let transform = parent_transform * local;
+
+        match self.animation.as_ref().and_then(|animation| animation.morphs_at(time)) {
+            Some(morphs) => meshes.extend(self.meshes.iter().zip(morphs).map(|(mesh, (vertices, indices))| {
+                transform * Mesh { vertices, indices, material: mesh.material.clone() }
+            })),
+            None => meshes.extend(self.meshes.iter().map(|mesh| transform * mesh.clone())),
+        }
+
+        for child in &self.children {
+            child.flatten_at_recursive(time, &transform, meshes)
+        }
+    }
+
+    pub fn duration(&self) -> f32 {
+        let own = self.animation.as_ref().map_or(0.0, NodeAnimation::duration);
+        self.children.iter().map(Node::duration).fold(own, f32::max)
+    }
+
 }
 
 impl Default for Node {
@@ -105,6 +185,7 @@ impl Default for Node {
             children: Vec::new(),
             transform: Affine3::identity(),
             meshes: Vec::new(),
+            animation: None,
         }
     }
 }
@@ -122,6 +203,16 @@ impl<A> Scene<A> where
             SurfacePoint::new(&info.barycentrics, &info.vertices, material)
         })
     } 
+
+    // like intersect, but also estimates the footprint of the ray on the surface.
+    pub fn intersect_differential<'s>(&'s self, ray: &RayDifferential) -> Option<SurfacePoint<'s>> {
+        self.accelerator.intersect(&ray.ray).map(|info|  {
+            let material = self.materials[info.mesh as usize].as_ref();
+            let mut p = SurfacePoint::new(&info.barycentrics, &info.vertices, material);
+            p.compute_differentials(ray, &info.vertices);
+            p
+        })
+    }
     
     pub fn pick_light<'a>(&'a self) -> (&'a LightSource, f32) {
         let mut rng = rand::thread_rng();
@@ -129,6 +220,10 @@ impl<A> Scene<A> where
         (&self.light_sources[idx], 1.0 / self.light_sources.len() as f32)
     }
 
+    pub fn has_lights(&self) -> bool {
+        !self.light_sources.is_empty()
+    }
+
     pub fn background_lights<'a>(&'a self) -> impl Iterator<Item = &'a LightSource> {
         self.light_sources.iter().filter(|&l| l.is_background())
     } 
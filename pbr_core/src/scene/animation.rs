@@ -0,0 +1,327 @@
+use std::cmp::Ordering;
+
+use nalgebra::{Vector3, Quaternion, UnitQuaternion, Translation3, Scale3, Affine3, convert};
+
+use crate::error::{Result, Error};
+use super::{Vertex, loader::{NormalGeneration, generate}};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // holds each value until the next keyframe.
+    Step,
+    // a hermite spline through the values, with an in and an out tangent at each keyframe.
+    CubicSpline,
+}
+
+// the values a property can be interpolated between.
+pub trait Interpolate: Clone {
+    fn lerp(&self, other: &Self, s: f32) -> Self;
+    // the points and tangents of a spline, each times its weight.
+    fn weighted_sum(terms: [(&Self, f32); 4]) -> Self;
+}
+
+impl Interpolate for Vector3<f32> {
+    fn lerp(&self, other: &Self, s: f32) -> Self {
+        self + (other - self) * s
+    }
+
+    fn weighted_sum(terms: [(&Self, f32); 4]) -> Self {
+        terms.iter().map(|&(v, w)| v * w).sum()
+    }
+}
+
+// rotations are kept as plain quaternions, as the tangents of a spline are not unit length.
+impl Interpolate for Quaternion<f32> {
+    // takes the shorter way around, falling back to a normalized lerp for opposite rotations.
+    fn lerp(&self, other: &Self, s: f32) -> Self {
+        let (from, to) = (UnitQuaternion::from_quaternion(*self), UnitQuaternion::from_quaternion(*other));
+        match from.try_slerp(&to, s, 1e-6) {
+            Some(rotation) => rotation.into_inner(),
+            None => Quaternion::from(self.coords.lerp(&other.coords, s)).normalize(),
+        }
+    }
+
+    fn weighted_sum(terms: [(&Self, f32); 4]) -> Self {
+        Quaternion::from(terms.iter().map(|&(q, w)| q.coords * w).sum::<nalgebra::Vector4<f32>>()).normalize()
+    }
+}
+
+// the weights of the morph targets.
+impl Interpolate for Vec<f32> {
+    fn lerp(&self, other: &Self, s: f32) -> Self {
+        self.iter().zip(other).map(|(a, b)| a + (b - a) * s).collect()
+    }
+
+    fn weighted_sum(terms: [(&Self, f32); 4]) -> Self {
+        (0..terms[0].0.len()).map(|i| terms.iter().map(|&(v, w)| v[i] * w).sum()).collect()
+    }
+}
+
+// Source: https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#appendix-c-interpolation
+fn hermite_weights(s: f32) -> [f32; 4] {
+    let (s2, s3) = (s * s, s * s * s);
+    [2.0 * s3 - 3.0 * s2 + 1.0, s3 - 2.0 * s2 + s, -2.0 * s3 + 3.0 * s2, s3 - s2]
+}
+
+// the values of one property over time, in seconds. Cubic splines store an in tangent, the value
+// and an out tangent for each keyframe. Before the first and after the last keyframe the value
+// is held.
+#[derive(Clone, Debug)]
+pub struct Keyframes<T> {
+    times: Vec<f32>,
+    values: Vec<T>,
+    interpolation: Interpolation,
+}
+
+impl<T: Interpolate> Keyframes<T> {
+    pub fn new(times: Vec<f32>, values: Vec<T>, interpolation: Interpolation) -> Result<Self> {
+        if times.is_empty() {
+            return Err(Error::invalid("keyframes, there are none"));
+        }
+        if let Some(pair) = times.windows(2).find(|pair| pair[1].partial_cmp(&pair[0]) != Some(Ordering::Greater)) {
+            return Err(Error::invalid(format!("keyframe time {} after {}, times have to increase", pair[1], pair[0])));
+        }
+
+        let per_keyframe = if interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+        if values.len() != times.len() * per_keyframe {
+            return Err(Error::invalid(format!("{} keyframe values for {} times", values.len(), times.len())));
+        }
+
+        Ok(Keyframes { times, values, interpolation })
+    }
+
+    // the time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.times[self.times.len() - 1]
+    }
+
+    pub fn sample(&self, time: f32) -> T {
+        let value = |i: usize| match self.interpolation {
+            Interpolation::CubicSpline => &self.values[3 * i + 1],
+            _ => &self.values[i],
+        };
+
+        // the first keyframe after the time.
+        let next = self.times.partition_point(|&t| t <= time);
+        if next == 0 {
+            return value(0).clone();
+        }
+        if next == self.times.len() {
+            return value(next - 1).clone();
+        }
+
+        let i = next - 1;
+        let dt = self.times[next] - self.times[i];
+        let s = (time - self.times[i]) / dt;
+
+        match self.interpolation {
+            Interpolation::Step => value(i).clone(),
+            Interpolation::Linear => value(i).lerp(value(next), s),
+            Interpolation::CubicSpline => {
+                // the out tangent of this keyframe and the in tangent of the next.
+                let [a, b, c, d] = hermite_weights(s);
+                T::weighted_sum([(value(i), a), (&self.values[3 * i + 2], b * dt), (value(next), c), (&self.values[3 * next], d * dt)])
+            },
+        }
+    }
+}
+
+// the displacements of a morph target for each vertex, empty when it leaves an attribute as it is.
+#[derive(Clone, Debug, Default)]
+pub struct MorphTarget {
+    pub positions: Vec<Vector3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+    pub tangents: Vec<Vector3<f32>>,
+}
+
+// a mesh as read from a file, with the targets it is blended from. The attributes the file
+// leaves out are generated after blending, as they depend on the displaced positions.
+#[derive(Clone, Default)]
+pub struct Morph {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub targets: Vec<MorphTarget>,
+    pub normals: Option<NormalGeneration>,
+    pub tangents: bool,
+}
+
+impl Morph {
+    // weights past the targets are ignored, missing weights leave their target out.
+    pub fn blend(&self, weights: &[f32]) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = self.vertices.clone();
+        let mut indices = self.indices.clone();
+
+        for (target, &weight) in self.targets.iter().zip(weights).filter(|(_, &w)| w != 0.0) {
+            vertices.iter_mut().zip(&target.positions).for_each(|(v, d)| v.position += d * weight);
+            vertices.iter_mut().zip(&target.normals).for_each(|(v, d)| v.normal += d * weight);
+            vertices.iter_mut().zip(&target.tangents).for_each(|(v, d)| v.tangent += d * weight);
+        }
+
+        for v in vertices.iter_mut() {
+            v.normal = v.normal.try_normalize(0.0).unwrap_or(v.normal);
+            v.tangent = v.tangent.try_normalize(0.0).unwrap_or(v.tangent);
+        }
+
+        match self.normals {
+            Some(NormalGeneration::Flat) => generate::flat_normals(&mut vertices, &mut indices),
+            Some(NormalGeneration::Smooth) => generate::smooth_normals(&mut vertices, &indices),
+            None => (),
+        }
+
+        if self.tangents {
+            generate::mikktspace_tangents(&mut vertices, &mut indices);
+        }
+
+        (vertices, indices)
+    }
+}
+
+// the keyframes of a node, its rest pose fills in the properties without any.
+#[derive(Clone)]
+pub struct NodeAnimation {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    pub translations: Option<Keyframes<Vector3<f32>>>,
+    pub rotations: Option<Keyframes<Quaternion<f32>>>,
+    pub scales: Option<Keyframes<Vector3<f32>>>,
+    pub weights: Option<Keyframes<Vec<f32>>>,
+    // one for each mesh of the node, blended by the weights.
+    pub morphs: Vec<Morph>,
+}
+
+impl NodeAnimation {
+    pub fn new(translation: Vector3<f32>, rotation: Quaternion<f32>, scale: Vector3<f32>) -> Self {
+        NodeAnimation { translation, rotation, scale, translations: None, rotations: None, scales: None, weights: None, morphs: Vec::new() }
+    }
+
+    pub fn with_translations(mut self, translations: Keyframes<Vector3<f32>>) -> Self {
+        self.translations = Some(translations);
+        self
+    }
+
+    pub fn with_rotations(mut self, rotations: Keyframes<Quaternion<f32>>) -> Self {
+        self.rotations = Some(rotations);
+        self
+    }
+
+    pub fn with_scales(mut self, scales: Keyframes<Vector3<f32>>) -> Self {
+        self.scales = Some(scales);
+        self
+    }
+
+    pub fn with_weights(mut self, weights: Keyframes<Vec<f32>>, morphs: Vec<Morph>) -> Self {
+        self.weights = Some(weights);
+        self.morphs = morphs;
+        self
+    }
+
+    // the time of the last keyframe of any property.
+    pub fn duration(&self) -> f32 {
+        [
+            self.translations.as_ref().map(Keyframes::duration),
+            self.rotations.as_ref().map(Keyframes::duration),
+            self.scales.as_ref().map(Keyframes::duration),
+            self.weights.as_ref().map(Keyframes::duration),
+        ].into_iter().flatten().fold(0.0, f32::max)
+    }
+
+    pub fn transform_at(&self, time: f32) -> Affine3<f32> {
+        let translation = self.translations.as_ref().map_or(self.translation, |keyframes| keyframes.sample(time));
+        let rotation = self.rotations.as_ref().map_or(self.rotation, |keyframes| keyframes.sample(time));
+        let scale = self.scales.as_ref().map_or(self.scale, |keyframes| keyframes.sample(time));
+
+        let scale: Affine3<f32> = convert(Scale3::from(scale));
+        Translation3::from(translation) * UnitQuaternion::from_quaternion(rotation) * scale
+    }
+
+    // the meshes of the node blended by the weights at the time, none without weight keyframes.
+    pub fn morphs_at(&self, time: f32) -> Option<Vec<(Vec<Vertex>, Vec<u32>)>> {
+        let weights = self.weights.as_ref()?.sample(time);
+        Some(self.morphs.iter().map(|morph| morph.blend(&weights)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point3, Vector3, Quaternion, UnitQuaternion};
+
+    use crate::scene::{Vertex, loader::NormalGeneration};
+
+    use super::{Interpolation, Keyframes, Morph, MorphTarget, NodeAnimation};
+
+    #[test]
+    fn keyframe_test() {
+        let values = vec![Vector3::new(0.0, 0.0, 0.0), Vector3::new(2.0, 0.0, 0.0), Vector3::new(2.0, 4.0, 0.0)];
+        let linear = Keyframes::new(vec![1.0, 2.0, 4.0], values.clone(), Interpolation::Linear).unwrap();
+        assert_eq!(linear.duration(), 4.0);
+        assert_eq!(linear.sample(0.0), values[0]);
+        assert_eq!(linear.sample(1.5), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(linear.sample(3.0), Vector3::new(2.0, 2.0, 0.0));
+        assert_eq!(linear.sample(5.0), values[2]);
+
+        let step = Keyframes::new(vec![1.0, 2.0, 4.0], values.clone(), Interpolation::Step).unwrap();
+        assert_eq!(step.sample(1.9), values[0]);
+        assert_eq!(step.sample(2.0), values[1]);
+
+        // with flat tangents the spline eases in and out, passing the middle halfway.
+        let zero = Vector3::zeros();
+        let spline = vec![zero, values[0], zero, zero, values[1], zero];
+        let cubic = Keyframes::new(vec![0.0, 2.0], spline, Interpolation::CubicSpline).unwrap();
+        assert_eq!(cubic.sample(1.0), Vector3::new(1.0, 0.0, 0.0));
+        assert!(cubic.sample(0.5).x < 0.5 && cubic.sample(1.5).x > 1.5);
+
+        // a slope of one over the two seconds makes the spline a straight line.
+        let slope = Vector3::new(1.0, 0.0, 0.0);
+        let spline = vec![zero, values[0], slope, slope, values[1], zero];
+        let cubic = Keyframes::new(vec![0.0, 2.0], spline, Interpolation::CubicSpline).unwrap();
+        assert!((cubic.sample(0.5) - Vector3::new(0.5, 0.0, 0.0)).norm() < 1e-6);
+
+        assert!(Keyframes::new(vec![], Vec::<Vector3<f32>>::new(), Interpolation::Linear).is_err());
+        assert!(Keyframes::new(vec![1.0, 1.0, 2.0], values.clone(), Interpolation::Linear).is_err());
+        assert!(Keyframes::new(vec![1.0, 2.0, 3.0], values, Interpolation::CubicSpline).is_err());
+    }
+
+    #[test]
+    fn rotation_test() {
+        let quarter = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), std::f32::consts::FRAC_PI_2);
+        // the same rotation with its sign flipped, which has to take the short way as well.
+        let values = vec![Quaternion::identity(), -quarter.into_inner()];
+        let keyframes = Keyframes::new(vec![0.0, 1.0], values, Interpolation::Linear).unwrap();
+
+        let half = UnitQuaternion::from_quaternion(keyframes.sample(0.5));
+        assert!((half.angle() - std::f32::consts::FRAC_PI_4).abs() < 1e-5);
+
+        let animation = NodeAnimation::new(Vector3::new(0.0, 1.0, 0.0), Quaternion::identity(), Vector3::repeat(2.0))
+            .with_rotations(keyframes);
+        assert_eq!(animation.duration(), 1.0);
+        let p = animation.transform_at(1.0) * Point3::new(1.0, 0.0, 0.0);
+        assert!((p - Point3::new(0.0, 1.0, -2.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn morph_test() {
+        let vertex = |x: f32, y: f32| Vertex { position: Point3::new(x, y, 0.0), normal: Vector3::z(), ..Default::default() };
+        let morph = Morph {
+            vertices: vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0)],
+            indices: vec![0, 1, 2],
+            targets: vec![
+                MorphTarget { positions: vec![Vector3::zeros(), Vector3::zeros(), Vector3::new(0.0, 0.0, 1.0)], ..Default::default() },
+                MorphTarget { positions: vec![Vector3::new(1.0, 0.0, 0.0); 3], ..Default::default() },
+            ],
+            normals: Some(NormalGeneration::Flat),
+            tangents: false,
+        };
+
+        let (vertices, _) = morph.blend(&[0.0, 0.5]);
+        assert_eq!(vertices[0].position, Point3::new(0.5, 0.0, 0.0));
+        assert_eq!(vertices[0].normal, Vector3::z());
+
+        // the normals follow the tilted triangle.
+        let (vertices, indices) = morph.blend(&[1.0, 0.0]);
+        assert_eq!(vertices[indices[2] as usize].position, Point3::new(0.0, 1.0, 1.0));
+        assert!((vertices[0].normal - Vector3::new(0.0, -1.0, 1.0).normalize()).norm() < 1e-6);
+    }
+}
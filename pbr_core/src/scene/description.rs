@@ -0,0 +1,723 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}, sync::Arc};
+
+use nalgebra::{Point3, Vector3, Affine3, Translation3, UnitQuaternion, Matrix4};
+use serde::Deserialize;
+
+use crate::{
+    error::{Result, Error, Location},
+    spectrum::Spectrum,
+    camera::Camera,
+    light::{LightSource, DirectionalLight, SkySphere},
+    material::{Material, Ggx, Metal, LambertianMaterial, MetalMaterial, PlasticMaterial, ThinDielectricMaterial, PrincipledMaterial},
+    texture::{Texture, SurfaceTexture, MultiplyTexture, ColorSpace, ImageWriter, ImageFormat},
+    accelerator::Accelerator,
+    integrator::{Integrator, PathTracer, BruteForcer},
+    tone_map::{ToneMap, LinearToneMap, ReinhardToneMap},
+};
+
+use super::{Scene, SceneBuilder, loader::{Loader, LoadOptions, MaterialFactory, NormalGeneration, CameraSelection, SceneSelection, AnimationSelection, Gltf, Obj, Ply}};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneFormat {
+    Toml,
+    Json,
+    Ron,
+}
+
+impl SceneFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<SceneFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "toml" => Some(SceneFormat::Toml),
+            "json" => Some(SceneFormat::Json),
+            "ron" => Some(SceneFormat::Ron),
+            _ => None,
+        }
+    }
+}
+
+// A declarative scene: the mesh files to load, the materials, lights and camera around them and
+// how to render and store the image. Relative paths are resolved against the directory of the
+// scene file. Colours are linear rgb triples and angles are in degrees.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub film: FilmDescription,
+    // wins over the camera of a mesh file.
+    pub camera: Option<CameraDescription>,
+    #[serde(default)]
+    pub integrator: IntegratorDescription,
+    #[serde(default)]
+    pub sampler: SamplerDescription,
+    #[serde(default)]
+    pub tone_map: ToneMapDescription,
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDescription>,
+    #[serde(default)]
+    pub meshes: Vec<MeshDescription>,
+    #[serde(default)]
+    pub lights: Vec<LightDescription>,
+    #[serde(default)]
+    pub outputs: Vec<OutputDescription>,
+    // the time in seconds to pose animated meshes at, frames of a sequence differ only in it.
+    #[serde(default)]
+    pub time: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilmDescription {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for FilmDescription {
+    fn default() -> Self {
+        Self { width: 1280, height: 720 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum CameraDescription {
+    Perspective {
+        position: [f32; 3],
+        look_at: [f32; 3],
+        #[serde(default = "default_up")]
+        up: [f32; 3],
+        // the vertical field of view.
+        #[serde(default = "default_fov")]
+        fov: f32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum IntegratorDescription {
+    PathTracer {
+        #[serde(default = "default_depth")]
+        max_depth: u32,
+    },
+    BruteForcer {
+        #[serde(default = "default_depth")]
+        max_depth: u32,
+    },
+}
+
+impl Default for IntegratorDescription {
+    fn default() -> Self {
+        IntegratorDescription::PathTracer { max_depth: default_depth() }
+    }
+}
+
+// every integrator draws independent random samples, so only their number can be chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SamplerDescription {
+    pub samples_per_pixel: u32,
+}
+
+impl Default for SamplerDescription {
+    fn default() -> Self {
+        Self { samples_per_pixel: 64 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ToneMapDescription {
+    Linear {
+        #[serde(default = "default_one")]
+        scale: f32,
+    },
+    Reinhard {
+        white_point: Option<f32>,
+    },
+}
+
+impl Default for ToneMapDescription {
+    fn default() -> Self {
+        ToneMapDescription::Reinhard { white_point: None }
+    }
+}
+
+impl ToneMapDescription {
+    pub fn tone_map(&self) -> Box<dyn ToneMap> {
+        match *self {
+            ToneMapDescription::Linear { scale } => Box::new(LinearToneMap::new_scaling(scale)),
+            ToneMapDescription::Reinhard { white_point: Some(white_point) } => Box::new(ReinhardToneMap::with_whitepoint(white_point)),
+            ToneMapDescription::Reinhard { white_point: None } => Box::new(ReinhardToneMap::new()),
+        }
+    }
+}
+
+// texture paths point to srgb encoded images that are multiplied with the colour.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDescription {
+    Diffuse {
+        #[serde(default = "default_white")]
+        color: [f32; 3],
+        texture: Option<PathBuf>,
+    },
+    // a measured metal by name, e.g. "gold", or a reflectance colour.
+    Metal {
+        metal: Option<String>,
+        #[serde(default = "default_white")]
+        color: [f32; 3],
+        #[serde(default = "default_roughness")]
+        roughness: f32,
+    },
+    Plastic {
+        #[serde(default = "default_white")]
+        color: [f32; 3],
+        texture: Option<PathBuf>,
+        #[serde(default = "default_roughness")]
+        roughness: f32,
+        #[serde(default = "default_ior")]
+        ior: f32,
+    },
+    Glass {
+        #[serde(default = "default_white")]
+        tint: [f32; 3],
+        #[serde(default = "default_ior")]
+        ior: f32,
+    },
+    Principled {
+        #[serde(default = "default_white")]
+        color: [f32; 3],
+        texture: Option<PathBuf>,
+        #[serde(default)]
+        metallic: f32,
+        #[serde(default = "default_roughness")]
+        roughness: f32,
+        #[serde(default)]
+        transmission: f32,
+        #[serde(default = "default_ior")]
+        ior: f32,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MeshDescription {
+    // a gltf, glb, obj or ply file.
+    pub file: PathBuf,
+    // used for the parts the file gives no material.
+    pub material: Option<String>,
+    #[serde(default)]
+    pub normals: NormalGeneration,
+    #[serde(default)]
+    pub translation: [f32; 3],
+    // euler angles, applied around x, then y, then z.
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    #[serde(default = "default_scale")]
+    pub scale: [f32; 3],
+    // the camera of a gltf or glb file to render the scene from, by index or name.
+    pub camera: Option<CameraSelection>,
+    // the scene of a gltf or glb file to load, by index or name.
+    pub scene: Option<SceneSelection>,
+    // the name of a node of a gltf or glb file, to load only its subtree.
+    pub node: Option<String>,
+    // the animation of a gltf or glb file to play, by index or name.
+    pub animation: Option<AnimationSelection>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LightDescription {
+    // direction is the way the light travels.
+    Directional {
+        direction: [f32; 3],
+        irradiance: [f32; 3],
+    },
+    // an environment map in latitude longitude layout, or a constant colour without one.
+    Sky {
+        image: Option<PathBuf>,
+        #[serde(default = "default_white")]
+        color: [f32; 3],
+    },
+}
+
+// png outputs are tone mapped with the scene's tone map unless they set their own, float
+// formats keep the linear values unless a tone map is given.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputDescription {
+    pub path: PathBuf,
+    pub tone_map: Option<ToneMapDescription>,
+    // stores the fraction of camera rays that hit the scene as alpha.
+    #[serde(default)]
+    pub alpha: bool,
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_fov() -> f32 {
+    90.0
+}
+
+fn default_depth() -> u32 {
+    4
+}
+
+fn default_one() -> f32 {
+    1.0
+}
+
+fn default_white() -> [f32; 3] {
+    [1.0; 3]
+}
+
+fn default_scale() -> [f32; 3] {
+    [1.0; 3]
+}
+
+fn default_ior() -> f32 {
+    1.5
+}
+
+fn default_roughness() -> f32 {
+    0.5
+}
+
+// 0 is a mirror like surface, anything negative or not a number can't be shaded.
+fn check_roughness(roughness: f32) -> Result<f32> {
+    if roughness.is_finite() && roughness >= 0.0 {
+        Ok(roughness)
+    } else {
+        Err(Error::invalid(format!("roughness {}", roughness)))
+    }
+}
+
+fn spectrum(color: &[f32; 3]) -> Spectrum<f32> {
+    Spectrum::new(color[0], color[1], color[2])
+}
+
+// Everything in a scene file that is not part of the scene itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub film_size: (u32, u32),
+    pub integrator: IntegratorDescription,
+    pub samples_per_pixel: u32,
+    pub tone_map: ToneMapDescription,
+    pub outputs: Vec<OutputDescription>,
+}
+
+impl RenderSettings {
+    // returns the image and the coverage of each pixel for outputs with alpha.
+    pub fn render<A: Accelerator>(&self, scene: &Scene<A>, report_progress: impl Fn(&Texture<Spectrum<f32>>)) -> (Texture<Spectrum<f32>>, Texture<f32>) {
+        match self.integrator {
+            IntegratorDescription::PathTracer { max_depth } => PathTracer::new(max_depth, self.samples_per_pixel).render(scene, self.film_size, report_progress),
+            IntegratorDescription::BruteForcer { max_depth } => BruteForcer::new(max_depth, self.samples_per_pixel).render(scene, self.film_size, report_progress),
+        }
+    }
+
+    pub fn write_outputs(&self, image: &Texture<Spectrum<f32>>, coverage: &Texture<f32>) -> Result<()> {
+        for output in &self.outputs {
+            let writer = ImageWriter::from_path(&output.path).map_err(|e| Error::from(e).in_file(&output.path))?;
+
+            let tone_map = match (&output.tone_map, writer.format) {
+                (Some(tone_map), _) => Some(tone_map),
+                (None, ImageFormat::Png) => Some(&self.tone_map),
+                (None, _) => None,
+            };
+
+            let mut image = image.clone();
+            if let Some(tone_map) = tone_map {
+                tone_map.tone_map().apply(&mut image);
+            }
+
+            let alpha = output.alpha.then_some(coverage);
+
+            writer.write(&output.path, &image, alpha).map_err(|e| Error::from(e).in_file(&output.path))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl SceneDescription {
+    pub fn parse(text: &str, format: SceneFormat) -> Result<SceneDescription> {
+        match format {
+            SceneFormat::Toml => Ok(toml::from_str(text)?),
+            SceneFormat::Json => Ok(serde_json::from_str(text)?),
+            SceneFormat::Ron => Ok(ron::from_str(text)?),
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<SceneDescription> {
+        let path = path.as_ref();
+        let format = SceneFormat::from_path(path)
+            .ok_or_else(|| Error::unsupported("scene format, expected toml, json or ron").in_file(path))?;
+        let text = std::fs::read_to_string(path).map_err(|e| Error::from(e).in_file(path))?;
+
+        Self::parse(&text, format).map_err(|e| e.in_file(path))
+    }
+
+    // reads a scene file and loads everything it references.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<(SceneBuilder, RenderSettings)> {
+        let path = path.as_ref();
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        Self::from_file(path)?.build(dir).map_err(|e| e.in_file(path))
+    }
+
+    // relative paths are resolved against dir.
+    pub fn build(&self, dir: &Path) -> Result<(SceneBuilder, RenderSettings)> {
+        let mut builder = SceneBuilder::new();
+        let film_size = (self.film.width, self.film.height);
+
+        if film_size.0 == 0 || film_size.1 == 0 {
+            return Err(Error::invalid(format!("film size {}x{}", film_size.0, film_size.1)));
+        }
+
+        let aspect = film_size.0 as f32 / film_size.1 as f32;
+
+        let materials = self.materials.iter()
+            .map(|(name, material)| {
+                let factory = material.factory(dir).map_err(|e| e.at(Location::Material(format!("'{}'", name))))?;
+                Ok((name.as_str(), factory))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+
+        for (i, mesh) in self.meshes.iter().enumerate() {
+            mesh.load(dir, &materials, aspect, &mut builder).map_err(|e| e.at(Location::Mesh(Location::name(None, i))))?;
+        }
+
+        builder = builder.at_time(self.time);
+
+        if let Some(camera) = &self.camera {
+            builder.camera = camera.camera(aspect);
+        }
+
+        for (i, light) in self.lights.iter().enumerate() {
+            let light = light.light(dir).map_err(|e| e.at(Location::Light(Location::name(None, i))))?;
+            builder.light_sources.push(light);
+        }
+
+        let outputs = self.outputs.iter()
+            .map(|output| OutputDescription { path: dir.join(&output.path), ..output.clone() })
+            .collect();
+
+        let settings = RenderSettings {
+            film_size,
+            integrator: self.integrator,
+            samples_per_pixel: self.sampler.samples_per_pixel,
+            tone_map: self.tone_map,
+            outputs,
+        };
+
+        Ok((builder, settings))
+    }
+}
+
+impl CameraDescription {
+    fn camera(&self, aspect: f32) -> Camera {
+        match self {
+            CameraDescription::Perspective { position, look_at, up, fov } => Camera::perspective_look_at(
+                &Point3::from(*position),
+                &Point3::from(*look_at),
+                &Vector3::from(*up),
+                fov.to_radians(),
+                aspect,
+            ),
+        }
+    }
+}
+
+fn load_color_texture(dir: &Path, path: &Option<PathBuf>) -> Result<Option<Arc<Texture<Spectrum<f32>>>>> {
+    path.as_ref()
+        .map(|path| Texture::from_image_file(dir.join(path), ColorSpace::Srgb).map(Arc::new))
+        .transpose()
+}
+
+// the colour, multiplied by the texture if there is one.
+fn color_input(color: Spectrum<f32>, texture: &Option<Arc<Texture<Spectrum<f32>>>>) -> Box<dyn SurfaceTexture<Spectrum<f32>>> {
+    match texture {
+        Some(texture) => Box::new(MultiplyTexture::new(color, texture.clone())),
+        None => Box::new(color),
+    }
+}
+
+impl MaterialDescription {
+    // textures are loaded once here and shared by every material made from the factory.
+    fn factory(&self, dir: &Path) -> Result<MaterialFactory> {
+        let factory: MaterialFactory = match self {
+            MaterialDescription::Diffuse { color, texture } => {
+                let (color, texture) = (spectrum(color), load_color_texture(dir, texture)?);
+                Arc::new(move || Box::new(LambertianMaterial::new(color_input(color, &texture))))
+            },
+            MaterialDescription::Metal { metal: Some(name), roughness, .. } => {
+                let metal = Metal::from_name(name).ok_or_else(|| Error::unsupported(format!("metal '{}'", name)))?;
+                let roughness = check_roughness(*roughness)?;
+                Arc::new(move || Box::new(MetalMaterial::<Ggx>::measured(roughness, metal)))
+            },
+            MaterialDescription::Metal { metal: None, color, roughness } => {
+                let (color, roughness) = (spectrum(color), check_roughness(*roughness)?);
+                Arc::new(move || Box::new(MetalMaterial::<Ggx>::new(roughness, color)))
+            },
+            MaterialDescription::Plastic { color, texture, roughness, ior } => {
+                let (color, texture) = (spectrum(color), load_color_texture(dir, texture)?);
+                let (roughness, ior) = (check_roughness(*roughness)?, *ior);
+                Arc::new(move || Box::new(PlasticMaterial::<Ggx>::new(roughness, ior, color_input(color, &texture))))
+            },
+            MaterialDescription::Glass { tint, ior } => {
+                let (tint, ior) = (spectrum(tint), *ior);
+                Arc::new(move || Box::new(ThinDielectricMaterial::tinted(ior, tint)))
+            },
+            MaterialDescription::Principled { color, texture, metallic, roughness, transmission, ior } => {
+                let (color, texture) = (spectrum(color), load_color_texture(dir, texture)?);
+                let (metallic, roughness, transmission, ior) = (*metallic, check_roughness(*roughness)?, *transmission, *ior);
+                Arc::new(move || {
+                    let mut material = PrincipledMaterial::new(color_input(color, &texture));
+                    material.metallic = Box::new(metallic);
+                    material.roughness = Box::new(roughness);
+                    material.transmission = transmission;
+                    material.ior = ior;
+                    Box::new(material) as Box<dyn Material>
+                })
+            },
+        };
+
+        Ok(factory)
+    }
+}
+
+impl MeshDescription {
+    fn transform(&self) -> Affine3<f32> {
+        let [x, y, z] = self.rotation.map(f32::to_radians);
+        let matrix = Translation3::from(self.translation).to_homogeneous()
+            * UnitQuaternion::from_euler_angles(x, y, z).to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&Vector3::from(self.scale));
+
+        Affine3::from_matrix_unchecked(matrix)
+    }
+
+    fn load(&self, dir: &Path, materials: &BTreeMap<&str, MaterialFactory>, aspect: f32, builder: &mut SceneBuilder) -> Result<()> {
+        let mut options = LoadOptions::default().with_normals(self.normals).with_aspect_ratio(aspect);
+        if let Some(name) = &self.material {
+            let factory = materials.get(name.as_str()).ok_or_else(|| Error::missing(format!("material '{}'", name)))?;
+            options.default_material = Some(factory.clone());
+        }
+        options.camera = self.camera.clone();
+        options.scene = self.scene.clone();
+        options.node = self.node.clone();
+        options.animation = self.animation.clone();
+
+        // the file is loaded on its own so its nodes can be placed together.
+        let path = dir.join(&self.file);
+        let mut file_builder = SceneBuilder::new();
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+
+        if !matches!(extension.as_str(), "gltf" | "glb") {
+            let unsupported = match (&self.camera, &self.scene, &self.node, &self.animation) {
+                (Some(camera), _, _, _) => Some(format!("{}, only gltf and glb files have cameras", camera)),
+                (_, Some(scene), _, _) => Some(format!("{}, only gltf and glb files have scenes", scene)),
+                (_, _, Some(node), _) => Some(format!("node '{}', only gltf and glb files have named nodes", node)),
+                (_, _, _, Some(animation)) => Some(format!("{}, only gltf and glb files have animations", animation)),
+                _ => None,
+            };
+            if let Some(message) = unsupported {
+                return Err(Error::unsupported(message).in_file(&path));
+            }
+        }
+
+        match extension.as_str() {
+            "gltf" | "glb" => Gltf::load_from_file(&path, &mut file_builder, &options)?,
+            "obj" => Obj::load_from_file(&path, &mut file_builder, &options)?,
+            "ply" => Ply::load_from_file(&path, &mut file_builder, &options)?,
+            _ => return Err(Error::unsupported("mesh format, expected gltf, glb, obj or ply").in_file(&path)),
+        }
+
+        if self.camera.is_some() {
+            builder.camera = self.transform() * file_builder.camera;
+        }
+
+        let mut root = file_builder.root;
+        root.transform = self.transform();
+        builder.root.children.push(root);
+
+        Ok(())
+    }
+}
+
+impl LightDescription {
+    fn light(&self, dir: &Path) -> Result<LightSource> {
+        match self {
+            LightDescription::Directional { direction, irradiance } => {
+                let direction = Vector3::from(*direction);
+                if direction.norm() == 0.0 {
+                    return Err(Error::invalid("light direction of zero length"));
+                }
+
+                Ok(LightSource::Directional(DirectionalLight {
+                    neg_direction: -direction.normalize(),
+                    irradiance: spectrum(irradiance),
+                }))
+            },
+            LightDescription::Sky { image, color } => {
+                let mut texture = match image {
+                    Some(image) => Texture::from_file(dir.join(image), ColorSpace::Srgb)?,
+                    None => Texture::new(1, 1, &Spectrum::constant(1.0)),
+                };
+
+                let color = spectrum(color);
+                texture.pixels_mut().for_each(|(_, px)| *px *= color);
+
+                Ok(LightSource::SkySphere(SkySphere::new(texture)))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::{accelerator::Bvh, error::{Cause, Location}};
+
+    use super::{SceneDescription, SceneFormat, MaterialDescription, IntegratorDescription, LightDescription};
+
+    const TOML: &str = r#"
+        film = { width = 8, height = 4 }
+        camera = { type = "perspective", position = [0.0, 0.0, 3.0], look_at = [0.0, 0.0, 0.0], fov = 60.0 }
+        integrator = { type = "brute_forcer", max_depth = 2 }
+        sampler = { samples_per_pixel = 1 }
+
+        [materials.gold]
+        type = "metal"
+        metal = "gold"
+        roughness = 0.2
+
+        [[meshes]]
+        file = "triangle.ply"
+        material = "gold"
+        translation = [0.0, 1.0, 0.0]
+        scale = [2.0, 2.0, 2.0]
+
+        [[lights]]
+        type = "sky"
+        color = [0.5, 0.5, 0.5]
+
+        [[outputs]]
+        path = "render.png"
+    "#;
+
+    const JSON: &str = r#"{
+        "film": { "width": 8, "height": 4 },
+        "camera": { "type": "perspective", "position": [0.0, 0.0, 3.0], "look_at": [0.0, 0.0, 0.0], "fov": 60.0 },
+        "integrator": { "type": "brute_forcer", "max_depth": 2 },
+        "sampler": { "samples_per_pixel": 1 },
+        "materials": { "gold": { "type": "metal", "metal": "gold", "roughness": 0.2 } },
+        "meshes": [ { "file": "triangle.ply", "material": "gold", "translation": [0.0, 1.0, 0.0], "scale": [2.0, 2.0, 2.0] } ],
+        "lights": [ { "type": "sky", "color": [0.5, 0.5, 0.5] } ],
+        "outputs": [ { "path": "render.png" } ]
+    }"#;
+
+    const RON: &str = r#"(
+        film: (width: 8, height: 4),
+        camera: Some((type: "perspective", position: (0.0, 0.0, 3.0), look_at: (0.0, 0.0, 0.0), fov: 60.0)),
+        integrator: (type: "brute_forcer", max_depth: 2),
+        sampler: (samples_per_pixel: 1),
+        materials: { "gold": (type: "metal", metal: Some("gold"), roughness: 0.2) },
+        meshes: [ (file: "triangle.ply", material: Some("gold"), translation: (0.0, 1.0, 0.0), scale: (2.0, 2.0, 2.0)) ],
+        lights: [ (type: "sky", color: (0.5, 0.5, 0.5)) ],
+        outputs: [ (path: "render.png") ],
+    )"#;
+
+    const TRIANGLE: &str = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n";
+
+    fn scene_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("triangle.ply"), TRIANGLE).unwrap();
+        dir
+    }
+
+    #[test]
+    fn scene_format_test() {
+        assert_eq!(SceneFormat::from_path("scene.TOML"), Some(SceneFormat::Toml));
+        assert_eq!(SceneFormat::from_path("scene.xml"), None);
+
+        let scene = SceneDescription::parse(TOML, SceneFormat::Toml).unwrap();
+        assert_eq!(SceneDescription::parse(JSON, SceneFormat::Json).unwrap(), scene);
+        assert_eq!(SceneDescription::parse(RON, SceneFormat::Ron).unwrap(), scene);
+
+        assert_eq!(scene.integrator, IntegratorDescription::BruteForcer { max_depth: 2 });
+        assert_eq!(scene.materials["gold"], MaterialDescription::Metal { metal: Some("gold".to_string()), color: [1.0; 3], roughness: 0.2 });
+        assert_eq!(scene.meshes[0].rotation, [0.0; 3]);
+        assert_eq!(scene.lights[0], LightDescription::Sky { image: None, color: [0.5; 3] });
+        assert_eq!(scene.time, 0.0);
+        assert_eq!(SceneDescription::parse(&format!("time = 1.5\n{}", TOML), SceneFormat::Toml).unwrap().time, 1.5);
+    }
+
+    #[test]
+    fn scene_load_test() {
+        let dir = scene_dir("pbr_core_scene_load_test");
+        let path = dir.join("scene.toml");
+        std::fs::write(&path, TOML).unwrap();
+
+        let (builder, settings) = SceneDescription::load(&path).unwrap();
+        assert_eq!(settings.film_size, (8, 4));
+        assert_eq!(settings.outputs[0].path, dir.join("render.png"));
+
+        let meshes = builder.root.flatten();
+        assert_eq!(meshes.len(), 1);
+        let mut positions: Vec<_> = meshes[0].vertices.iter().map(|v| [v.position.x, v.position.y, v.position.z]).collect();
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        positions.dedup();
+        assert_eq!(positions, vec![[0.0, 1.0, 0.0], [0.0, 3.0, 0.0], [2.0, 1.0, 0.0]]);
+
+        let (builder, settings) = SceneDescription::load(&path).unwrap();
+        let scene = builder.build::<Bvh>();
+        let (image, coverage) = settings.render(&scene, |_| ());
+        assert_eq!(image.size(), (8, 4));
+        assert_eq!(coverage.size(), (8, 4));
+        settings.write_outputs(&image, &coverage).unwrap();
+        assert!(image::open(dir.join("render.png")).is_ok());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn scene_error_test() {
+        let dir = scene_dir("pbr_core_scene_error_test");
+
+        let scene = SceneDescription::parse(&TOML.replace("material = \"gold\"", "material = \"silver\""), SceneFormat::Toml).unwrap();
+        let error = scene.build(&dir).err().unwrap();
+        assert_eq!(error.location, Some(Location::Mesh("#0".to_string())));
+        assert!(matches!(*error.cause, Cause::Missing(_)));
+
+        let scene = SceneDescription::parse(&TOML.replace("roughness = 0.2", "roughness = -0.2"), SceneFormat::Toml).unwrap();
+        let error = scene.build(&dir).err().unwrap();
+        assert_eq!(error.location, Some(Location::Material("'gold'".to_string())));
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+
+        // materials without a roughness get the same default as principled ones, not a mirror.
+        let scene = SceneDescription::parse(&TOML.replace("roughness = 0.2", ""), SceneFormat::Toml).unwrap();
+        assert_eq!(scene.materials["gold"], MaterialDescription::Metal { metal: Some("gold".to_string()), color: [1.0; 3], roughness: 0.5 });
+
+        let error = SceneDescription::parse(&TOML.replace("roughness", "roughnes"), SceneFormat::Toml).unwrap_err();
+        assert!(matches!(*error.cause, Cause::Toml(_)));
+        let error = SceneDescription::parse("{ \"film\": { \"width\": 8 } }", SceneFormat::Json).unwrap_err();
+        assert!(matches!(*error.cause, Cause::Json(_)));
+
+        let error = SceneDescription::from_file(Path::new("scene.yaml")).unwrap_err();
+        assert!(matches!(*error.cause, Cause::Unsupported(_)));
+
+        // only gltf and glb files have cameras, scenes and named nodes to choose from.
+        for selection in ["camera = 0", "scene = \"Main\"", "node = \"Leaf\"", "animation = 1"] {
+            let scene = SceneDescription::parse(&TOML.replace("material = \"gold\"", &format!("material = \"gold\"\n{}", selection)), SceneFormat::Toml).unwrap();
+            let error = scene.build(&dir).err().unwrap();
+            assert!(matches!(*error.cause, Cause::Unsupported(_)));
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
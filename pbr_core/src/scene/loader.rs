@@ -1,13 +1,159 @@
 mod gltf;
+mod obj;
+mod ply;
+mod pbrt;
+mod mitsuba;
+mod tracked;
+pub(super) mod generate;
 
-pub use crate::scene::loader::gltf::Gltf;
+pub use crate::scene::loader::gltf::{Gltf, Glb};
+pub use crate::scene::loader::obj::Obj;
+pub use crate::scene::loader::ply::Ply;
+pub use crate::scene::loader::pbrt::Pbrt;
+pub use crate::scene::loader::mitsuba::Mitsuba;
 
-use crate::scene::SceneBuilder;
+use crate::{scene::SceneBuilder, error::Result, material::{Material, LambertianMaterial}, spectrum::Spectrum};
 
+use std::fmt;
 use std::path::Path;
-use std::io::{Result, Read, Seek};
+use std::io::{Read, Seek};
+use std::sync::Arc;
+
+use nalgebra::Affine3;
+use serde::Deserialize;
+
+// how to fill in normals that a file leaves out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NormalGeneration {
+    #[default]
+    Flat,
+    Smooth,
+}
+
+// creates the material for each mesh whose file does not give it one.
+pub type MaterialFactory = Arc<dyn Fn() -> Box<dyn Material> + Send + Sync>;
+
+// a camera of a file by its index or by its name or the name of its node.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum CameraSelection {
+    Index(usize),
+    Name(String),
+}
+
+impl fmt::Display for CameraSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CameraSelection::Index(index) => write!(f, "camera #{}", index),
+            CameraSelection::Name(name) => write!(f, "camera '{}'", name),
+        }
+    }
+}
+
+// a scene of a file by its index or by its name.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum SceneSelection {
+    Index(usize),
+    Name(String),
+}
+
+impl fmt::Display for SceneSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneSelection::Index(index) => write!(f, "scene #{}", index),
+            SceneSelection::Name(name) => write!(f, "scene '{}'", name),
+        }
+    }
+}
+
+// an animation of a file by its index or by its name.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum AnimationSelection {
+    Index(usize),
+    Name(String),
+}
+
+impl fmt::Display for AnimationSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnimationSelection::Index(index) => write!(f, "animation #{}", index),
+            AnimationSelection::Name(name) => write!(f, "animation '{}'", name),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct LoadOptions {
+    pub normals: NormalGeneration,
+    pub default_material: Option<MaterialFactory>,
+    // without a selection the first camera of the file is used, if it has one.
+    pub camera: Option<CameraSelection>,
+    // the width over the height of the image, which cameras use in place of their own.
+    pub aspect_ratio: Option<f32>,
+    // without a selection the default scene of the file is loaded, or else its first one.
+    pub scene: Option<SceneSelection>,
+    // the name of a node whose subtree is loaded in place of the whole scene.
+    pub node: Option<String>,
+    // without a selection the first animation of the file plays, if it has one.
+    pub animation: Option<AnimationSelection>,
+    // places the loaded nodes and camera, so files can be composed into a larger scene.
+    pub transform: Option<Affine3<f32>>,
+}
+
+impl LoadOptions {
+    pub fn with_normals(mut self, normals: NormalGeneration) -> Self {
+        self.normals = normals;
+        self
+    }
+
+    pub fn with_default_material(mut self, factory: impl Fn() -> Box<dyn Material> + Send + Sync + 'static) -> Self {
+        self.default_material = Some(Arc::new(factory));
+        self
+    }
+
+    pub fn with_camera(mut self, camera: CameraSelection) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    pub fn with_aspect_ratio(mut self, aspect_ratio: f32) -> Self {
+        self.aspect_ratio = Some(aspect_ratio);
+        self
+    }
+
+    pub fn with_scene(mut self, scene: SceneSelection) -> Self {
+        self.scene = Some(scene);
+        self
+    }
+
+    pub fn with_node(mut self, node: impl Into<String>) -> Self {
+        self.node = Some(node.into());
+        self
+    }
+
+    pub fn with_animation(mut self, animation: AnimationSelection) -> Self {
+        self.animation = Some(animation);
+        self
+    }
+
+    pub fn with_transform(mut self, transform: Affine3<f32>) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
+    // without a factory meshes are the diffuse grey most exporters assume.
+    fn default_material(&self) -> Box<dyn Material> {
+        match &self.default_material {
+            Some(factory) => factory(),
+            None => Box::new(LambertianMaterial::flat(Spectrum::constant(0.8))),
+        }
+    }
+}
 
 pub trait Loader {
-    fn load_from_file<P: AsRef<Path>>(path: P, builder: &mut SceneBuilder) -> Result<()>;
-    fn load_from_reader<R: Read + Seek>(rdr: &mut R, builder: &mut SceneBuilder) -> Result<()>;
+    fn load_from_file<P: AsRef<Path>>(path: P, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()>;
+    fn load_from_reader<R: Read + Seek>(rdr: &mut R, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()>;
 }
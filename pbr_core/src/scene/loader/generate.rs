@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+
+use bevy_mikktspace::{Geometry, generate_tangents};
+use nalgebra::{Point2, Point3, Vector3};
+
+use crate::scene::Vertex;
+
+// Fills in vertex attributes that a file left out. Generating flat normals and tangents can
+// split vertices, so those take the index buffer as well.
+
+// gives every corner of every triangle its own vertex.
+fn unweld(vertices: &[Vertex], indices: &[u32]) -> Vec<Vertex> {
+    indices.iter().map(|&i| vertices[i as usize]).collect()
+}
+
+// merges vertices whose attributes are all identical.
+fn weld(corners: Vec<Vertex>) -> (Vec<Vertex>, Vec<u32>) {
+    let mut lookup = HashMap::new();
+    let mut vertices = Vec::new();
+
+    let indices = corners.into_iter()
+        .map(|v| {
+            let key: Vec<u32> = v.position.iter()
+                .chain(v.normal.iter())
+                .chain(v.tangent.iter())
+                .chain(std::iter::once(&v.bitangent_sign))
+                .chain(v.tex_coords.iter())
+                .chain(v.color.iter())
+                .map(|x| x.to_bits())
+                .collect();
+
+            *lookup.entry(key).or_insert_with(|| {
+                vertices.push(v);
+                vertices.len() as u32 - 1
+            })
+        })
+        .collect();
+
+    (vertices, indices)
+}
+
+fn position_key(p: &Point3<f32>) -> [u32; 3] {
+    [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]
+}
+
+pub(super) fn face_normal(p: &[Point3<f32>; 3]) -> Option<Vector3<f32>> {
+    (p[1] - p[0]).cross(&(p[2] - p[0])).try_normalize(0.0)
+}
+
+// any unit vector perpendicular to n.
+fn perpendicular(n: &Vector3<f32>) -> Vector3<f32> {
+    let axis = if n.x.abs() < 0.9 { Vector3::x() } else { Vector3::y() };
+    n.cross(&axis).try_normalize(0.0).unwrap_or_else(Vector3::x)
+}
+
+// Source: https://doi.org/10.1016/B978-0-12-543455-4.50036-4 (Newell's method)
+pub(super) fn polygon_normal(positions: &[Point3<f32>]) -> Vector3<f32> {
+    positions.iter()
+        .zip(positions.iter().cycle().skip(1))
+        .map(|(a, b)| Vector3::new(
+            (a.y - b.y) * (a.z + b.z),
+            (a.z - b.z) * (a.x + b.x),
+            (a.x - b.x) * (a.y + b.y),
+        ))
+        .sum()
+}
+
+// clips ears off the polygon projected onto the plane it faces most, which handles concave polygons.
+// Source: https://www.geometrictools.com/Documentation/TriangulationByEarClipping.pdf
+pub(super) fn triangulate(positions: &[Point3<f32>]) -> Vec<[usize; 3]> {
+    let normal = polygon_normal(positions);
+    let axis = normal.iamax();
+
+    // the other two axes in cyclic order keep the polygon counter clockwise when seen from its front.
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let sign = if normal[axis] < 0.0 { -1.0 } else { 1.0 };
+    let points: Vec<_> = positions.iter().map(|p| Point2::new(p[u], p[v] * sign)).collect();
+
+    let cross = |a: usize, b: usize, c: usize| (points[b] - points[a]).perp(&(points[c] - points[a]));
+    let inside = |p: usize, [a, b, c]: [usize; 3]| cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0;
+
+    let mut remaining: Vec<usize> = (0..positions.len()).collect();
+    let mut triangles = Vec::new();
+
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n)
+            .map(|i| [remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]])
+            .position(|[a, b, c]| {
+                cross(a, b, c) > 0.0 && remaining.iter().all(|&p| [a, b, c].contains(&p) || !inside(p, [a, b, c]))
+            });
+
+        // degenerate or self intersecting polygons can run out of ears, the rest is cut into a fan.
+        let Some(i) = ear else { break };
+        triangles.push([remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]]);
+        remaining.remove(i);
+    }
+
+    triangles.extend((1..remaining.len() - 1).map(|i| [remaining[0], remaining[i], remaining[i + 1]]));
+    triangles
+}
+
+// every triangle gets the normal of its plane, as the gltf spec asks for missing normals.
+pub fn flat_normals(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>) {
+    let mut corners = unweld(vertices, indices);
+
+    for triangle in corners.chunks_exact_mut(3) {
+        let positions = [triangle[0].position, triangle[1].position, triangle[2].position];
+        let normal = face_normal(&positions).unwrap_or_else(Vector3::z);
+        triangle.iter_mut().for_each(|v| v.normal = normal);
+    }
+
+    (*vertices, *indices) = weld(corners);
+}
+
+// averages the normals of the triangles around a vertex, weighted by their angle at it.
+// vertices at the same position share their normal, even when split for other attributes.
+// Source: https://doi.org/10.1080/10867651.1998.10487487
+pub fn smooth_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut sums: HashMap<[u32; 3], Vector3<f32>> = HashMap::new();
+
+    for triangle in indices.chunks_exact(3) {
+        let p = [0, 1, 2].map(|k| vertices[triangle[k] as usize].position);
+        let normal = match face_normal(&p) {
+            Some(normal) => normal,
+            None => continue,
+        };
+
+        for k in 0..3 {
+            let angle = (p[(k + 1) % 3] - p[k]).angle(&(p[(k + 2) % 3] - p[k]));
+            *sums.entry(position_key(&p[k])).or_insert_with(Vector3::zeros) += normal * angle;
+        }
+    }
+
+    for v in vertices.iter_mut() {
+        v.normal = sums.get(&position_key(&v.position))
+            .and_then(|sum| sum.try_normalize(0.0))
+            .unwrap_or_else(Vector3::z);
+    }
+}
+
+// projects the positions onto the plane of the two longest sides of their bounding box,
+// scaled to fill [0, 1].
+pub fn planar_tex_coords(vertices: &mut [Vertex]) {
+    let (min, max) = vertices.iter().fold(
+        (Point3::from(Vector3::repeat(f32::INFINITY)), Point3::from(Vector3::repeat(f32::NEG_INFINITY))),
+        |(min, max), v| (min.inf(&v.position), max.sup(&v.position)),
+    );
+    let extent = max - min;
+
+    // the two longest axes in their original order, so the projection keeps its handedness.
+    let shortest = extent.imin();
+    let (u_axis, v_axis) = match shortest {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    };
+
+    let scale = |x: f32, axis: usize| if extent[axis] > 0.0 { (x - min[axis]) / extent[axis] } else { 0.0 };
+
+    for v in vertices.iter_mut() {
+        v.tex_coords = Point2::new(scale(v.position[u_axis], u_axis), 1.0 - scale(v.position[v_axis], v_axis));
+    }
+}
+
+struct MikkGeometry<'a> {
+    corners: &'a mut [Vertex],
+}
+
+impl Geometry for MikkGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.corners.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.corners[face * 3 + vert].position.into()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.corners[face * 3 + vert].normal.into()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.corners[face * 3 + vert].tex_coords.into()
+    }
+
+    // the fourth component is the sign of the bitangent.
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        let corner = &mut self.corners[face * 3 + vert];
+        corner.tangent = Vector3::new(tangent[0], tangent[1], tangent[2]);
+        corner.bitangent_sign = tangent[3];
+    }
+}
+
+// tangents as baked normal maps expect them, needs normals and texture coordinates.
+// Source: http://www.mikktspace.com
+pub fn mikktspace_tangents(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>) {
+    let mut corners = unweld(vertices, indices);
+
+    if !generate_tangents(&mut MikkGeometry { corners: &mut corners }) {
+        corners.iter_mut().for_each(|v| v.tangent = perpendicular(&v.normal));
+    }
+
+    (*vertices, *indices) = weld(corners);
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point2, Point3, Vector3};
+
+    use crate::{scene::Vertex, geometry::SurfacePoint, material::LambertianMaterial, spectrum::Spectrum};
+
+    use super::{triangulate, flat_normals, smooth_normals, planar_tex_coords, mikktspace_tangents};
+
+    // two triangles folded along the x axis by 90 degrees, sharing the vertices on the fold.
+    fn folded_quad() -> (Vec<Vertex>, Vec<u32>) {
+        let vertex = |x: f32, y: f32, z: f32| Vertex { position: Point3::new(x, y, z), ..Default::default() };
+        let vertices = vec![vertex(0.0, 0.0, 0.0), vertex(2.0, 0.0, 0.0), vertex(0.0, 1.0, 0.0), vertex(0.0, 0.0, -1.0)];
+        (vertices, vec![0, 1, 2, 0, 1, 3])
+    }
+
+    #[test]
+    fn triangulation_test() {
+        // an L shape, concave at its inner corner.
+        let positions = [(0.0, 0.0), (2.0, 0.0), (2.0, 1.0), (1.0, 1.0), (1.0, 2.0), (0.0, 2.0)]
+            .map(|(x, y)| Point3::new(x, 0.0, -y));
+        let triangles = triangulate(&positions);
+
+        assert_eq!(triangles.len(), 4);
+        let normals: Vec<Vector3<f32>> = triangles.iter()
+            .map(|t| (positions[t[1]] - positions[t[0]]).cross(&(positions[t[2]] - positions[t[0]])))
+            .collect();
+
+        // all triangles face the same way and together cover the three unit squares.
+        assert!(normals.iter().all(|n| n.y > 0.0));
+        assert!((normals.iter().map(|n| n.norm() / 2.0).sum::<f32>() - 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn normal_generation_test() {
+        let (mut vertices, mut indices) = folded_quad();
+        flat_normals(&mut vertices, &mut indices);
+
+        // the shared vertices are split as they get a different normal on each side of the fold.
+        assert_eq!((vertices.len(), indices.len()), (6, 6));
+        assert_eq!(vertices[indices[0] as usize].normal, Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(vertices[indices[3] as usize].normal, Vector3::new(0.0, 1.0, 0.0));
+
+        let (mut vertices, indices) = folded_quad();
+        smooth_normals(&mut vertices, &indices);
+
+        let diagonal = Vector3::new(0.0, 1.0, 1.0).normalize();
+        assert!((vertices[0].normal - diagonal).norm() < 1e-6);
+        assert!((vertices[1].normal - diagonal).norm() < 1e-6);
+        assert_eq!(vertices[2].normal, Vector3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn tex_coord_generation_test() {
+        let (mut vertices, _) = folded_quad();
+        planar_tex_coords(&mut vertices);
+
+        // x and z are the longest sides, y is dropped.
+        let uvs: Vec<_> = vertices.iter().map(|v| v.tex_coords).collect();
+        assert_eq!(uvs, vec![Point2::new(0.0, 0.0), Point2::new(1.0, 0.0), Point2::new(0.0, 0.0), Point2::new(0.0, 1.0)]);
+    }
+
+    #[test]
+    fn tangent_generation_test() {
+        let material = LambertianMaterial::flat(Spectrum::constant(0.5));
+
+        // u runs along y and v along -x, or along x when mirrored.
+        for v_sign in [1.0, -1.0] {
+            let vertex = |x: f32, y: f32, u: f32, v: f32| Vertex {
+                position: Point3::new(x, y, 0.0),
+                normal: Vector3::z(),
+                tex_coords: Point2::new(u, v * v_sign),
+                ..Default::default()
+            };
+
+            let mut vertices = vec![vertex(0.0, 0.0, 0.0, 0.0), vertex(0.0, 1.0, 1.0, 0.0), vertex(-1.0, 0.0, 0.0, 1.0)];
+            let mut indices = vec![0, 1, 2];
+            mikktspace_tangents(&mut vertices, &mut indices);
+
+            assert_eq!((vertices.len(), indices.len()), (3, 3));
+            for v in &vertices {
+                assert!((v.tangent - Vector3::y()).norm() < 1e-5, "tangent: {:?}", v.tangent);
+                assert_eq!(v.bitangent_sign, v_sign);
+
+                let frame = SurfacePoint::from_vertex(v, &material).tangent_to_world();
+                assert!((frame.column(1) - Vector3::x() * -v_sign).norm() < 1e-5, "bitangent: {:?}", frame.column(1));
+            }
+        }
+    }
+}
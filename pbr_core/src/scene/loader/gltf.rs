@@ -2,105 +2,488 @@
 pub use gltf::Gltf;
 pub use gltf::Glb;
 
-use itertools::izip;
 use nalgebra::Point2;
 use nalgebra::Vector3;
 use nalgebra::Vector4;
 
+use std::collections::HashMap;
 use std::io::Read;
 use std::io::Seek;
 use std::path::Path;
-use std::io::{Result, Error, ErrorKind};
+use std::fs;
 
-use super::Loader;
+use super::{Loader, LoadOptions, CameraSelection, SceneSelection, AnimationSelection, NormalGeneration, generate};
 use crate::material::MetalMaterial;
 use crate::{
-    material::{Material, Ggx, MicrofacetMaterial},
-    scene::{SceneBuilder, Node, Mesh, Vertex},
+    error::{Result, Error, Location},
+    camera::Camera,
+    material::{Material, Ggx, PlasticMaterial, PrincipledMaterial, ThinDielectricMaterial, MixMaterial, NormalMappedMaterial},
+    scene::{SceneBuilder, Node, Mesh, Vertex, animation::{Interpolation, Keyframes, Morph, MorphTarget, NodeAnimation}},
     spectrum::Spectrum,
+    texture::{Texture, SurfaceTexture, ScaleTexture, MultiplyTexture, ChannelTexture, NormalTexture, Sampler, Filter, WrapMode, MipMap, MipFilter, ColorSpace},
 };
 
 use nalgebra::{Point3, Matrix4, Quaternion, convert, try_convert, Translation3, UnitQuaternion, Scale3, Affine3};
 struct GltfData(Vec<gltf::buffer::Data>, Vec<gltf::image::Data>);
 
 impl Loader for Gltf {
-    fn load_from_file<P: AsRef<Path>>(path: P, builder: &mut SceneBuilder) -> Result<()> {
-        
-        let (document, buffers, images) = gltf::import(path).map_err(|_| Error::from(ErrorKind::InvalidData))?;
-        let data = GltfData(buffers, images);
+    // reads both the json and the binary format.
+    fn load_from_file<P: AsRef<Path>>(path: P, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()> {
+        let load = || -> Result<Import> {
+            let bytes = fs::read(&path)?;
+            let base = path.as_ref().parent().unwrap_or_else(|| Path::new("."));
+            import_slice(&bytes, Some(base), options)
+        };
 
-        let gltf_scene = document.default_scene().unwrap();
+        load().map_err(|e| e.in_file(&path))?.add_to(builder);
 
-        builder.root.children.extend(gltf_scene.nodes()
-            .map(|gltf_node| make_node(gltf_node, &data)));
+        Ok(())
+    }
+
+    // only buffers and images embedded in the file or given as data uris can be resolved.
+    fn load_from_reader<R: Read + Seek>(rdr: &mut R, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()> {
+        let mut bytes = Vec::new();
+        rdr.read_to_end(&mut bytes)?;
+
+        import_slice(&bytes, None, options)?.add_to(builder);
 
         Ok(())
     }
+}
 
-    fn load_from_reader<R: Read + Seek>(_rdr: &mut R, _builder: &mut SceneBuilder) -> Result<()> {
-        unimplemented!()
+// like the gltf loader, but rejects files that are not in the binary format.
+impl Loader for Glb<'_> {
+    fn load_from_file<P: AsRef<Path>>(path: P, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()> {
+        let load = || -> Result<Import> {
+            let bytes = fs::read(&path)?;
+            Glb::from_slice(&bytes)?;
+
+            let base = path.as_ref().parent().unwrap_or_else(|| Path::new("."));
+            import_slice(&bytes, Some(base), options)
+        };
+
+        load().map_err(|e| e.in_file(&path))?.add_to(builder);
+
+        Ok(())
+    }
+
+    fn load_from_reader<R: Read + Seek>(rdr: &mut R, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()> {
+        let mut bytes = Vec::new();
+        rdr.read_to_end(&mut bytes)?;
+        Glb::from_slice(&bytes)?;
+
+        import_slice(&bytes, None, options)?.add_to(builder);
+
+        Ok(())
     }
 }
 
-fn make_node(gltf_node: gltf::Node, data: &GltfData) -> Node {
+// the nodes of a scene and the camera chosen from it.
+struct Import {
+    nodes: Vec<Node>,
+    camera: Option<Camera>,
+}
 
-    let transform = make_affine(&gltf_node.transform());
+impl Import {
+    fn add_to(self, builder: &mut SceneBuilder) {
+        builder.root.children.extend(self.nodes);
+        if let Some(camera) = self.camera {
+            builder.camera = camera;
+        }
+    }
+}
 
-    let meshes = gltf_node.mesh().iter()
-        .flat_map(|gltf_mesh| gltf_mesh.primitives())
-        .map(|gltf_prim| make_mesh(gltf_prim, &data))
-        .collect();
+// external buffers and images are resolved relative to base, without a base only embedded
+// data can be used.
+fn import_slice(bytes: &[u8], base: Option<&Path>, options: &LoadOptions) -> Result<Import> {
+    let Gltf { document, blob } = Gltf::from_slice(bytes)?;
+    let buffers = gltf::import_buffers(&document, base, blob)?;
+    let images = import_images(&document, base, &buffers)?;
+    let data = GltfData(buffers, images);
+
+    // without a default scene the first one is shown, a file without scenes has nothing to show.
+    let gltf_scene = match &options.scene {
+        Some(selection) => {
+            let found = document.scenes().find(|gltf_scene| match selection {
+                SceneSelection::Index(index) => gltf_scene.index() == *index,
+                SceneSelection::Name(name) => gltf_scene.name() == Some(name),
+            });
+            Some(found.ok_or_else(|| Error::missing(selection.to_string()))?)
+        },
+        None => document.default_scene().or_else(|| document.scenes().next()),
+    };
+
+    let gltf_nodes: Vec<_> = gltf_scene.iter().flat_map(|gltf_scene| gltf_scene.nodes()).collect();
+    let gltf_nodes = match &options.node {
+        Some(name) => {
+            let found = gltf_nodes.into_iter().find_map(|gltf_node| find_node(gltf_node, name));
+            vec![found.ok_or_else(|| Error::missing(format!("node '{}' in the scene", name)))?]
+        },
+        None => gltf_nodes,
+    };
+
+    let mut animations = import_animations(&document, &data, options)?;
+
+    let mut cameras = Vec::new();
+    for gltf_node in &gltf_nodes {
+        find_cameras(gltf_node.clone(), &Affine3::identity(), &animations, false, &mut cameras)?;
+    }
+
+    let nodes = gltf_nodes.iter()
+        .map(|gltf_node| make_node(gltf_node.clone(), &data, &mut animations, options))
+        .collect::<Result<Vec<_>>>()?;
+
+    let camera = match &options.camera {
+        Some(selection) => {
+            let found = cameras.into_iter().find(|(gltf_node, gltf_camera, _, _)| match selection {
+                CameraSelection::Index(index) => gltf_camera.index() == *index,
+                CameraSelection::Name(name) => gltf_camera.name() == Some(name) || gltf_node.name() == Some(name),
+            });
+            Some(found.ok_or_else(|| Error::missing(format!("{} in the scene", selection)))?)
+        },
+        None => cameras.into_iter().next(),
+    };
+
+    let camera = camera
+        .map(|(_, gltf_camera, transform, animated)| {
+            let location = Location::Camera(Location::name(gltf_camera.name(), gltf_camera.index()));
+            if animated {
+                log::warn!("{} is placed by an animated node, it stays where the node is at rest", location);
+            }
+            make_camera(&gltf_camera, &transform, options).map_err(|e| e.at(location))
+        })
+        .transpose()?;
+
+    // the nodes are placed under one more node, so their own transforms stay as they are.
+    match options.transform {
+        Some(transform) => Ok(Import {
+            nodes: vec![Node { transform, children: nodes, ..Node::default() }],
+            camera: camera.map(|camera| transform * camera),
+        }),
+        None => Ok(Import { nodes, camera }),
+    }
+}
+
+// the first node with the name in the subtree, searched depth first.
+fn find_node<'a>(gltf_node: gltf::Node<'a>, name: &str) -> Option<gltf::Node<'a>> {
+    if gltf_node.name() == Some(name) {
+        return Some(gltf_node);
+    }
+    gltf_node.children().find_map(|gltf_child| find_node(gltf_child, name))
+}
+
+// the nodes that place a camera, in the order they are visited, with their world transform at
+// rest and whether the node or one of its parents is animated.
+fn find_cameras<'a>(gltf_node: gltf::Node<'a>, parent: &Affine3<f32>, animations: &HashMap<usize, NodeAnimation>, parent_animated: bool, cameras: &mut Vec<(gltf::Node<'a>, gltf::Camera<'a>, Affine3<f32>, bool)>) -> Result<()> {
+    let transform = parent * make_affine(&gltf_node.transform())
+        .map_err(|e| e.at(Location::Node(Location::name(gltf_node.name(), gltf_node.index()))))?;
+    let animated = parent_animated || animations.contains_key(&gltf_node.index());
+
+    if let Some(gltf_camera) = gltf_node.camera() {
+        cameras.push((gltf_node.clone(), gltf_camera, transform, animated));
+    }
+
+    for gltf_child in gltf_node.children() {
+        find_cameras(gltf_child, &transform, animations, animated, cameras)?;
+    }
+
+    Ok(())
+}
+
+// gltf cameras look along -z with y up. The aspect ratio of the image wins over the one of the
+// camera, so the render is not stretched when they differ.
+fn make_camera(gltf_camera: &gltf::Camera, transform: &Affine3<f32>, options: &LoadOptions) -> Result<Camera> {
+    use gltf::camera::Projection;
+
+    let position = transform * Point3::origin();
+    let forward = transform * -Vector3::z();
+    let up = transform * Vector3::y();
+
+    match gltf_camera.projection() {
+        Projection::Perspective(perspective) => {
+            let yfov = perspective.yfov();
+            if !(yfov > 0.0 && yfov < std::f32::consts::PI) {
+                return Err(Error::invalid(format!("vertical field of view {}", yfov)));
+            }
+
+            let aspect = match (options.aspect_ratio, perspective.aspect_ratio()) {
+                (Some(image), Some(camera)) if (image - camera).abs() > 0.01 * image => {
+                    log::warn!("the camera's aspect ratio {} differs from the image's {}, keeping its vertical field of view", camera, image);
+                    image
+                },
+                (Some(image), _) => image,
+                (None, Some(camera)) => camera,
+                (None, None) => 1.0,
+            };
+
+            Ok(Camera::perspective_look_to(&position, &forward, &up, yfov, aspect))
+        },
+        Projection::Orthographic(orthographic) => {
+            let (xmag, ymag) = (orthographic.xmag().abs(), orthographic.ymag().abs());
+            if xmag == 0.0 || ymag == 0.0 {
+                return Err(Error::invalid(format!("orthographic magnification {} by {}", xmag, ymag)));
+            }
+
+            // xmag and ymag are half the width and height of the view.
+            let aspect = options.aspect_ratio.unwrap_or(xmag / ymag);
+            Ok(Camera::orthographic_look_to(&position, &forward, &up, 2.0 * ymag, aspect))
+        },
+    }
+}
+
+fn import_images(document: &gltf::Document, base: Option<&Path>, buffers: &[gltf::buffer::Data]) -> Result<Vec<gltf::image::Data>> {
+    document.images()
+        .map(|gltf_image| {
+            let location = Location::Texture(Location::name(gltf_image.name(), gltf_image.index()));
+
+            // the gltf crate only decodes data uris when given a base, which they do not use.
+            let base = match (gltf_image.source(), base) {
+                (_, Some(base)) => base,
+                (gltf::image::Source::Uri { uri, .. }, None) if !uri.starts_with("data:") => {
+                    return Err(Error::unsupported(format!("external image '{}' without a base directory", uri)).at(location));
+                },
+                _ => Path::new("."),
+            };
+
+            gltf::image::Data::from_source(gltf_image.source(), Some(base), buffers)
+                .map_err(|e| Error::from(e).at(location))
+        })
+        .collect()
+}
+
+// the animation of the node is taken from the ones of the file.
+fn make_node(gltf_node: gltf::Node, data: &GltfData, animations: &mut HashMap<usize, NodeAnimation>, options: &LoadOptions) -> Result<Node> {
+    let node_name = Location::name(gltf_node.name(), gltf_node.index());
+
+    let transform = make_affine(&gltf_node.transform())
+        .map_err(|e| e.at(Location::Node(node_name.clone())))?;
+
+    let mut meshes = Vec::new();
+    let mut morphs = Vec::new();
+    if let Some(gltf_mesh) = gltf_node.mesh() {
+        let mesh_name = Location::name(gltf_mesh.name(), gltf_mesh.index());
+        // the weights of the node win over the ones of its mesh.
+        let weights = gltf_node.weights().or_else(|| gltf_mesh.weights()).unwrap_or(&[]);
+
+        for gltf_prim in gltf_mesh.primitives() {
+            let location = Location::Primitive { mesh: mesh_name.clone(), index: gltf_prim.index() };
+            let (mesh, morph) = make_mesh(gltf_prim, data, weights, options, &location).map_err(|e| e.at(location))?;
+            meshes.push(mesh);
+            morphs.push(morph);
+        }
+    }
+
+    // the meshes as read are only kept when there are weights to blend them by.
+    let mut animation = animations.remove(&gltf_node.index());
+    if let Some(animation) = animation.as_mut().filter(|animation| animation.weights.is_some()) {
+        animation.morphs = morphs;
+    }
 
     let children = gltf_node.children()
-        .map(|gltf_child| make_node(gltf_child, data))
-        .collect();
+        .map(|gltf_child| make_node(gltf_child, data, animations, options))
+        .collect::<Result<_>>()?;
 
-    Node { transform, meshes, children, }
+    Ok(Node { transform, meshes, children, animation })
 }
 
-fn make_affine(gltf_transform: &gltf::scene::Transform) -> Affine3<f32> {
+// the node animations of one clip of the file, clips animating the same nodes would overwrite
+// each other's channels if they were played together.
+fn import_animations(document: &gltf::Document, data: &GltfData, options: &LoadOptions) -> Result<HashMap<usize, NodeAnimation>> {
+    let mut animations = HashMap::new();
+
+    let gltf_animation = match &options.animation {
+        Some(selection) => {
+            let found = document.animations().find(|gltf_animation| match selection {
+                AnimationSelection::Index(index) => gltf_animation.index() == *index,
+                AnimationSelection::Name(name) => gltf_animation.name() == Some(name),
+            });
+            Some(found.ok_or_else(|| Error::missing(selection.to_string()))?)
+        },
+        None => document.animations().next(),
+    };
+
+    if let Some(gltf_animation) = gltf_animation {
+        let location = Location::Animation(Location::name(gltf_animation.name(), gltf_animation.index()));
+        for gltf_channel in gltf_animation.channels() {
+            add_channel(&gltf_channel, data, &mut animations).map_err(|e| e.at(location.clone()))?;
+        }
+    }
+
+    Ok(animations)
+}
+
+fn add_channel(gltf_channel: &gltf::animation::Channel, data: &GltfData, animations: &mut HashMap<usize, NodeAnimation>) -> Result<()> {
+    use gltf::animation::util::ReadOutputs;
+
+    let gltf_node = gltf_channel.target().node();
+    let (t, r, s) = match gltf_node.transform() {
+        gltf::scene::Transform::Decomposed { translation, rotation, scale } => (translation, rotation, scale),
+        gltf::scene::Transform::Matrix { .. } => {
+            let name = Location::name(gltf_node.name(), gltf_node.index());
+            return Err(Error::invalid(format!("target node {}, it has a matrix in place of a translation, rotation and scale", name)));
+        },
+    };
+    let animation = animations.entry(gltf_node.index())
+        .or_insert_with(|| NodeAnimation::new(Vector3::from(t), Quaternion::new(r[3], r[0], r[1], r[2]), Vector3::from(s)));
+
+    let interpolation = match gltf_channel.sampler().interpolation() {
+        gltf::animation::Interpolation::Linear => Interpolation::Linear,
+        gltf::animation::Interpolation::Step => Interpolation::Step,
+        gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+    };
+
+    let reader = gltf_channel.reader(|buffer| Some(&data.0[buffer.index()]));
+    let times: Vec<f32> = reader.read_inputs().ok_or_else(|| Error::missing("keyframe times"))?.collect();
+
+    match reader.read_outputs().ok_or_else(|| Error::missing("keyframe values"))? {
+        ReadOutputs::Translations(values) => {
+            animation.translations = Some(Keyframes::new(times, values.map(Vector3::from).collect(), interpolation)?);
+        },
+        ReadOutputs::Rotations(values) => {
+            let values = values.into_f32().map(|r| Quaternion::new(r[3], r[0], r[1], r[2])).collect();
+            animation.rotations = Some(Keyframes::new(times, values, interpolation)?);
+        },
+        ReadOutputs::Scales(values) => {
+            animation.scales = Some(Keyframes::new(times, values.map(Vector3::from).collect(), interpolation)?);
+        },
+        ReadOutputs::MorphTargetWeights(values) => {
+            // the weights of all targets are stored one keyframe after the other.
+            let values: Vec<f32> = values.into_f32().collect();
+            let per_keyframe = if interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+            let targets = values.len() / (times.len() * per_keyframe).max(1);
+            if targets == 0 || values.len() != targets * times.len() * per_keyframe {
+                return Err(Error::invalid(format!("{} morph target weights for {} keyframes", values.len(), times.len())));
+            }
+
+            let values = values.chunks(targets).map(<[f32]>::to_vec).collect();
+            animation.weights = Some(Keyframes::new(times, values, interpolation)?);
+        },
+    }
+
+    Ok(())
+}
+
+fn make_affine(gltf_transform: &gltf::scene::Transform) -> Result<Affine3<f32>> {
     match gltf_transform {
         gltf::scene::Transform::Matrix{matrix} => {
-            try_convert(Matrix4::from_fn(|i, j| matrix[j][i])).unwrap()
+            try_convert(Matrix4::from_fn(|i, j| matrix[j][i]))
+                .ok_or_else(|| Error::invalid("transform matrix, it is not affine"))
         },
         gltf::scene::Transform::Decomposed{translation: t, rotation: r, scale: s} => {
             let translation = Translation3::new(t[0], t[1], t[2]);
             let rotation = UnitQuaternion::new_unchecked(Quaternion::new(r[3],r[0], r[1], r[2]));
             let scale: Affine3<f32> = convert(Scale3::new(s[0], s[1], s[2]));
-            translation * rotation * scale
+            Ok(translation * rotation * scale)
         }
     }
 }
 
-fn make_mesh(gltf_prim: gltf::Primitive, data: &GltfData) -> Mesh {
+// attributes missing from the file are generated, the location names the primitive in log messages.
+// The mesh is blended from its morph targets by the weights, the morph keeps it as read.
+fn make_mesh(gltf_prim: gltf::Primitive, data: &GltfData, weights: &[f32], options: &LoadOptions, location: &Location) -> Result<(Mesh, Morph)> {
+    if gltf_prim.mode() != gltf::mesh::Mode::Triangles {
+        return Err(Error::unsupported(format!("primitive mode {:?}, only triangles are supported", gltf_prim.mode())));
+    }
+
     let reader = gltf_prim.reader(|buffer| Some(&data.0[buffer.index()]));
 
-    let indices = reader.read_indices()
-        .unwrap()
-        .into_u32()
+    let mut vertices: Vec<Vertex> = reader.read_positions()
+        .ok_or_else(|| Error::missing("positions"))?
+        .map(|p| Vertex { position: Point3::from(p), ..Default::default() })
         .collect();
 
-    
-
-    let vertices = izip!(
-        reader.read_positions().unwrap(),
-        reader.read_normals().unwrap(),
-        reader.read_tangents().unwrap(),
-        reader.read_tex_coords(0).unwrap().into_f32(),
-    )
-        .map(|(p, n, t, uv)| Vertex {
-            position: Point3::from(p),
-            normal: Vector3::from(n),
-            tangent: Vector4::from(t).xyz() * t[3],
-            tex_coords: Point2::from(uv),
+    let normals = reader.read_normals()
+        .map(|normals| set_attribute(&mut vertices, "normal", normals, |v, n| v.normal = Vector3::from(n)))
+        .transpose()?;
+    let tangents = reader.read_tangents()
+        .map(|tangents| set_attribute(&mut vertices, "tangent", tangents, |v, t| {
+            v.tangent = Vector4::from(t).xyz();
+            v.bitangent_sign = t[3];
+        }))
+        .transpose()?;
+    let tex_coords = reader.read_tex_coords(0)
+        .map(|tex_coords| set_attribute(&mut vertices, "texture coordinate", tex_coords.into_f32(), |v, uv| v.tex_coords = Point2::from(uv)))
+        .transpose()?;
+
+    // primitives without indices draw their vertices in order.
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+
+    if !indices.len().is_multiple_of(3) {
+        return Err(Error::invalid(format!("index count {}, it is not a multiple of 3", indices.len())));
+    }
+
+    if let Some(index) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+        return Err(Error::invalid(format!("index {} for {} vertices", index, vertices.len())));
+    }
+
+    let targets = reader.read_morph_targets()
+        .map(|(positions, normals, tangents)| {
+            let target = MorphTarget {
+                positions: positions.map(|d| d.map(Vector3::from).collect()).unwrap_or_default(),
+                normals: normals.map(|d| d.map(Vector3::from).collect()).unwrap_or_default(),
+                tangents: tangents.map(|d| d.map(Vector3::from).collect()).unwrap_or_default(),
+            };
+            match [target.positions.len(), target.normals.len(), target.tangents.len()].into_iter().find(|&n| n != 0 && n != vertices.len()) {
+                Some(n) => Err(Error::invalid(format!("morph target with {} displacements for {} positions", n, vertices.len()))),
+                None => Ok(target),
+            }
         })
-        .collect();
+        .collect::<Result<Vec<_>>>()?;
+
+    if !weights.is_empty() && weights.len() != targets.len() {
+        return Err(Error::invalid(format!("{} morph target weights for {} targets", weights.len(), targets.len())));
+    }
+
+    // the texture coordinates are projected before blending, so they stay on the surface.
+    if tex_coords.is_none() {
+        log::warn!("{} has no texture coordinates, projecting them onto its bounding box", location);
+        generate::planar_tex_coords(&mut vertices);
+    }
+
+    if normals.is_none() {
+        match options.normals {
+            NormalGeneration::Flat => log::warn!("{} has no normals, generating flat normals", location),
+            NormalGeneration::Smooth => log::warn!("{} has no normals, generating smooth normals", location),
+        }
+    }
+
+    if tangents.is_none() {
+        log::warn!("{} has no tangents, generating mikktspace tangents", location);
+    }
 
-    let material = make_material(gltf_prim.material(), data);
-    Mesh { indices, vertices, material }
+    let morph = Morph {
+        vertices,
+        indices,
+        targets,
+        normals: normals.is_none().then_some(options.normals),
+        tangents: tangents.is_none(),
+    };
+    let (vertices, indices) = morph.blend(weights);
+
+    let gltf_material = gltf_prim.material();
+    let material_name = match gltf_material.index() {
+        Some(index) => Location::name(gltf_material.name(), index),
+        None => "default".to_string(),
+    };
+    let material = make_material(gltf_material, data).map_err(|e| e.at(Location::Material(material_name)))?;
+
+    Ok((Mesh { indices, vertices, material: material.into() }, morph))
+}
+
+// every attribute has to provide a value for each vertex.
+fn set_attribute<T>(vertices: &mut [Vertex], name: &str, values: impl ExactSizeIterator<Item = T>, set: impl Fn(&mut Vertex, T)) -> Result<()> {
+    if values.len() != vertices.len() {
+        return Err(Error::invalid(format!("{} count {} for {} positions", name, values.len(), vertices.len())));
+    }
+
+    vertices.iter_mut().zip(values).for_each(|(v, x)| set(v, x));
+    Ok(())
 }
 
-fn make_material(gltf_material: gltf::Material, _data: &GltfData) -> Box<dyn Material> {
+fn make_material(gltf_material: gltf::Material, data: &GltfData) -> Result<Box<dyn Material>> {
 
     let pmr = gltf_material.pbr_metallic_roughness();
 
@@ -108,30 +491,562 @@ fn make_material(gltf_material: gltf::Material, _data: &GltfData) -> Box<dyn Mat
     let metallic_factor = pmr.metallic_factor();
     let roughness_factor = pmr.roughness_factor();
 
-    let material: Box<dyn Material> = if metallic_factor < 0.5 {
-        Box::new(MicrofacetMaterial::<Ggx>::new(roughness_factor))
+    let base_color = || -> Result<_> {
+        let texture = pmr.base_color_texture()
+            .map(|info| make_texture(info.texture(), ColorSpace::Srgb, data))
+            .transpose()?;
+        Ok(make_factored_texture(base_color_factor, texture))
+    };
+
+    let opaque = || -> Result<Box<dyn Material>> {
+        let roughness = make_channel(&pmr, roughness_factor, ROUGHNESS_CHANNEL, data)?;
+        if metallic_factor < 0.5 {
+            Ok(Box::new(PlasticMaterial::<Ggx>::new(roughness, 1.5, base_color()?)))
+        } else {
+            Ok(Box::new(MetalMaterial::<Ggx>::new(roughness, base_color()?)))
+        }
+    };
+
+    let material: Box<dyn Material> = if is_thin_walled_transmission(&gltf_material) {
+        // the sheet is smooth, the roughness only applies to the opaque part it is mixed with.
+        let ior = gltf_material.ior().unwrap_or(1.5);
+        let sheet = ThinDielectricMaterial::tinted(ior, base_color()?);
+        let weight = transmission_factor(&gltf_material) * (1.0 - metallic_factor);
+        Box::new(MixMaterial::new(opaque()?, Box::new(sheet), weight))
+    } else if uses_principled_extensions(&gltf_material) {
+        Box::new(make_principled_material(&gltf_material, data)?)
     } else {
-        Box::new(MetalMaterial::<Ggx>::new(roughness_factor, base_color_factor))
+        opaque()?
     };
 
-    // let base_color = FactoredTexture::new(
-    //     base_color_factor, 
-    //     pmr.base_color_texture().map(|info| make_texture(info.texture(), data)),
-    // );
+    match gltf_material.normal_texture() {
+        Some(info) => {
+            let texture = make_texture(info.texture(), ColorSpace::Linear, data)?;
+            Ok(Box::new(NormalMappedMaterial::new(material, NormalTexture::new(texture, info.scale()))))
+        },
+        None => Ok(material),
+    }
+}
 
-    material
+// the metallic-roughness texture stores roughness in its green and metalness in its blue channel.
+const ROUGHNESS_CHANNEL: usize = 1;
+const METALLIC_CHANNEL: usize = 2;
+
+fn make_channel(pmr: &gltf::material::PbrMetallicRoughness, factor: f32, channel: usize, data: &GltfData) -> Result<Box<dyn SurfaceTexture<f32>>> {
+    let texture = pmr.metallic_roughness_texture()
+        .map(|info| make_texture(info.texture(), ColorSpace::Linear, data))
+        .transpose()?;
+
+    match texture {
+        Some(texture) => Ok(Box::new(ScaleTexture::new(ChannelTexture::new(texture, channel), factor))),
+        None => Ok(Box::new(factor)),
+    }
+}
+
+fn make_factored_texture(factor: Spectrum<f32>, texture: Option<Box<dyn SurfaceTexture<Spectrum<f32>>>>) -> Box<dyn SurfaceTexture<Spectrum<f32>>> {
+    match texture {
+        Some(texture) => Box::new(MultiplyTexture::new(factor, texture)),
+        None => Box::new(factor),
+    }
+}
+
+// a transmissive material with a volume of zero thickness is an infinitely thin sheet.
+fn is_thin_walled_transmission(gltf_material: &gltf::Material) -> bool {
+    let transmission = transmission_factor(gltf_material);
+
+    let thickness = gltf_material.volume()
+        .map(|volume| volume.thickness_factor());
+
+    transmission > 0.0 && thickness == Some(0.0)
+}
+
+fn transmission_factor(gltf_material: &gltf::Material) -> f32 {
+    gltf_material.transmission()
+        .map(|transmission| transmission.transmission_factor())
+        .unwrap_or(0.0)
+}
+
+fn uses_principled_extensions(gltf_material: &gltf::Material) -> bool {
+    gltf_material.transmission().is_some() ||
+    gltf_material.ior().is_some() ||
+    gltf_material.specular().is_some() ||
+    gltf_material.extension_value("KHR_materials_clearcoat").is_some() ||
+    gltf_material.extension_value("KHR_materials_sheen").is_some()
+}
+
+fn make_principled_material(gltf_material: &gltf::Material, data: &GltfData) -> Result<PrincipledMaterial> {
+    let pmr = gltf_material.pbr_metallic_roughness();
+
+    let base_color_texture = pmr.base_color_texture()
+        .map(|info| make_texture(info.texture(), ColorSpace::Srgb, data))
+        .transpose()?;
+    let base_color = make_factored_texture(Spectrum::from(&pmr.base_color_factor()[0..3]), base_color_texture);
+
+    let mut material = PrincipledMaterial::new(base_color);
+    material.metallic = make_channel(&pmr, pmr.metallic_factor(), METALLIC_CHANNEL, data)?;
+    material.roughness = make_channel(&pmr, pmr.roughness_factor(), ROUGHNESS_CHANNEL, data)?;
+    material.ior = gltf_material.ior().unwrap_or(1.5);
+
+    if let Some(transmission) = gltf_material.transmission() {
+        material.transmission = transmission.transmission_factor();
+    }
+
+    if let Some(specular) = gltf_material.specular() {
+        material.specular = specular.specular_factor();
+        material.specular_color = Spectrum::from(&specular.specular_color_factor()[..]);
+    }
+
+    if let Some(clearcoat) = gltf_material.extension_value("KHR_materials_clearcoat") {
+        material.clearcoat = json_f32(clearcoat, "clearcoatFactor").unwrap_or(0.0);
+        material.clearcoat_roughness = json_f32(clearcoat, "clearcoatRoughnessFactor").unwrap_or(0.0);
+        warn_ignored_textures(gltf_material, clearcoat, &["clearcoatTexture", "clearcoatRoughnessTexture", "clearcoatNormalTexture"]);
+    }
+
+    if let Some(sheen) = gltf_material.extension_value("KHR_materials_sheen") {
+        let color = json_spectrum(sheen, "sheenColorFactor").unwrap_or_else(Spectrum::black);
+        material.sheen = if color == Spectrum::black() { 0.0 } else { 1.0 };
+        material.sheen_color = color;
+        material.sheen_roughness = json_f32(sheen, "sheenRoughnessFactor").unwrap_or(0.0);
+        warn_ignored_textures(gltf_material, sheen, &["sheenColorTexture", "sheenRoughnessTexture"]);
+    }
+
+    Ok(material)
+}
+
+// the clearcoat and sheen inputs of the principled material are constants, so only their factors are used.
+fn warn_ignored_textures(gltf_material: &gltf::Material, extension: &gltf::json::Value, keys: &[&str]) {
+    for key in keys.iter().filter(|&&key| extension.get(key).is_some()) {
+        log::warn!("{} of material '{}' is not supported and is ignored", key, gltf_material.name().unwrap_or("unnamed"));
+    }
+}
+
+fn json_f32(value: &gltf::json::Value, key: &str) -> Option<f32> {
+    value.get(key)?.as_f64().map(|x| x as f32)
 }
 
-// fn make_texture(gtlf_texture: gltf::Texture, data: &GltfData) -> Texture<Spectrum<f32>> {
-//     let img_data = &data.1[gtlf_texture.source().index()];
+fn json_spectrum(value: &gltf::json::Value, key: &str) -> Option<Spectrum<f32>> {
+    let array = value.get(key)?.as_array()?;
+    let components = array.iter()
+        .map(|x| x.as_f64().map(|x| x as f32))
+        .collect::<Option<Vec<f32>>>()?;
+
+    (components.len() == 3).then(|| Spectrum::from(&components[..]))
+}
+
+// colour textures are srgb encoded, data textures like normals and roughness are linear.
+fn make_texture(gltf_texture: gltf::Texture, color_space: ColorSpace, data: &GltfData) -> Result<Box<dyn SurfaceTexture<Spectrum<f32>>>> {
+    use gltf::texture::MinFilter;
+
+    let texture = make_image_texture(&gltf_texture, color_space, data)
+        .map_err(|e| e.at(Location::Texture(Location::name(gltf_texture.name(), gltf_texture.index()))))?;
+
+    // mipmaps are built unless the sampler explicitly asks for a single level.
+    match gltf_texture.sampler().min_filter() {
+        Some(MinFilter::Nearest) | Some(MinFilter::Linear) => Ok(Box::new(texture)),
+        _ => Ok(Box::new(MipMap::new(texture, MipFilter::Trilinear))),
+    }
+}
+
+fn make_image_texture(gtlf_texture: &gltf::Texture, color_space: ColorSpace, data: &GltfData) -> Result<Texture<Spectrum<f32>>> {
+    let img_data = &data.1[gtlf_texture.source().index()];
+
+    let pixels = &img_data.pixels;
+    let width = img_data.width;
+    let height = img_data.height;
+
+    let texture = match img_data.format {
+        gltf::image::Format::R8G8B8 => Texture::<Spectrum<f32>>::from_raw_data::<u8, 3>(width, height, pixels, color_space)?,
+        gltf::image::Format::R8G8B8A8 => Texture::<Spectrum<f32>>::from_raw_data::<u8, 4>(width, height, pixels, color_space)?,
+        format => return Err(Error::unsupported(format!("image format {:?}", format))),
+    };
+
+    Ok(texture.with_sampler(make_sampler(&gtlf_texture.sampler())))
+}
+
+fn make_sampler(gltf_sampler: &gltf::texture::Sampler) -> Sampler {
+    use gltf::texture::{MagFilter, WrappingMode};
+
+    // without a filter specified viewers smoothly interpolate.
+    let filter = match gltf_sampler.mag_filter() {
+        Some(MagFilter::Nearest) => Filter::Nearest,
+        Some(MagFilter::Linear) | None => Filter::Bilinear,
+    };
+
+    let wrap = |mode| match mode {
+        WrappingMode::Repeat => WrapMode::Repeat,
+        WrappingMode::ClampToEdge => WrapMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => WrapMode::MirroredRepeat,
+    };
+
+    Sampler::new(filter, wrap(gltf_sampler.wrap_s()), wrap(gltf_sampler.wrap_t()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, path::PathBuf};
+
+    use nalgebra::{Point2, Vector3, Affine3, Translation3, convert};
+
+    use crate::{error::{Cause, Location}, scene::{SceneBuilder, Mesh, loader::{CameraSelection, SceneSelection, AnimationSelection, Loader, LoadOptions, NormalGeneration}}};
+
+    use super::{Gltf, Glb};
+
+    // the json and buffer of a single triangle with the given indices, with only its positions when
+    // the other attributes are left out. The buffer has no uri if buffer_uri gives none.
+    fn triangle(indices: [u16; 3], only_positions: bool, buffer_uri: impl Fn(&[u8]) -> Option<String>) -> (String, Vec<u8>) {
+        let positions = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let normals = [[0.0f32, 0.0, 1.0]; 3];
+        let tangents = [[1.0f32, 0.0, 0.0, 1.0]; 3];
+        let tex_coords = [[0.0f32, 0.0], [1.0, 0.0], [0.0, 1.0]];
+
+        let mut buffer: Vec<u8> = Vec::new();
+        buffer.extend(positions.iter().flatten().flat_map(|x| x.to_le_bytes()));
+        buffer.extend(normals.iter().flatten().flat_map(|x| x.to_le_bytes()));
+        buffer.extend(tangents.iter().flatten().flat_map(|x| x.to_le_bytes()));
+        buffer.extend(tex_coords.iter().flatten().flat_map(|x| x.to_le_bytes()));
+        buffer.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+        buffer.extend([0, 0]);
+
+        let attributes = if only_positions {
+            r#"{ "POSITION": 0 }"#
+        } else {
+            r#"{ "POSITION": 0, "NORMAL": 1, "TANGENT": 2, "TEXCOORD_0": 3 }"#
+        };
+
+        let buffer_json = match buffer_uri(&buffer) {
+            Some(uri) => format!(r#"{{ "uri": "{}", "byteLength": {} }}"#, uri, buffer.len()),
+            None => format!(r#"{{ "byteLength": {} }}"#, buffer.len()),
+        };
+
+        let json = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scene": 0,
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": [{{ "mesh": 0 }}],
+            "meshes": [{{ "name": "Triangle", "primitives": [{{
+                "attributes": {attributes},
+                "indices": 4
+            }}] }}],
+            "buffers": [{buffer}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 72, "byteLength": 48 }},
+                {{ "buffer": 0, "byteOffset": 120, "byteLength": 24 }},
+                {{ "buffer": 0, "byteOffset": 144, "byteLength": 6 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+                {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                {{ "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC4" }},
+                {{ "bufferView": 3, "componentType": 5126, "count": 3, "type": "VEC2" }},
+                {{ "bufferView": 4, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+            ]
+        }}"#, attributes = attributes, buffer = buffer_json);
+
+        (json, buffer)
+    }
+
+    // writes the triangle next to its buffer into the temp directory.
+    fn write_triangle(name: &str, indices: [u16; 3], only_positions: bool) -> PathBuf {
+        let (json, buffer) = triangle(indices, only_positions, |_| Some(format!("{}.bin", name)));
 
-//     let pixels = &img_data.pixels;
-//     let width = img_data.width;
-//     let height = img_data.height;
+        let dir = std::env::temp_dir();
+        std::fs::write(dir.join(format!("{}.bin", name)), buffer).unwrap();
+        let path = dir.join(format!("{}.gltf", name));
+        std::fs::write(&path, json).unwrap();
+        path
+    }
+
+    // Source: https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#binary-gltf-layout
+    fn glb(json: &str, buffer: &[u8]) -> Vec<u8> {
+        // chunks are padded to four bytes, json with spaces and binary data with zeros.
+        let pad = |data: &[u8], fill: u8| {
+            let mut data = data.to_vec();
+            data.resize(data.len().div_ceil(4) * 4, fill);
+            data
+        };
+        let chunks = [(pad(json.as_bytes(), b' '), *b"JSON"), (pad(buffer, 0), *b"BIN\0")];
+
+        let length = 12 + chunks.iter().map(|(data, _)| 8 + data.len()).sum::<usize>();
+        let mut glb = Vec::new();
+        glb.extend(b"glTF");
+        glb.extend(2u32.to_le_bytes());
+        glb.extend((length as u32).to_le_bytes());
+
+        for (data, kind) in chunks {
+            glb.extend((data.len() as u32).to_le_bytes());
+            glb.extend(kind);
+            glb.extend(data);
+        }
+
+        glb
+    }
+
+    fn remove_triangle(path: PathBuf) {
+        std::fs::remove_file(path.with_extension("bin")).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn gltf_load_test() {
+        let path = write_triangle("pbr_core_gltf_triangle", [0, 1, 2], false);
+        let mut builder = SceneBuilder::new();
+        Gltf::load_from_file(&path, &mut builder, &LoadOptions::default()).unwrap();
+        assert_eq!(builder.root.flatten().len(), 1);
+
+        remove_triangle(path);
+    }
+
+    #[test]
+    fn gltf_error_test() {
+        let missing = std::env::temp_dir().join("pbr_core_gltf_missing.gltf");
+        let error = Gltf::load_from_file(&missing, &mut SceneBuilder::new(), &LoadOptions::default()).unwrap_err();
+        assert_eq!(error.path, Some(missing));
+
+        let path = write_triangle("pbr_core_gltf_bad_indices", [0, 1, 7], false);
+        let error = Gltf::load_from_file(&path, &mut SceneBuilder::new(), &LoadOptions::default()).unwrap_err();
+        assert_eq!(error.path.as_ref(), Some(&path));
+        assert_eq!(error.location, Some(Location::Primitive { mesh: "'Triangle'".to_string(), index: 0 }));
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+
+        remove_triangle(path);
+    }
+
+    #[test]
+    fn gltf_generate_test() {
+        let path = write_triangle("pbr_core_gltf_positions", [0, 1, 2], true);
+
+        for normals in [NormalGeneration::Flat, NormalGeneration::Smooth] {
+            let mut builder = SceneBuilder::new();
+            Gltf::load_from_file(&path, &mut builder, &LoadOptions::default().with_normals(normals)).unwrap();
+
+            let meshes = builder.root.flatten();
+            let vertices = &meshes[0].vertices;
+            assert_eq!(vertices.len(), 3);
+            assert!(vertices.iter().all(|v| v.normal == nalgebra::Vector3::z()));
+            assert!(vertices.iter().all(|v| (v.tangent.norm() - 1.0).abs() < 1e-5 && v.tangent.z == 0.0));
+        }
+
+        remove_triangle(path);
+    }
+
+    #[test]
+    fn gltf_reader_test() {
+        let options = LoadOptions::default();
+        let (json, buffer) = triangle([0, 1, 2], false, |buffer| {
+            Some(format!("data:application/octet-stream;base64,{}", base64::encode(buffer)))
+        });
+
+        let mut builder = SceneBuilder::new();
+        Gltf::load_from_reader(&mut Cursor::new(json.as_bytes()), &mut builder, &options).unwrap();
+        assert_eq!(builder.root.flatten().len(), 1);
+
+        // the glb loader only reads the binary format, the gltf loader reads both.
+        assert!(Glb::load_from_reader(&mut Cursor::new(json.as_bytes()), &mut SceneBuilder::new(), &options).is_err());
+
+        let (binary_json, _) = triangle([0, 1, 2], false, |_| None);
+        let binary = glb(&binary_json, &buffer);
+
+        for load in [Gltf::load_from_reader::<Cursor<&[u8]>>, Glb::load_from_reader::<Cursor<&[u8]>>] {
+            let mut builder = SceneBuilder::new();
+            load(&mut Cursor::new(&binary), &mut builder, &options).unwrap();
+            assert_eq!(builder.root.flatten().len(), 1);
+        }
+
+        let path = std::env::temp_dir().join("pbr_core_gltf_binary.glb");
+        std::fs::write(&path, &binary).unwrap();
+        let mut builder = SceneBuilder::new();
+        Glb::load_from_file(&path, &mut builder, &options).unwrap();
+        Gltf::load_from_file(&path, &mut builder, &options).unwrap();
+        assert_eq!(builder.root.flatten().len(), 2);
+        std::fs::remove_file(path).unwrap();
+
+        // external buffers can not be resolved without a directory.
+        let (external, _) = triangle([0, 1, 2], false, |_| Some("triangle.bin".to_string()));
+        assert!(Gltf::load_from_reader(&mut Cursor::new(external.as_bytes()), &mut SceneBuilder::new(), &options).is_err());
+    }
+
+    // the triangle with a perspective camera on a child of its node, looking along -x, and an
+    // orthographic camera looking down.
+    fn triangle_with_cameras() -> String {
+        let (json, _) = triangle([0, 1, 2], false, |buffer| {
+            Some(format!("data:application/octet-stream;base64,{}", base64::encode(buffer)))
+        });
+        json.replace(r#""nodes": [0] }"#, r#""nodes": [0, 2] }"#).replace(r#""nodes": [{ "mesh": 0 }],"#, r#""nodes": [
+                { "mesh": 0, "children": [1], "translation": [0, 0, 5] },
+                { "name": "Main", "camera": 0, "rotation": [0, 0.70710678, 0, 0.70710678] },
+                { "camera": 1, "translation": [0, 10, 0], "rotation": [-0.70710678, 0, 0, 0.70710678] }
+            ],
+            "cameras": [
+                { "name": "Lens", "type": "perspective", "perspective": { "yfov": 0.5, "aspectRatio": 2.0, "znear": 0.1 } },
+                { "type": "orthographic", "orthographic": { "xmag": 2.0, "ymag": 1.0, "znear": 0.1, "zfar": 100.0 } }
+            ],"#)
+    }
 
-//     match img_data.format {
-//         gltf::image::Format::R8G8B8 => Texture::<Spectrum<f32>>::from_raw_data::<u8, 3>(width, height, pixels).unwrap(),
-//         gltf::image::Format::R8G8B8A8 => Texture::<Spectrum<f32>>::from_raw_data::<u8, 4>(width, height, pixels).unwrap(),
-//         _ => todo!(),
-//     }
-// }
+    #[test]
+    fn gltf_camera_test() {
+        let json = triangle_with_cameras();
+        let load = |options: &LoadOptions| {
+            let mut builder = SceneBuilder::new();
+            Gltf::load_from_reader(&mut Cursor::new(json.as_bytes()), &mut builder, options).map(|_| builder.camera)
+        };
+
+        // the first camera is used when none is chosen, with its own aspect ratio.
+        let camera = load(&LoadOptions::default()).unwrap();
+        let ray = camera.get_ray(Point2::new(199, 50), (200, 100)).ray;
+        assert!((ray.origin.coords - Vector3::new(0.0, 0.0, 5.0)).norm() < 1e-5);
+        assert!(ray.direction.x < 0.0 && ray.direction.z < 0.0 && ray.direction.y.abs() < 0.01);
+        assert!((ray.direction.z / ray.direction.x - 2.0 * 0.25f32.tan()).abs() < 0.01);
+
+        // the aspect ratio of the image wins, cameras are found by their own or their node's name.
+        for selection in [CameraSelection::Name("Main".to_string()), CameraSelection::Name("Lens".to_string()), CameraSelection::Index(0)] {
+            let camera = load(&LoadOptions::default().with_camera(selection).with_aspect_ratio(1.0)).unwrap();
+            let ray = camera.get_ray(Point2::new(199, 50), (200, 100)).ray;
+            assert!((ray.direction.z / ray.direction.x - 0.25f32.tan()).abs() < 0.01);
+        }
+
+        let camera = load(&LoadOptions::default().with_camera(CameraSelection::Index(1))).unwrap();
+        let ray = camera.get_ray(Point2::new(199, 50), (200, 100)).ray;
+        assert!((ray.direction - Vector3::new(0.0, -1.0, 0.0)).norm() < 1e-5);
+        assert!((ray.origin.x - 1.99).abs() < 0.011 && (ray.origin.y - 10.0).abs() < 1e-5 && ray.origin.z.abs() < 0.021);
+
+        let error = load(&LoadOptions::default().with_camera(CameraSelection::Name("Side".to_string()))).err().unwrap();
+        assert!(matches!(*error.cause, Cause::Missing(_)));
+
+        let bad = json.replace(r#""yfov": 0.5"#, r#""yfov": 4.0"#);
+        let error = Gltf::load_from_reader(&mut Cursor::new(bad.as_bytes()), &mut SceneBuilder::new(), &LoadOptions::default()).unwrap_err();
+        assert_eq!(error.location, Some(Location::Camera("'Lens'".to_string())));
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+    }
+
+    #[test]
+    fn gltf_scene_test() {
+        let (json, _) = triangle([0, 1, 2], false, |buffer| {
+            Some(format!("data:application/octet-stream;base64,{}", base64::encode(buffer)))
+        });
+        // two scenes without a default one, the second has a prop off to the side.
+        let json = json.replace(r#""scene": 0,"#, "")
+            .replace(r#""scenes": [{ "nodes": [0] }],"#, r#""scenes": [{ "name": "Main", "nodes": [0] }, { "name": "Props", "nodes": [1] }],"#)
+            .replace(r#""nodes": [{ "mesh": 0 }],"#, r#""nodes": [
+                { "name": "Root", "mesh": 0, "children": [2] },
+                { "name": "Prop", "mesh": 0, "translation": [5, 0, 0] },
+                { "name": "Leaf", "mesh": 0, "translation": [0, 0, 1] }
+            ],"#);
+
+        let load = |options: &LoadOptions| {
+            let mut builder = SceneBuilder::new();
+            Gltf::load_from_reader(&mut Cursor::new(json.as_bytes()), &mut builder, options).map(|_| builder.root.flatten())
+        };
+
+        assert_eq!(load(&LoadOptions::default()).unwrap().len(), 2);
+
+        for selection in [SceneSelection::Index(1), SceneSelection::Name("Props".to_string())] {
+            let meshes = load(&LoadOptions::default().with_scene(selection)).unwrap();
+            assert_eq!(meshes.len(), 1);
+            assert!(meshes[0].vertices.iter().all(|v| v.position.x >= 5.0));
+        }
+
+        // a subtree keeps the transform of its own node and goes under the given one.
+        let transform: Affine3<f32> = convert(Translation3::new(0.0, 2.0, 0.0));
+        let meshes = load(&LoadOptions::default().with_node("Leaf").with_transform(transform)).unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert!(meshes[0].vertices.iter().all(|v| v.position.z == 1.0 && v.position.y >= 2.0));
+
+        for options in [LoadOptions::default().with_scene(SceneSelection::Index(2)), LoadOptions::default().with_node("Prop")] {
+            let error = load(&options).err().unwrap();
+            assert!(matches!(*error.cause, Cause::Missing(_)));
+        }
+
+        // the cameras of a subtree are placed by the given transform, not by the nodes above them.
+        let json = triangle_with_cameras();
+        let mut builder = SceneBuilder::new();
+        let options = LoadOptions::default().with_node("Main").with_transform(transform);
+        Gltf::load_from_reader(&mut Cursor::new(json.as_bytes()), &mut builder, &options).unwrap();
+        assert!(builder.root.flatten().is_empty());
+        let ray = builder.camera.get_ray(Point2::new(100, 50), (200, 100)).ray;
+        assert!((ray.origin.coords - Vector3::new(0.0, 2.0, 0.0)).norm() < 1e-5);
+        assert!(ray.direction.x < -0.99);
+    }
+
+    // the triangle moving two along x in a second and snapping onto a morph target raised by one,
+    // half raised at rest. a second clip snaps it along x at the end of the second.
+    fn animated_triangle() -> String {
+        let (json, mut buffer) = triangle([0, 1, 2], false, |_| None);
+        let length = buffer.len();
+
+        let floats = [0.0f32, 1.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0];
+        buffer.extend(floats.iter().flat_map(|x| x.to_le_bytes()));
+        let uri = format!("data:application/octet-stream;base64,{}", base64::encode(&buffer));
+
+        json.replace(&format!(r#"{{ "byteLength": {} }}"#, length), &format!(r#"{{ "uri": "{}", "byteLength": {} }}"#, uri, buffer.len()))
+            .replace(r#""name": "Triangle","#, r#""name": "Triangle", "weights": [0.5],"#)
+            .replace(r#""indices": 4"#, r#""indices": 4, "targets": [{ "POSITION": 8 }]"#)
+            .replace(r#"{ "buffer": 0, "byteOffset": 144, "byteLength": 6 }"#, &format!(r#"{{ "buffer": 0, "byteOffset": 144, "byteLength": 6 }},
+                {{ "buffer": 0, "byteOffset": {}, "byteLength": 8 }},
+                {{ "buffer": 0, "byteOffset": {}, "byteLength": 24 }},
+                {{ "buffer": 0, "byteOffset": {}, "byteLength": 8 }},
+                {{ "buffer": 0, "byteOffset": {}, "byteLength": 36 }}"#, length, length + 8, length + 32, length + 40))
+            .replace(r#"{ "bufferView": 4, "componentType": 5123, "count": 3, "type": "SCALAR" }"#, r#"{ "bufferView": 4, "componentType": 5123, "count": 3, "type": "SCALAR" },
+                { "bufferView": 5, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0], "max": [1] },
+                { "bufferView": 6, "componentType": 5126, "count": 2, "type": "VEC3" },
+                { "bufferView": 7, "componentType": 5126, "count": 2, "type": "SCALAR" },
+                { "bufferView": 8, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 1], "max": [0, 0, 1] }"#)
+            .replace(r#""scene": 0,"#, r#""scene": 0,
+            "animations": [{
+                "name": "Slide",
+                "channels": [
+                    { "sampler": 0, "target": { "node": 0, "path": "translation" } },
+                    { "sampler": 1, "target": { "node": 0, "path": "weights" } }
+                ],
+                "samplers": [
+                    { "input": 5, "output": 6 },
+                    { "input": 5, "output": 7, "interpolation": "STEP" }
+                ]
+            }, {
+                "name": "Snap",
+                "channels": [{ "sampler": 0, "target": { "node": 0, "path": "translation" } }],
+                "samplers": [{ "input": 5, "output": 6, "interpolation": "STEP" }]
+            }],"#)
+    }
+
+    #[test]
+    fn gltf_animation_test() {
+        let json = animated_triangle();
+        let mut builder = SceneBuilder::new();
+        Gltf::load_from_reader(&mut Cursor::new(json.as_bytes()), &mut builder, &LoadOptions::default()).unwrap();
+
+        let bounds = |meshes: Vec<Mesh>| {
+            let positions: Vec<_> = meshes[0].vertices.iter().map(|v| v.position).collect();
+            let min_x = positions.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
+            (min_x, positions[0].z)
+        };
+
+        assert_eq!(builder.duration(), 1.0);
+
+        // posing copies the meshes, so the same builder can be posed at any time and in any order.
+        assert_eq!(bounds(builder.meshes_at(0.5)), (1.0, 0.0));
+        assert_eq!(bounds(builder.meshes_at(1.0)), (2.0, 1.0));
+        assert_eq!(bounds(builder.meshes_at(3.0)), (2.0, 1.0));
+        assert_eq!(bounds(builder.meshes_at(0.5)), (1.0, 0.0));
+
+        assert_eq!(bounds(builder.root.flatten()), (0.0, 0.5));
+
+        // only the selected clip plays, the other one doesn't overwrite its channels.
+        for selection in [AnimationSelection::Index(1), AnimationSelection::Name("Snap".to_string())] {
+            let mut builder = SceneBuilder::new();
+            Gltf::load_from_reader(&mut Cursor::new(json.as_bytes()), &mut builder, &LoadOptions::default().with_animation(selection)).unwrap();
+            assert_eq!(bounds(builder.meshes_at(0.5)), (0.0, 0.5));
+            assert_eq!(bounds(builder.meshes_at(1.0)), (2.0, 0.5));
+        }
+
+        let options = LoadOptions::default().with_animation(AnimationSelection::Name("Spin".to_string()));
+        let error = Gltf::load_from_reader(&mut Cursor::new(json.as_bytes()), &mut SceneBuilder::new(), &options).unwrap_err();
+        assert!(matches!(*error.cause, Cause::Missing(_)));
+
+        let bad = json.replace(r#"{ "bufferView": 7, "componentType": 5126, "count": 2, "type": "SCALAR" }"#, r#"{ "bufferView": 7, "componentType": 5126, "count": 1, "type": "SCALAR" }"#);
+        let error = Gltf::load_from_reader(&mut Cursor::new(bad.as_bytes()), &mut SceneBuilder::new(), &LoadOptions::default()).unwrap_err();
+        assert_eq!(error.location, Some(Location::Animation("'Slide'".to_string())));
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+    }
+}
@@ -0,0 +1,1096 @@
+use std::{collections::HashMap, f32::consts::PI, fs, io::{Read, Seek}, path::{Path, PathBuf}, sync::Arc};
+
+use nalgebra::{Affine3, Matrix3, Matrix4, Point2, Point3, Rotation3, Vector3, Unit};
+use roxmltree::{Document, Node as XmlNode};
+
+use super::{Loader, LoadOptions, MaterialFactory, NormalGeneration, Obj, Ply, tracked::{Named, Tracked}};
+use crate::{
+    error::{Result, Error, Location},
+    camera::Camera,
+    light::{LightSource, PointLight, DirectionalLight, SkySphere},
+    material::{Material, Ggx, Metal, LambertianMaterial, MetalMaterial, PlasticMaterial, PrincipledMaterial, ThinDielectricMaterial, EmissiveMaterial},
+    scene::{SceneBuilder, Node, Mesh, Vertex},
+    spectrum::Spectrum,
+    texture::{Texture, FactoredTexture, ColorSpace},
+};
+
+// Mitsuba 3 scene files, and 0.6 ones where only the property names differ: the perspective sensor
+// and its film, obj, ply, rectangle and sphere shapes, the common bsdfs and area, point,
+// directional, constant and environment map emitters. Everything else is skipped with a warning.
+// Objects with an id can be used again with <ref>, and $name in an attribute is replaced by the
+// value of the parameter, which <default> declares unless the caller gives it.
+// Source: https://mitsuba.readthedocs.io/en/stable/src/key_topics/scene_format.html
+pub struct Mitsuba;
+
+impl Loader for Mitsuba {
+    fn load_from_file<P: AsRef<Path>>(path: P, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()> {
+        Self::load_with_parameters(path, builder, options, &HashMap::new())
+    }
+
+    fn load_from_reader<R: Read + Seek>(rdr: &mut R, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()> {
+        let mut text = String::new();
+        rdr.read_to_string(&mut text)?;
+
+        // included files are looked up relative to the working directory.
+        let mut importer = Importer::new(Path::new(""), options, HashMap::new());
+        importer.parse(&text)?;
+        importer.finish(builder);
+
+        Ok(())
+    }
+}
+
+impl Mitsuba {
+    // the parameters take the place of the defaults in the file, like mitsuba's -D option.
+    pub fn load_with_parameters<P: AsRef<Path>>(path: P, builder: &mut SceneBuilder, options: &LoadOptions, parameters: &HashMap<String, String>) -> Result<()> {
+        let dir = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+        let mut importer = Importer::new(dir, options, parameters.clone());
+
+        importer.parse_file(path.as_ref())?;
+        importer.finish(builder);
+
+        Ok(())
+    }
+}
+
+enum Value {
+    Number(f32),
+    Bool(bool),
+    String(String),
+    Color(Spectrum<f32>),
+    Vector(Vector3<f32>),
+    Transform(Matrix4<f32>),
+    Texture(Texture<Spectrum<f32>>),
+}
+
+struct Property {
+    name: String,
+    value: Value,
+}
+
+impl Named for Property {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+struct Props(Tracked<Property>);
+
+impl Props {
+    fn find(&self, names: &[&str]) -> Option<&Property> {
+        self.0.find(names)
+    }
+
+    fn float(&self, names: &[&str], default: f32) -> Result<f32> {
+        match self.find(names) {
+            Some(Property { value: Value::Number(x), .. }) => Ok(*x),
+            Some(property) => Err(Error::invalid(format!("property '{}', expected a number", property.name))),
+            None => Ok(default),
+        }
+    }
+
+    fn string(&self, names: &[&str]) -> Option<&str> {
+        match self.find(names) {
+            Some(Property { value: Value::String(string), .. }) => Some(string),
+            _ => None,
+        }
+    }
+
+    fn bool(&self, names: &[&str], default: bool) -> bool {
+        match self.find(names) {
+            Some(Property { value: Value::Bool(b), .. }) => *b,
+            _ => default,
+        }
+    }
+
+    fn vector(&self, names: &[&str]) -> Option<Vector3<f32>> {
+        match self.find(names) {
+            Some(Property { value: Value::Vector(v), .. }) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn transform(&self) -> Matrix4<f32> {
+        match self.find(&["to_world", "toWorld"]) {
+            Some(Property { value: Value::Transform(m), .. }) => *m,
+            _ => Matrix4::identity(),
+        }
+    }
+
+    fn color(&self, names: &[&str]) -> Option<Spectrum<f32>> {
+        match self.find(names) {
+            Some(Property { value: Value::Color(color), .. }) => Some(*color),
+            Some(Property { value: Value::Number(x), .. }) => Some(Spectrum::constant(*x)),
+            Some(property) => {
+                log::warn!("property '{}' is not a colour and is ignored", property.name);
+                None
+            },
+            None => None,
+        }
+    }
+
+    // a colour input of a bsdf, which may be a bitmap texture.
+    fn color_texture(&self, names: &[&str], default: f32) -> FactoredTexture<Spectrum<f32>> {
+        match self.find(names) {
+            Some(Property { value: Value::Texture(texture), .. }) => FactoredTexture::new(Spectrum::constant(1.0), Some(texture.clone())),
+            _ => FactoredTexture::new(self.color(names).unwrap_or_else(|| Spectrum::constant(default)), None),
+        }
+    }
+
+    fn ior(&self, names: &[&str], default: f32) -> Result<f32> {
+        match self.find(names) {
+            Some(Property { value: Value::Number(x), .. }) => Ok(*x),
+            Some(Property { value: Value::String(name), .. }) => named_ior(name).ok_or_else(|| Error::invalid(format!("index of refraction '{}'", name))),
+            Some(property) => Err(Error::invalid(format!("property '{}', expected an index of refraction", property.name))),
+            None => Ok(default),
+        }
+    }
+
+    // mitsuba gives the microfacet alpha, ours is squared to get alpha.
+    fn roughness(&self, default: f32) -> Result<f32> {
+        let alpha = match (self.find(&["alpha_u", "alphaU"]), self.find(&["alpha_v", "alphaV"])) {
+            (Some(Property { value: Value::Number(u), .. }), Some(Property { value: Value::Number(v), .. })) => (u + v) / 2.0,
+            _ => self.float(&["alpha"], default)?,
+        };
+
+        Ok(alpha.max(0.0).sqrt())
+    }
+
+    fn warn_unused(&self, object: &str) {
+        for property in self.0.unused() {
+            log::warn!("{} property '{}' is not supported and is ignored", object, property.name);
+        }
+    }
+}
+
+struct Importer<'a> {
+    dir: PathBuf,
+    options: &'a LoadOptions,
+    // the files currently being parsed, an include of one of them would never end.
+    open_files: Vec<PathBuf>,
+    parameters: HashMap<String, String>,
+    default_material: MaterialFactory,
+    bsdfs: HashMap<String, MaterialFactory>,
+    textures: HashMap<String, Texture<Spectrum<f32>>>,
+    // the world from camera transform, the vertical field of view and the aspect ratio.
+    camera: Option<(Matrix4<f32>, f32, f32)>,
+    nodes: Vec<Node>,
+    lights: Vec<LightSource>,
+    // environment maps and their world transform, resampled once it is known whether the world is mirrored.
+    environments: Vec<(Texture<Spectrum<f32>>, Matrix4<f32>)>,
+}
+
+impl<'a> Importer<'a> {
+    fn new(dir: &Path, options: &'a LoadOptions, parameters: HashMap<String, String>) -> Self {
+        // mitsuba's default bsdf is a grey diffuse one.
+        let default_material: MaterialFactory = match &options.default_material {
+            Some(factory) => factory.clone(),
+            None => Arc::new(|| Box::new(LambertianMaterial::flat(Spectrum::constant(0.5)))),
+        };
+
+        Self {
+            dir: dir.to_path_buf(),
+            options,
+            open_files: Vec::new(),
+            parameters,
+            default_material,
+            bsdfs: HashMap::new(),
+            textures: HashMap::new(),
+            camera: None,
+            nodes: Vec::new(),
+            lights: Vec::new(),
+            environments: Vec::new(),
+        }
+    }
+
+    fn parse_file(&mut self, path: &Path) -> Result<()> {
+        let text = fs::read_to_string(path).map_err(|e| Error::from(e).in_file(path))?;
+
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if self.open_files.contains(&canonical) {
+            return Err(Error::invalid(format!("{} includes itself", path.display())));
+        }
+
+        self.open_files.push(canonical);
+        let result = self.parse(&text).map_err(|e| e.in_file(path));
+        self.open_files.pop();
+
+        result
+    }
+
+    fn parse(&mut self, text: &str) -> Result<()> {
+        let document = Document::parse(text).map_err(|e| {
+            let line = e.pos().row as usize;
+            Error::from(e).at(Location::Line(line))
+        })?;
+
+        let scene = document.root_element();
+        if !scene.has_tag_name("scene") {
+            return Err(Error::invalid(format!("root element <{}>, expected <scene>", scene.tag_name().name())).at(line(scene)));
+        }
+
+        for node in scene.children().filter(XmlNode::is_element) {
+            self.element(node).map_err(|e| e.at(line(node)))?;
+        }
+
+        Ok(())
+    }
+
+    fn element(&mut self, node: XmlNode) -> Result<()> {
+        match node.tag_name().name() {
+            "default" => {
+                let (name, value) = (self.required(node, "name")?, self.required(node, "value")?);
+                self.parameters.entry(name).or_insert(value);
+            },
+            "include" => {
+                let path = self.dir.join(self.required(node, "filename")?);
+                self.parse_file(&path)?;
+            },
+            "sensor" => self.sensor(node)?,
+            "bsdf" => {
+                self.bsdf(node)?;
+            },
+            "texture" => {
+                self.texture(node)?;
+            },
+            "shape" => self.shape(node)?,
+            "emitter" => self.emitter(node)?,
+            "integrator" | "medium" | "phase" | "shapegroup" => log::warn!("<{}> is not supported and is ignored", node.tag_name().name()),
+            tag => return Err(Error::unsupported(format!("element <{}>", tag))),
+        }
+
+        Ok(())
+    }
+
+    // replaces every $name with the value of the parameter.
+    fn substitute(&self, text: &str) -> Result<String> {
+        let mut result = String::new();
+        let mut rest = text;
+
+        while let Some(start) = rest.find('$') {
+            result.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+
+            let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            let name = &rest[..end];
+            let value = self.parameters.get(name).ok_or_else(|| Error::missing(format!("value of parameter '{}'", name)))?;
+            result.push_str(value);
+            rest = &rest[end..];
+        }
+        result.push_str(rest);
+
+        Ok(result)
+    }
+
+    fn attribute(&self, node: XmlNode, name: &str) -> Result<Option<String>> {
+        node.attribute(name).map(|value| self.substitute(value)).transpose()
+    }
+
+    fn required(&self, node: XmlNode, name: &str) -> Result<String> {
+        self.attribute(node, name)?.ok_or_else(|| Error::missing(format!("attribute '{}' of <{}>", name, node.tag_name().name())))
+    }
+
+    // the properties among the children of an object, nested objects are left to the caller.
+    fn properties(&mut self, node: XmlNode) -> Result<Props> {
+        let mut properties = Vec::new();
+
+        for child in node.children().filter(XmlNode::is_element) {
+            if let Some(property) = self.property(child).map_err(|e| e.at(line(child)))? {
+                properties.push(property);
+            }
+        }
+
+        Ok(Props(Tracked::new(properties)))
+    }
+
+    fn property(&mut self, node: XmlNode) -> Result<Option<Property>> {
+        let value = match node.tag_name().name() {
+            "float" | "integer" => Value::Number(number(&self.required(node, "value")?)?),
+            "boolean" => match self.required(node, "value")?.as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                value => return Err(Error::invalid(format!("boolean '{}'", value))),
+            },
+            "string" => Value::String(self.required(node, "value")?),
+            "rgb" | "srgb" | "spectrum" => Value::Color(self.color(node)?),
+            "blackbody" => {
+                log::warn!("blackbody spectra are not supported, using white instead");
+                Value::Color(Spectrum::constant(1.0))
+            },
+            "point" | "vector" => Value::Vector(self.xyz(node, 0.0)?),
+            "transform" => Value::Transform(self.transform(node)?),
+            "texture" if node.has_attribute("name") => match self.texture(node)? {
+                Some(texture) => Value::Texture(texture),
+                None => return Ok(None),
+            },
+            "ref" if node.has_attribute("name") => {
+                let id = self.required(node, "id")?;
+                let texture = self.textures.get(&id).ok_or_else(|| Error::missing(format!("texture '{}'", id)))?;
+                Value::Texture(texture.clone())
+            },
+            "texture" | "ref" | "bsdf" | "emitter" | "film" | "sampler" | "rfilter" | "medium" | "phase" => return Ok(None),
+            tag => return Err(Error::unsupported(format!("element <{}>", tag))),
+        };
+
+        Ok(Some(Property { name: self.required(node, "name")?, value }))
+    }
+
+    fn color(&self, node: XmlNode) -> Result<Spectrum<f32>> {
+        let value = self.required(node, "value")?;
+
+        // wavelength and value pairs, which are averaged to grey.
+        if node.has_tag_name("spectrum") && value.contains(':') {
+            let values = value.split(',')
+                .map(|pair| match pair.split_once(':') {
+                    Some((_, value)) => number(value),
+                    None => Err(Error::invalid(format!("spectrum '{}'", value))),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(Spectrum::constant(values.iter().sum::<f32>() / values.len() as f32));
+        }
+
+        let color = match numbers(&value)?[..] {
+            [x] => Spectrum::constant(x),
+            [r, g, b] => Spectrum::new(r, g, b),
+            _ => return Err(Error::invalid(format!("colour '{}'", value))),
+        };
+
+        // srgb colours are gamma encoded.
+        Ok(match node.tag_name().name() {
+            "srgb" => Spectrum::new(ColorSpace::Srgb.decode(color.r), ColorSpace::Srgb.decode(color.g), ColorSpace::Srgb.decode(color.b)),
+            _ => color,
+        })
+    }
+
+    // x, y and z are given on their own or together as the value, where one number stands for all three.
+    fn xyz(&self, node: XmlNode, default: f32) -> Result<Vector3<f32>> {
+        if let Some(value) = self.attribute(node, "value")? {
+            return match numbers(&value)?[..] {
+                [x] => Ok(Vector3::repeat(x)),
+                [x, y, z] => Ok(Vector3::new(x, y, z)),
+                _ => Err(Error::invalid(format!("vector '{}'", value))),
+            };
+        }
+
+        let component = |name| match self.attribute(node, name)? {
+            Some(value) => number(&value),
+            None => Ok(default),
+        };
+
+        Ok(Vector3::new(component("x")?, component("y")?, component("z")?))
+    }
+
+    fn transform(&self, node: XmlNode) -> Result<Matrix4<f32>> {
+        let mut transform = Matrix4::identity();
+
+        // each operation is applied after the ones before it.
+        for child in node.children().filter(XmlNode::is_element) {
+            transform = self.transform_operation(child).map_err(|e| e.at(line(child)))? * transform;
+        }
+
+        Ok(transform)
+    }
+
+    fn transform_operation(&self, node: XmlNode) -> Result<Matrix4<f32>> {
+        Ok(match node.tag_name().name() {
+            "translate" => Matrix4::new_translation(&self.xyz(node, 0.0)?),
+            "scale" => Matrix4::new_nonuniform_scaling(&self.xyz(node, 1.0)?),
+            "rotate" => {
+                let axis = Unit::try_new(self.xyz(node, 0.0)?, 0.0).ok_or_else(|| Error::invalid("rotation axis of zero length"))?;
+                let angle = number(&self.required(node, "angle")?)?;
+                Rotation3::from_axis_angle(&axis, angle.to_radians()).to_homogeneous()
+            },
+            // matrices are given row by row.
+            "matrix" => {
+                let values = numbers(&self.required(node, "value")?)?;
+                match values.len() {
+                    16 => Matrix4::from_row_slice(&values),
+                    9 => Matrix3::from_row_slice(&values).to_homogeneous(),
+                    n => return Err(Error::invalid(format!("matrix of {} numbers", n))),
+                }
+            },
+            "lookat" => {
+                let vector = |name| -> Result<Option<Vector3<f32>>> {
+                    match self.attribute(node, name)? {
+                        Some(value) => match numbers(&value)?[..] {
+                            [x, y, z] => Ok(Some(Vector3::new(x, y, z))),
+                            _ => Err(Error::invalid(format!("{} '{}' of the lookat", name, value))),
+                        },
+                        None => Ok(None),
+                    }
+                };
+                let origin = vector("origin")?.ok_or_else(|| Error::missing("origin of the lookat"))?;
+                let target = vector("target")?.ok_or_else(|| Error::missing("target of the lookat"))?;
+                look_at(&origin, &target, &vector("up")?.unwrap_or_else(Vector3::y))?
+            },
+            tag => return Err(Error::unsupported(format!("transform <{}>", tag))),
+        })
+    }
+
+    fn sensor(&mut self, node: XmlNode) -> Result<()> {
+        let ty = self.required(node, "type")?;
+        let props = self.properties(node)?;
+
+        if !matches!(ty.as_str(), "perspective" | "thinlens") {
+            log::warn!("sensor '{}' is not supported and is ignored", ty);
+            return Ok(());
+        }
+        if node.children().any(|child| child.has_tag_name("sampler")) {
+            log::warn!("the sampler of the sensor is not supported and is ignored");
+        }
+
+        let (width, height) = self.film(node)?;
+        let aspect = width / height;
+
+        // a focal length is for a 35mm film and spans its diagonal.
+        let (fov, axis) = match props.find(&["fov"]) {
+            Some(_) => (props.float(&["fov"], 90.0)?, props.string(&["fov_axis", "fovAxis"]).unwrap_or("x")),
+            None => {
+                let focal_length = props.string(&["focal_length", "focalLength"]).unwrap_or("50mm");
+                let focal_length = number(focal_length.trim_end_matches("mm"))?;
+                ((36.0f32.hypot(24.0) / (2.0 * focal_length)).atan().to_degrees() * 2.0, "diagonal")
+            },
+        };
+
+        let tan = (fov.to_radians() / 2.0).tan();
+        let tan_y = match axis {
+            "x" => tan / aspect,
+            "y" => tan,
+            "diagonal" => tan / aspect.hypot(1.0),
+            "smaller" if aspect >= 1.0 => tan,
+            "smaller" => tan / aspect,
+            "larger" if aspect >= 1.0 => tan / aspect,
+            "larger" => tan,
+            axis => return Err(Error::invalid(format!("field of view axis '{}'", axis))),
+        };
+
+        self.camera = Some((props.transform(), 2.0 * tan_y.atan(), aspect));
+        props.warn_unused(&format!("sensor '{}'", ty));
+
+        Ok(())
+    }
+
+    // the width and height of the sensor's film.
+    fn film(&mut self, node: XmlNode) -> Result<(f32, f32)> {
+        match node.children().find(|child| child.has_tag_name("film")) {
+            Some(film) => {
+                let props = self.properties(film).map_err(|e| e.at(line(film)))?;
+                let size = (props.float(&["width"], 768.0)?, props.float(&["height"], 576.0)?);
+                props.warn_unused(&format!("film '{}'", film.attribute("type").unwrap_or("")));
+                Ok(size)
+            },
+            None => Ok((768.0, 576.0)),
+        }
+    }
+
+    fn texture(&mut self, node: XmlNode) -> Result<Option<Texture<Spectrum<f32>>>> {
+        let ty = self.required(node, "type")?;
+        if ty != "bitmap" {
+            log::warn!("texture '{}' is not supported and is ignored", ty);
+            return Ok(None);
+        }
+
+        let props = self.properties(node)?;
+        let filename = props.string(&["filename"]).ok_or_else(|| Error::missing("filename of the bitmap"))?;
+        let texture = load_image(&self.dir.join(filename), props.bool(&["raw"], false))?;
+        props.warn_unused("texture 'bitmap'");
+
+        if let Some(id) = self.attribute(node, "id")? {
+            self.textures.insert(id, texture.clone());
+        }
+
+        Ok(Some(texture))
+    }
+
+    fn bsdf(&mut self, node: XmlNode) -> Result<MaterialFactory> {
+        let factory = self.bsdf_factory(node)?;
+
+        if let Some(id) = self.attribute(node, "id")? {
+            self.bsdfs.insert(id, factory.clone());
+        }
+
+        Ok(factory)
+    }
+
+    fn bsdf_factory(&mut self, node: XmlNode) -> Result<MaterialFactory> {
+        let ty = self.required(node, "type")?;
+        let props = self.properties(node)?;
+
+        let factory: MaterialFactory = match ty.as_str() {
+            // our materials shade both sides already.
+            "twosided" => self.nested_bsdf(node)?.unwrap_or_else(|| self.default_material.clone()),
+            "diffuse" => {
+                let texture = props.color_texture(&["reflectance"], 0.5);
+                Arc::new(move || Box::new(LambertianMaterial::new(FactoredTexture::new(texture.factor, texture.texture.clone()))))
+            },
+            "conductor" | "roughconductor" => {
+                let roughness = if ty == "conductor" { 0.0 } else { props.roughness(0.1)? };
+                let reflectance = props.color(&["specular_reflectance", "specularReflectance"]).unwrap_or_else(|| Spectrum::constant(1.0));
+                match (props.color(&["eta"]), props.color(&["k"]), props.string(&["material"]).unwrap_or("none")) {
+                    (Some(eta), Some(k), _) => Arc::new(move || Box::new(MetalMaterial::<Ggx>::conductor(roughness, eta, k))),
+                    (_, _, "none") => Arc::new(move || Box::new(MetalMaterial::<Ggx>::new(roughness, reflectance))),
+                    (_, _, name) => match Metal::from_name(name) {
+                        Some(metal) => Arc::new(move || Box::new(MetalMaterial::<Ggx>::measured(roughness, metal))),
+                        None => {
+                            log::warn!("metal '{}' is not known, using a perfect mirror", name);
+                            Arc::new(move || Box::new(MetalMaterial::<Ggx>::new(roughness, reflectance)))
+                        },
+                    },
+                }
+            },
+            "dielectric" | "roughdielectric" | "thindielectric" => {
+                let ior = props.ior(&["int_ior", "intIOR"], 1.5046)? / props.ior(&["ext_ior", "extIOR"], 1.000277)?;
+                if ty == "thindielectric" {
+                    Arc::new(move || Box::new(ThinDielectricMaterial::new(ior)))
+                } else {
+                    let roughness = if ty == "dielectric" { 0.0 } else { props.roughness(0.1)? };
+                    Arc::new(move || {
+                        let mut material = PrincipledMaterial::flat(Spectrum::constant(1.0));
+                        material.roughness = Box::new(roughness);
+                        material.transmission = 1.0;
+                        material.ior = ior;
+                        Box::new(material) as Box<dyn Material>
+                    })
+                }
+            },
+            "plastic" | "roughplastic" => {
+                let texture = props.color_texture(&["diffuse_reflectance", "diffuseReflectance"], 0.5);
+                let ior = props.ior(&["int_ior", "intIOR"], 1.49)? / props.ior(&["ext_ior", "extIOR"], 1.000277)?;
+                let roughness = if ty == "plastic" { 0.0 } else { props.roughness(0.1)? };
+                Arc::new(move || Box::new(PlasticMaterial::<Ggx>::new(roughness, ior, FactoredTexture::new(texture.factor, texture.texture.clone()))))
+            },
+            _ => {
+                log::warn!("bsdf '{}' is not supported, using the default material instead", ty);
+                return Ok(self.default_material.clone());
+            },
+        };
+
+        props.warn_unused(&format!("bsdf '{}'", ty));
+
+        Ok(factory)
+    }
+
+    // the bsdf nested in an object or referenced from it.
+    fn nested_bsdf(&mut self, node: XmlNode) -> Result<Option<MaterialFactory>> {
+        for child in node.children().filter(XmlNode::is_element) {
+            let factory = match child.tag_name().name() {
+                "bsdf" => self.bsdf(child),
+                "ref" if !child.has_attribute("name") => self.required(child, "id").and_then(|id| {
+                    self.bsdfs.get(&id).cloned().ok_or_else(|| Error::missing(format!("bsdf '{}'", id)))
+                }),
+                _ => continue,
+            };
+            return factory.map(Some).map_err(|e| e.at(line(child)));
+        }
+
+        Ok(None)
+    }
+
+    // the radiance of the area emitter nested in a shape.
+    fn area_emitter(&mut self, node: XmlNode) -> Result<Option<Spectrum<f32>>> {
+        for child in node.children().filter(|child| child.has_tag_name("emitter")) {
+            let emitter = |importer: &mut Self| -> Result<Option<Spectrum<f32>>> {
+                let ty = importer.required(child, "type")?;
+                if ty != "area" {
+                    log::warn!("emitter '{}' on a shape is not supported and is ignored", ty);
+                    return Ok(None);
+                }
+
+                let props = importer.properties(child)?;
+                let radiance = props.color(&["radiance"]).unwrap_or_else(|| Spectrum::constant(1.0));
+                props.warn_unused("emitter 'area'");
+                Ok(Some(radiance))
+            };
+
+            if let Some(radiance) = emitter(self).map_err(|e| e.at(line(child)))? {
+                return Ok(Some(radiance));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn shape(&mut self, node: XmlNode) -> Result<()> {
+        let ty = self.required(node, "type")?;
+        let props = self.properties(node)?;
+
+        if !matches!(ty.as_str(), "obj" | "ply" | "rectangle" | "sphere") {
+            log::warn!("shape '{}' is not supported and is ignored", ty);
+            return Ok(());
+        }
+
+        // area emitters shine from the front of the shape only.
+        let mut material = self.nested_bsdf(node)?.unwrap_or_else(|| self.default_material.clone());
+        if let Some(radiance) = self.area_emitter(node)? {
+            let inner = material;
+            material = Arc::new(move || Box::new(EmissiveMaterial::new(inner(), radiance, false)));
+        }
+
+        let mut transform = props.transform();
+        let mut shape = match ty.as_str() {
+            "obj" | "ply" => {
+                let filename = props.string(&["filename"]).ok_or_else(|| Error::missing(format!("filename of the {} shape", ty)))?;
+
+                // face_normals chooses how missing normals are made, otherwise the options do.
+                let mut options = self.options.clone();
+                options.normals = match props.bool(&["face_normals", "faceNormals"], self.options.normals == NormalGeneration::Flat) {
+                    true => NormalGeneration::Flat,
+                    false => NormalGeneration::Smooth,
+                };
+                options.default_material = Some(material.clone());
+
+                let mut builder = SceneBuilder::new();
+                match ty.as_str() {
+                    "obj" => Obj::load_from_file(self.dir.join(filename), &mut builder, &options)?,
+                    _ => Ply::load_from_file(self.dir.join(filename), &mut builder, &options)?,
+                }
+
+                // the bsdf of the shape is used in place of the file's materials.
+                let mut root = builder.root;
+                for_each_mesh(&mut root, &mut |mesh| mesh.material = material().into());
+                root
+            },
+            "rectangle" => {
+                let (vertices, indices) = rectangle();
+                Node { meshes: vec![Mesh { vertices, indices, material: material().into() }], ..Default::default() }
+            },
+            _ => {
+                let center = props.vector(&["center"]).unwrap_or_else(Vector3::zeros);
+                let radius = props.float(&["radius"], 1.0)?;
+                transform *= Matrix4::new_translation(&center) * Matrix4::new_scaling(radius);
+
+                let (vertices, indices) = sphere();
+                Node { meshes: vec![Mesh { vertices, indices, material: material().into() }], ..Default::default() }
+            },
+        };
+
+        if props.bool(&["flip_normals", "flipNormals"], false) {
+            for_each_mesh(&mut shape, &mut |mesh| mesh.vertices.iter_mut().for_each(|v| v.normal = -v.normal));
+        }
+
+        props.warn_unused(&format!("shape '{}'", ty));
+
+        shape.transform = Affine3::from_matrix_unchecked(transform);
+        self.nodes.push(shape);
+
+        Ok(())
+    }
+
+    fn emitter(&mut self, node: XmlNode) -> Result<()> {
+        let ty = self.required(node, "type")?;
+        let props = self.properties(node)?;
+        let transform = props.transform();
+        let affine = Affine3::from_matrix_unchecked(transform);
+
+        let light = match ty.as_str() {
+            "point" => {
+                let position = props.vector(&["position"]).map_or_else(Point3::origin, Point3::from);
+                LightSource::Point(PointLight {
+                    position: affine * position,
+                    intensity: props.color(&["intensity"]).unwrap_or_else(|| Spectrum::constant(1.0)),
+                })
+            },
+            "directional" => {
+                let direction = props.vector(&["direction"]).unwrap_or_else(Vector3::z);
+                let direction = (affine * direction).try_normalize(0.0).ok_or_else(|| Error::invalid("emitter direction of zero length"))?;
+                LightSource::Directional(DirectionalLight {
+                    neg_direction: -direction,
+                    irradiance: props.color(&["irradiance"]).unwrap_or_else(|| Spectrum::constant(1.0)),
+                })
+            },
+            "constant" => {
+                let radiance = props.color(&["radiance"]).unwrap_or_else(|| Spectrum::constant(1.0));
+                LightSource::SkySphere(SkySphere::new(Texture::new(1, 1, &radiance)))
+            },
+            "envmap" => {
+                let filename = props.string(&["filename"]).ok_or_else(|| Error::missing("filename of the envmap"))?;
+                let mut texture = load_image(&self.dir.join(filename), false)?;
+                let scale = props.float(&["scale"], 1.0)?;
+                texture.pixels_mut().for_each(|(_, px)| *px *= scale);
+
+                props.warn_unused("emitter 'envmap'");
+                self.environments.push((texture, transform));
+                return Ok(());
+            },
+            "area" => return Err(Error::invalid("area emitter outside of a shape")),
+            _ => {
+                log::warn!("emitter '{}' is not supported and is ignored", ty);
+                return Ok(());
+            },
+        };
+
+        props.warn_unused(&format!("emitter '{}'", ty));
+        self.lights.push(light);
+
+        Ok(())
+    }
+
+    fn finish(self, builder: &mut SceneBuilder) {
+        // a camera transform that mirrors the world would flip the image, so the world is mirrored
+        // along x as well to turn the camera back.
+        let mirror = match self.camera {
+            Some((m, _, _)) if m.fixed_slice::<3, 3>(0, 0).determinant() < 0.0 => Matrix4::new_nonuniform_scaling(&Vector3::new(-1.0, 1.0, 1.0)),
+            _ => Matrix4::identity(),
+        };
+
+        if let Some((world_from_camera, vfov, aspect)) = self.camera {
+            let to_world = Affine3::from_matrix_unchecked(mirror * world_from_camera);
+            builder.camera = Camera::perspective_look_to(
+                &(to_world * Point3::origin()),
+                &(to_world * Vector3::z()),
+                &(to_world * Vector3::y()),
+                vfov,
+                aspect,
+            );
+        }
+
+        let mirror_affine = Affine3::from_matrix_unchecked(mirror);
+        builder.root.children.push(Node { children: self.nodes, transform: mirror_affine, ..Default::default() });
+
+        builder.light_sources.extend(self.lights.into_iter().map(|light| match light {
+            LightSource::Point(light) => LightSource::Point(PointLight { position: mirror_affine * light.position, ..light }),
+            LightSource::Directional(light) => LightSource::Directional(DirectionalLight { neg_direction: mirror_affine * light.neg_direction, ..light }),
+            light => light,
+        }));
+
+        builder.light_sources.extend(self.environments.iter().map(|(texture, transform)| {
+            LightSource::SkySphere(SkySphere::new(environment(texture, &(mirror * transform))))
+        }));
+    }
+}
+
+fn line(node: XmlNode) -> Location {
+    Location::Line(node.document().text_pos_at(node.range().start).row as usize)
+}
+
+fn number(text: &str) -> Result<f32> {
+    text.trim().parse().map_err(|_| Error::invalid(format!("number '{}'", text)))
+}
+
+// numbers separated by commas or spaces.
+fn numbers(text: &str) -> Result<Vec<f32>> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(number)
+        .collect()
+}
+
+// the world from camera transform of a camera at origin looking at target. mitsuba's camera looks
+// along z with x pointing to the left of the image.
+fn look_at(origin: &Vector3<f32>, target: &Vector3<f32>, up: &Vector3<f32>) -> Result<Matrix4<f32>> {
+    let dir = (target - origin).try_normalize(0.0).ok_or_else(|| Error::invalid("lookat, the target is the origin"))?;
+    let left = up.cross(&dir).try_normalize(0.0).ok_or_else(|| Error::invalid("lookat, the up vector is parallel to the view direction"))?;
+    let new_up = dir.cross(&left);
+
+    Ok(Matrix4::new_translation(origin) * Matrix3::from_columns(&[left, new_up, dir]).to_homogeneous())
+}
+
+// raw bitmaps hold data rather than colours and are not srgb decoded.
+fn load_image(path: &Path, raw: bool) -> Result<Texture<Spectrum<f32>>> {
+    Texture::from_file(path, if raw { ColorSpace::Linear } else { ColorSpace::Srgb })
+}
+
+// the materials mitsuba knows the index of refraction of.
+fn named_ior(name: &str) -> Option<f32> {
+    match name {
+        "vacuum" => Some(1.0),
+        "helium" => Some(1.000036),
+        "hydrogen" => Some(1.000132),
+        "air" => Some(1.000277),
+        "carbon dioxide" => Some(1.00045),
+        "water" => Some(1.333),
+        "acetone" => Some(1.36),
+        "ethanol" => Some(1.361),
+        "carbon tetrachloride" => Some(1.461),
+        "glycerol" => Some(1.4729),
+        "benzene" => Some(1.501),
+        "silicone oil" => Some(1.52045),
+        "bromine" => Some(1.661),
+        "water ice" => Some(1.31),
+        "fused quartz" => Some(1.458),
+        "pyrex" => Some(1.47),
+        "acrylic glass" => Some(1.49),
+        "polypropylene" => Some(1.49),
+        "bk7" => Some(1.5046),
+        "sodium chloride" => Some(1.544),
+        "amber" => Some(1.55),
+        "pet" => Some(1.575),
+        "diamond" => Some(2.419),
+        _ => None,
+    }
+}
+
+// the environment map as seen by our sky sphere. mitsuba's map starts at -z of its frame and turns
+// towards +x, ours starts at +x of the world and turns towards +z.
+fn environment(texture: &Texture<Spectrum<f32>>, world_from_map: &Matrix4<f32>) -> Texture<Spectrum<f32>> {
+    let map_from_world = world_from_map.fixed_slice::<3, 3>(0, 0).try_inverse().unwrap_or_else(Matrix3::identity);
+    let (width, height) = texture.size();
+
+    let mut sky = Texture::new(width, height, &Spectrum::black());
+    sky.pixels_mut().for_each(|(xy, px)| {
+        let phi = 2.0 * PI * (xy.x as f32 + 0.5) / width as f32;
+        let theta = PI * (xy.y as f32 + 0.5) / height as f32;
+        let d = (map_from_world * Vector3::new(phi.cos() * theta.sin(), theta.cos(), phi.sin() * theta.sin())).normalize();
+
+        let uv = Point2::new((d.x.atan2(-d.z) / (2.0 * PI)).rem_euclid(1.0), d.y.clamp(-1.0, 1.0).acos() / PI);
+        *px = texture.sample(&uv);
+    });
+
+    sky
+}
+
+// mitsuba's rectangle spans -1 to 1 in x and y and faces +z.
+fn rectangle() -> (Vec<Vertex>, Vec<u32>) {
+    let vertices = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter()
+        .map(|&(x, y)| Vertex {
+            position: Point3::new(x, y, 0.0),
+            normal: Vector3::z(),
+            tangent: Vector3::x(),
+            tex_coords: Point2::new((x + 1.0) / 2.0, (y + 1.0) / 2.0),
+            ..Default::default()
+        })
+        .collect();
+
+    (vertices, vec![0, 1, 2, 0, 2, 3])
+}
+
+// a unit sphere around the origin, with u going around z and v from +z to -z like mitsuba's.
+fn sphere() -> (Vec<Vertex>, Vec<u32>) {
+    const SEGMENTS: u32 = 64;
+    const RINGS: u32 = 32;
+
+    let mut vertices = Vec::new();
+    for i in 0..=RINGS {
+        for j in 0..=SEGMENTS {
+            let uv = Point2::new(j as f32 / SEGMENTS as f32, i as f32 / RINGS as f32);
+            let (phi, theta) = (2.0 * PI * uv.x, PI * uv.y);
+            let normal = Vector3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+
+            vertices.push(Vertex {
+                position: Point3::from(normal),
+                normal,
+                tangent: Vector3::new(-phi.sin(), phi.cos(), 0.0),
+                tex_coords: uv,
+                ..Default::default()
+            });
+        }
+    }
+
+    // the triangles that would collapse at the poles are left out.
+    let mut indices = Vec::new();
+    for i in 0..RINGS {
+        for j in 0..SEGMENTS {
+            let a = i * (SEGMENTS + 1) + j;
+            let (b, c, d) = (a + SEGMENTS + 1, a + SEGMENTS + 2, a + 1);
+            if i != RINGS - 1 {
+                indices.extend([a, b, c]);
+            }
+            if i != 0 {
+                indices.extend([a, c, d]);
+            }
+        }
+    }
+
+    (vertices, indices)
+}
+
+fn for_each_mesh(node: &mut Node, f: &mut impl FnMut(&mut Mesh)) {
+    node.meshes.iter_mut().for_each(&mut *f);
+    for child in &mut node.children {
+        for_each_mesh(child, f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io::Cursor};
+
+    use nalgebra::{Point2, Point3, Vector3};
+
+    use crate::{
+        accelerator::Bvh,
+        error::{Cause, Location},
+        geometry::Ray,
+        light::{LightSource, Emitter},
+        scene::{SceneBuilder, loader::{Loader, LoadOptions}},
+        spectrum::Spectrum,
+        texture::Texture,
+    };
+
+    use super::Mitsuba;
+
+    const SCENE: &str = r#"<scene version="3.0.0">
+        <default name="radius" value="0.5"/>
+        <sensor type="perspective">
+            <float name="fov" value="45"/>
+            <transform name="to_world">
+                <lookat origin="0, 0, -5" target="0, 0, 0" up="0, 1, 0"/>
+            </transform>
+            <film type="hdrfilm">
+                <integer name="width" value="200"/>
+                <integer name="height" value="100"/>
+            </film>
+        </sensor>
+        <bsdf type="twosided" id="red">
+            <bsdf type="diffuse">
+                <rgb name="reflectance" value="0.8, 0.1, 0.1"/>
+            </bsdf>
+        </bsdf>
+        <emitter type="point">
+            <point name="position" x="1" y="2" z="3"/>
+            <rgb name="intensity" value="4"/>
+        </emitter>
+        <emitter type="constant">
+            <rgb name="radiance" value="0.1, 0.2, 0.3"/>
+        </emitter>
+        <shape type="rectangle">
+            <transform name="to_world">
+                <rotate y="1" angle="180"/>
+                <translate z="1"/>
+            </transform>
+            <emitter type="area">
+                <rgb name="radiance" value="5"/>
+            </emitter>
+        </shape>
+        <shape type="sphere">
+            <point name="center" value="2, 0, 0"/>
+            <float name="radius" value="$radius"/>
+            <ref id="red"/>
+        </shape>
+        <shape type="cube"/>
+    </scene>"#;
+
+    fn load(text: &str) -> crate::error::Result<SceneBuilder> {
+        let mut builder = SceneBuilder::new();
+        Mitsuba::load_from_reader(&mut Cursor::new(text.as_bytes()), &mut builder, &LoadOptions::default())?;
+        Ok(builder)
+    }
+
+    fn point_light(builder: &SceneBuilder) -> Point3<f32> {
+        match &builder.light_sources[0] {
+            LightSource::Point(light) => light.position,
+            _ => panic!("expected a point light"),
+        }
+    }
+
+    #[test]
+    fn mitsuba_load_test() {
+        let builder = load(SCENE).unwrap();
+        assert_eq!(point_light(&builder), Point3::new(1.0, 2.0, 3.0));
+        assert!(matches!(builder.light_sources[1], LightSource::SkySphere(_)));
+
+        // the camera's x axis points to the left of the image and the field of view spans its width.
+        let center = builder.camera.get_ray(Point2::new(100, 50), (200, 100)).ray;
+        assert!((center.origin - Point3::new(0.0, 0.0, -5.0)).norm() < 1e-4);
+        assert!((center.direction - Vector3::z()).norm() < 0.02);
+        let right = builder.camera.get_ray(Point2::new(199, 50), (200, 100)).ray.direction;
+        assert!((right.x / right.z + 22.5f32.to_radians().tan()).abs() < 0.01);
+
+        assert_eq!(load(SCENE).unwrap().root.flatten().len(), 2);
+
+        let scene = builder.build::<Bvh>();
+
+        // the rectangle is turned around to face the camera, and emits from its front only.
+        let emission = |origin: Point3<f32>, direction: Vector3<f32>| {
+            let p = scene.intersect(&Ray { origin, direction }).unwrap();
+            p.emission(&(p.tangent_to_world().transpose() * -direction))
+        };
+        assert_eq!(emission(Point3::new(0.0, 0.0, -5.0), Vector3::z()), Spectrum::constant(5.0));
+        assert_eq!(emission(Point3::new(0.0, 0.0, 5.0), -Vector3::z()), Spectrum::black());
+
+        // the sphere's radius comes from the default parameter.
+        let dist = scene.intersect_dist(&Ray { origin: Point3::new(2.1, 0.1, -5.0), direction: Vector3::z() }).unwrap();
+        assert!((dist - (5.0 - 0.23f32.sqrt())).abs() < 0.01);
+
+        // a camera that mirrors the world mirrors the scene too, so the light stays on the same side of the image.
+        let mirrored = load(&SCENE.replace("up=\"0, 1, 0\"/>", "up=\"0, 1, 0\"/><scale x=\"-1\"/>")).unwrap();
+        assert_eq!(point_light(&mirrored), Point3::new(-1.0, 2.0, 3.0));
+        assert!(mirrored.camera.get_ray(Point2::new(199, 50), (200, 100)).ray.direction.x < 0.0);
+    }
+
+    #[test]
+    fn mitsuba_file_test() {
+        let dir = std::env::temp_dir().join("mitsuba_file_test");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut env = Texture::new(4, 2, &Spectrum::black());
+        env.set(Point2::new(0, 0), Spectrum::constant(1.0));
+        env.set(Point2::new(0, 1), Spectrum::constant(1.0));
+        env.save(dir.join("env.png")).unwrap();
+
+        std::fs::write(dir.join("triangle.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        std::fs::write(dir.join("bsdfs.xml"), r#"<scene>
+            <bsdf type="roughconductor" id="gold">
+                <string name="material" value="Au"/>
+                <float name="alpha" value="$alpha"/>
+            </bsdf>
+        </scene>"#).unwrap();
+        std::fs::write(dir.join("scene.xml"), r#"<scene version="3.0.0">
+            <default name="alpha" value="0.2"/>
+            <default name="mesh" value="triangle"/>
+            <include filename="bsdfs.xml"/>
+            <emitter type="envmap">
+                <string name="filename" value="env.png"/>
+                <float name="scale" value="2"/>
+            </emitter>
+            <shape type="obj">
+                <string name="filename" value="$mesh.obj"/>
+                <ref id="gold"/>
+                <transform name="to_world">
+                    <scale value="2"/>
+                </transform>
+            </shape>
+        </scene>"#).unwrap();
+
+        let builder = SceneBuilder::new().add_file::<Mitsuba, _>(dir.join("scene.xml")).unwrap();
+
+        // the first column of mitsuba's map is just right of -z, towards +x.
+        let sky = &builder.light_sources[0];
+        assert_eq!(sky.emission(&Vector3::new(1.0, 0.0, -1.0).normalize()), Spectrum::constant(2.0));
+        assert_eq!(sky.emission(&Vector3::new(-1.0, 0.0, -1.0).normalize()), Spectrum::black());
+        assert_eq!(sky.emission(&Vector3::new(-1.0, 0.0, 1.0).normalize()), Spectrum::black());
+
+        let meshes = builder.root.flatten();
+        assert_eq!(meshes.len(), 1);
+        assert!(meshes[0].vertices.iter().any(|v| v.position == Point3::new(2.0, 0.0, 0.0)));
+
+        // given parameters win over the defaults.
+        let parameters = HashMap::from([("mesh".to_string(), "missing".to_string())]);
+        let error = Mitsuba::load_with_parameters(dir.join("scene.xml"), &mut SceneBuilder::new(), &LoadOptions::default(), &parameters).err().unwrap();
+        assert_eq!(error.path, Some(dir.join("missing.obj")));
+        assert!(matches!(*error.cause, Cause::Io(_)));
+
+        std::fs::write(dir.join("bsdfs.xml"), "<scene>\n<include filename=\"scene.xml\"/>\n</scene>").unwrap();
+        let error = SceneBuilder::new().add_file::<Mitsuba, _>(dir.join("scene.xml")).err().unwrap();
+        assert_eq!(error.path, Some(dir.join("bsdfs.xml")));
+        assert_eq!(error.location, Some(Location::Line(2)));
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mitsuba_error_test() {
+        let error = load("<scene>\n<shape type=\"sphere\">\n<ref id=\"missing\"/>\n</shape>\n</scene>").err().unwrap();
+        assert_eq!(error.location, Some(Location::Line(3)));
+        assert!(matches!(*error.cause, Cause::Missing(_)));
+
+        let error = load("<scene>\n<shape type=\"sphere\">\n<float name=\"radius\" value=\"$r\"/>\n</shape>\n</scene>").err().unwrap();
+        assert_eq!(error.location, Some(Location::Line(3)));
+        assert!(matches!(*error.cause, Cause::Missing(_)));
+
+        let error = load("<scene>\n<shape type=\"sphere\">\n<float name=\"radius\" value=\"big\"/>\n</shape>\n</scene>").err().unwrap();
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+
+        let error = load("<scene>\n<shape type=\"sphere\">\n</scene>").err().unwrap();
+        assert_eq!(error.location, Some(Location::Line(3)));
+        assert!(matches!(*error.cause, Cause::Xml(_)));
+
+        let error = load("<scene>\n<frobnicate/>\n</scene>").err().unwrap();
+        assert_eq!(error.location, Some(Location::Line(2)));
+        assert!(matches!(*error.cause, Cause::Unsupported(_)));
+
+        let error = load("<world/>").err().unwrap();
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+    }
+}
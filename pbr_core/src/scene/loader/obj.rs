@@ -0,0 +1,716 @@
+use std::{collections::HashMap, fs::File, io::{BufRead, BufReader, Read, Seek}, path::{Path, PathBuf}};
+
+use nalgebra::{Point2, Point3, Vector3};
+
+use super::{Loader, LoadOptions, NormalGeneration, generate};
+use crate::{
+    error::{Result, Error, Location},
+    material::{Material, Ggx, LambertianMaterial, MetalMaterial, PlasticMaterial, ThinDielectricMaterial, NormalMappedMaterial},
+    scene::{SceneBuilder, Node, Mesh, Vertex},
+    spectrum::Spectrum,
+    texture::{Texture, SurfaceTexture, MultiplyTexture, NormalTexture, MipMap, MipFilter, ColorSpace},
+};
+
+// Wavefront OBJ with MTL material libraries. Every group becomes a node holding a mesh for each
+// material used in it.
+// Source: https://paulbourke.net/dataformats/obj/
+pub struct Obj;
+
+impl Loader for Obj {
+    fn load_from_file<P: AsRef<Path>>(path: P, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()> {
+        let load = || -> Result<Vec<Node>> {
+            let reader = BufReader::new(File::open(&path)?);
+            let base = path.as_ref().parent().unwrap_or_else(|| Path::new("."));
+            load_obj(reader, Some(base), options)
+        };
+
+        let nodes = load().map_err(|e| e.in_file(&path))?;
+        builder.root.children.extend(nodes);
+
+        Ok(())
+    }
+
+    // material libraries can not be found without a directory, so every mesh gets the default material.
+    fn load_from_reader<R: Read + Seek>(rdr: &mut R, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()> {
+        let nodes = load_obj(BufReader::new(rdr), None, options)?;
+        builder.root.children.extend(nodes);
+
+        Ok(())
+    }
+}
+
+// a face corner as indices into the position, texture coordinate and normal lists.
+#[derive(Clone, Copy)]
+struct Corner {
+    position: usize,
+    tex_coords: Option<usize>,
+    normal: Option<usize>,
+}
+
+struct Face {
+    corners: Vec<Corner>,
+    smoothing: u32,
+}
+
+// the faces of a group that share a material.
+struct Batch {
+    material: Option<String>,
+    faces: Vec<Face>,
+}
+
+struct Group {
+    name: Option<String>,
+    batches: Vec<Batch>,
+}
+
+struct ObjParser<'a> {
+    base: Option<&'a Path>,
+    positions: Vec<Point3<f32>>,
+    tex_coords: Vec<Point2<f32>>,
+    normals: Vec<Vector3<f32>>,
+    groups: Vec<Group>,
+    material: Option<String>,
+    smoothing: u32,
+    library: MaterialLibrary,
+}
+
+fn load_obj(reader: impl BufRead, base: Option<&Path>, options: &LoadOptions) -> Result<Vec<Node>> {
+    let mut parser = ObjParser {
+        base,
+        positions: Vec::new(),
+        tex_coords: Vec::new(),
+        normals: Vec::new(),
+        groups: vec![Group { name: None, batches: Vec::new() }],
+        material: None,
+        smoothing: 0,
+        library: MaterialLibrary::default(),
+    };
+
+    for_each_line(reader, |line| parser.parse_line(line))?;
+    parser.make_nodes(options)
+}
+
+// strips comments and hands every non empty line to f, errors are tagged with the line number.
+fn for_each_line(mut reader: impl BufRead, mut f: impl FnMut(&str) -> Result<()>) -> Result<()> {
+    let mut bytes = Vec::new();
+    let mut number = 0;
+
+    while reader.read_until(b'\n', &mut bytes)? > 0 {
+        number += 1;
+
+        // names in older files are often not utf-8.
+        let line = String::from_utf8_lossy(&bytes);
+        let line = line.split('#').next().unwrap_or_default().trim();
+
+        if !line.is_empty() {
+            f(line).map_err(|e| e.at(Location::Line(number)))?;
+        }
+        bytes.clear();
+    }
+
+    Ok(())
+}
+
+// the keyword of a statement and everything after it, which may be a name with spaces.
+fn split_statement(line: &str) -> (&str, &str) {
+    match line.split_once(char::is_whitespace) {
+        Some((keyword, rest)) => (keyword, rest.trim()),
+        None => (line, ""),
+    }
+}
+
+impl ObjParser<'_> {
+    fn parse_line(&mut self, line: &str) -> Result<()> {
+        let (keyword, rest) = split_statement(line);
+        let mut tokens = rest.split_whitespace();
+
+        match keyword {
+            "v" => {
+                let [x, y, z] = parse_floats(&mut tokens, 3)?;
+                self.positions.push(Point3::new(x, y, z));
+            },
+            "vt" => {
+                // obj puts the origin at the bottom left of an image, textures here at the top left.
+                let [u, v] = parse_floats(&mut tokens, 1)?;
+                self.tex_coords.push(Point2::new(u, 1.0 - v));
+            },
+            "vn" => {
+                let [x, y, z] = parse_floats(&mut tokens, 3)?;
+                self.normals.push(Vector3::new(x, y, z));
+            },
+            "f" => {
+                let corners = tokens.map(|token| self.parse_corner(token)).collect::<Result<Vec<_>>>()?;
+                if corners.len() < 3 {
+                    return Err(Error::invalid(format!("face with {} vertices", corners.len())));
+                }
+
+                let face = Face { corners, smoothing: self.smoothing };
+                self.current_batch().faces.push(face);
+            },
+            "g" | "o" => {
+                let name = (!rest.is_empty()).then(|| rest.to_string());
+                self.groups.push(Group { name, batches: Vec::new() });
+            },
+            "s" => {
+                self.smoothing = match rest {
+                    "" | "off" => 0,
+                    group => group.parse().map_err(|_| Error::invalid(format!("smoothing group '{}'", group)))?,
+                };
+            },
+            "usemtl" => self.material = Some(rest.to_string()),
+            "mtllib" => match self.base {
+                Some(base) => {
+                    for name in tokens {
+                        self.library.load(&base.join(name))?;
+                    }
+                },
+                None => log::warn!("ignoring material library '{}' without a base directory", rest),
+            },
+            // lines, points, curves and surfaces have no area to render.
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    // corners are written as v, v/vt, v//vn or v/vt/vn.
+    fn parse_corner(&self, token: &str) -> Result<Corner> {
+        let mut parts = token.split('/');
+
+        let position = resolve_index(parts.next().unwrap_or_default(), self.positions.len(), "position")?;
+        let tex_coords = match parts.next() {
+            Some("") | None => None,
+            Some(index) => Some(resolve_index(index, self.tex_coords.len(), "texture coordinate")?),
+        };
+        let normal = match parts.next() {
+            Some("") | None => None,
+            Some(index) => Some(resolve_index(index, self.normals.len(), "normal")?),
+        };
+
+        Ok(Corner { position, tex_coords, normal })
+    }
+
+    fn current_batch(&mut self) -> &mut Batch {
+        let material = &self.material;
+        let batches = &mut self.groups.last_mut().expect("there is always a group").batches;
+
+        let index = match batches.iter().position(|batch| batch.material == *material) {
+            Some(index) => index,
+            None => {
+                batches.push(Batch { material: material.clone(), faces: Vec::new() });
+                batches.len() - 1
+            },
+        };
+
+        &mut batches[index]
+    }
+
+    fn make_nodes(mut self, options: &LoadOptions) -> Result<Vec<Node>> {
+        let groups = std::mem::take(&mut self.groups);
+
+        groups.into_iter()
+            .enumerate()
+            .filter(|(_, group)| !group.batches.is_empty())
+            .map(|(index, group)| {
+                let location = Location::Mesh(Location::name(group.name.as_deref(), index));
+
+                let meshes = group.batches.iter()
+                    .map(|batch| self.make_mesh(batch, options, &location))
+                    .collect::<Result<_>>()
+                    .map_err(|e| e.at(location))?;
+
+                Ok(Node { meshes, ..Default::default() })
+            })
+            .collect()
+    }
+
+    fn make_mesh(&mut self, batch: &Batch, options: &LoadOptions, location: &Location) -> Result<Mesh> {
+        let (mut vertices, mut indices) = self.make_vertices(&batch.faces, options);
+
+        if batch.faces.iter().flat_map(|face| &face.corners).all(|corner| corner.tex_coords.is_none()) {
+            log::warn!("{} has no texture coordinates, projecting them onto its bounding box", location);
+            generate::planar_tex_coords(&mut vertices);
+        }
+
+        // obj has no tangents, they are always generated.
+        generate::mikktspace_tangents(&mut vertices, &mut indices);
+
+        let material = match &batch.material {
+            Some(name) => self.library.make_material(name, options)
+                .map_err(|e| e.at(Location::Material(format!("'{}'", name))))?,
+            None => options.default_material(),
+        };
+
+        Ok(Mesh { vertices, indices, material: material.into() })
+    }
+
+    // vertices are shared between faces when all their attributes are. Faces without normals get
+    // the normal of their polygon, or when in a smoothing group the angle weighted average of the
+    // normals of the faces in that group around the vertex.
+    fn make_vertices(&self, faces: &[Face], options: &LoadOptions) -> (Vec<Vertex>, Vec<u32>) {
+        #[derive(Clone, Copy, PartialEq, Eq, Hash)]
+        enum NormalSource {
+            Given(usize),
+            Smooth(u32),
+            Flat(usize),
+        }
+
+        let mut lookup = HashMap::new();
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut sums: HashMap<(usize, u32), Vector3<f32>> = HashMap::new();
+
+        for (face_index, face) in faces.iter().enumerate() {
+            let positions: Vec<_> = face.corners.iter().map(|corner| self.positions[corner.position]).collect();
+            let face_normal = generate::polygon_normal(&positions).try_normalize(0.0).unwrap_or_else(Vector3::z);
+
+            // smoothing group 0 means no smoothing, unless smooth normals are asked for everywhere.
+            let sources: Vec<_> = face.corners.iter()
+                .map(|corner| match (corner.normal, face.smoothing, options.normals) {
+                    (Some(normal), _, _) => NormalSource::Given(normal),
+                    (None, 0, NormalGeneration::Flat) => NormalSource::Flat(face_index),
+                    (None, group, _) => NormalSource::Smooth(group),
+                })
+                .collect();
+
+            let corner_indices: Vec<u32> = face.corners.iter()
+                .zip(&sources)
+                .map(|(corner, &source)| *lookup.entry((corner.position, corner.tex_coords, source)).or_insert_with(|| {
+                    vertices.push(Vertex {
+                        position: self.positions[corner.position],
+                        normal: match source {
+                            NormalSource::Given(normal) => self.normals[normal],
+                            _ => face_normal,
+                        },
+                        tex_coords: corner.tex_coords.map_or_else(Point2::origin, |i| self.tex_coords[i]),
+                        ..Default::default()
+                    });
+                    vertices.len() as u32 - 1
+                }))
+                .collect();
+
+            for triangle in generate::triangulate(&positions) {
+                indices.extend(triangle.map(|k| corner_indices[k]));
+
+                let p = triangle.map(|k| positions[k]);
+                let normal = match generate::face_normal(&p) {
+                    Some(normal) => normal,
+                    None => continue,
+                };
+
+                for (k, &corner) in triangle.iter().enumerate() {
+                    if let NormalSource::Smooth(group) = sources[corner] {
+                        let angle = (p[(k + 1) % 3] - p[k]).angle(&(p[(k + 2) % 3] - p[k]));
+                        let key = (face.corners[corner].position, group);
+                        *sums.entry(key).or_insert_with(Vector3::zeros) += normal * angle;
+                    }
+                }
+            }
+        }
+
+        for ((position, _, source), index) in lookup {
+            if let NormalSource::Smooth(group) = source {
+                let vertex = &mut vertices[index as usize];
+                if let Some(normal) = sums.get(&(position, group)).and_then(|sum| sum.try_normalize(0.0)) {
+                    vertex.normal = normal;
+                }
+            }
+        }
+
+        (vertices, indices)
+    }
+}
+
+// indices start at 1, negative ones count back from the last element read so far.
+fn resolve_index(token: &str, count: usize, what: &str) -> Result<usize> {
+    let index: i64 = token.parse().map_err(|_| Error::invalid(format!("{} index '{}'", what, token)))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(Error::invalid(format!("{} index {} for {} values", what, index, count)));
+    }
+
+    Ok(resolved as usize)
+}
+
+// reads up to N numbers of which the first `required` have to be present, the rest default to zero.
+fn parse_floats<'a, const N: usize>(tokens: &mut impl Iterator<Item = &'a str>, required: usize) -> Result<[f32; N]> {
+    let mut values = [0.0; N];
+    let mut count = 0;
+
+    for (value, token) in values.iter_mut().zip(tokens) {
+        *value = token.parse().map_err(|_| Error::invalid(format!("number '{}'", token)))?;
+        count += 1;
+    }
+
+    if count < required {
+        return Err(Error::missing(format!("numbers, expected {} but found {}", required, count)));
+    }
+
+    Ok(values)
+}
+
+// a material as written in an mtl file.
+// Source: https://paulbourke.net/dataformats/mtl/
+#[derive(Clone)]
+struct MtlMaterial {
+    diffuse: Spectrum<f32>,
+    specular: Spectrum<f32>,
+    shininess: f32,
+    ior: f32,
+    dissolve: f32,
+    transmission: Spectrum<f32>,
+    illum: u32,
+    diffuse_map: Option<PathBuf>,
+    bump_map: Option<(PathBuf, f32)>,
+    normal_map: Option<PathBuf>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            diffuse: Spectrum::constant(0.8),
+            specular: Spectrum::black(),
+            shininess: 0.0,
+            ior: 1.5,
+            dissolve: 1.0,
+            transmission: Spectrum::constant(1.0),
+            illum: 2,
+            diffuse_map: None,
+            bump_map: None,
+            normal_map: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MaterialLibrary {
+    materials: HashMap<String, MtlMaterial>,
+    // decoded images by path and whether they hold colours.
+    textures: HashMap<(PathBuf, bool), Texture<Spectrum<f32>>>,
+}
+
+impl MaterialLibrary {
+    fn load(&mut self, path: &Path) -> Result<()> {
+        let mut load = || -> Result<()> {
+            let reader = BufReader::new(File::open(path)?);
+            let base = path.parent().unwrap_or_else(|| Path::new("."));
+            let mut current: Option<String> = None;
+
+            for_each_line(reader, |line| {
+                let (keyword, rest) = split_statement(line);
+
+                if keyword == "newmtl" {
+                    self.materials.insert(rest.to_string(), MtlMaterial::default());
+                    current = Some(rest.to_string());
+                    return Ok(());
+                }
+
+                let material = match &current {
+                    Some(name) => self.materials.get_mut(name).expect("current material was inserted"),
+                    None => return Err(Error::missing(format!("newmtl before '{}'", keyword))),
+                };
+                parse_mtl_statement(material, keyword, rest, base)
+            })
+        };
+
+        load().map_err(|e| e.in_file(path))
+    }
+
+    fn make_material(&mut self, name: &str, options: &LoadOptions) -> Result<Box<dyn Material>> {
+        let definition = match self.materials.get(name) {
+            Some(definition) => definition.clone(),
+            None => {
+                log::warn!("material '{}' is not defined, using the default material", name);
+                return Ok(options.default_material());
+            },
+        };
+
+        // illumination models 4, 6, 7 and 9 are glass, as are transparent materials.
+        let transparent = definition.dissolve < 1.0 || matches!(definition.illum, 4 | 6 | 7 | 9);
+
+        // the phong exponent maps to a microfacet roughness with a similar highlight.
+        // Source: https://www.cs.cornell.edu/~srm/publications/EGSR07-btdf.pdf
+        let roughness = (2.0 / (definition.shininess.max(0.0) + 2.0)).powf(0.25);
+
+        let mut diffuse = || -> Result<Box<dyn SurfaceTexture<Spectrum<f32>>>> {
+            match &definition.diffuse_map {
+                Some(path) => Ok(Box::new(MultiplyTexture::new(definition.diffuse, self.texture(path, ColorSpace::Srgb)?))),
+                None => Ok(Box::new(definition.diffuse)),
+            }
+        };
+
+        let material: Box<dyn Material> = if transparent {
+            Box::new(ThinDielectricMaterial::tinted(definition.ior.max(1.0), definition.transmission))
+        } else if definition.illum < 2 || definition.specular == Spectrum::black() {
+            Box::new(LambertianMaterial::new(diffuse()?))
+        } else if definition.diffuse == Spectrum::black() && definition.diffuse_map.is_none() {
+            Box::new(MetalMaterial::<Ggx>::new(roughness, definition.specular))
+        } else {
+            Box::new(PlasticMaterial::<Ggx>::new(roughness, definition.ior.max(1.0), diffuse()?))
+        };
+
+        if let Some(path) = &definition.normal_map {
+            let normals = self.texture(path, ColorSpace::Linear)?;
+            return Ok(Box::new(NormalMappedMaterial::new(material, NormalTexture::new(normals, 1.0))));
+        }
+
+        if let Some((path, scale)) = &definition.bump_map {
+            let heights = self.image(path, ColorSpace::Linear)?;
+            let normals = MipMap::new(heights.height_to_normal(*scale), MipFilter::Trilinear);
+            return Ok(Box::new(NormalMappedMaterial::new(material, NormalTexture::new(normals, 1.0))));
+        }
+
+        Ok(material)
+    }
+
+    fn image(&mut self, path: &Path, color_space: ColorSpace) -> Result<Texture<Spectrum<f32>>> {
+        let key = (path.to_path_buf(), color_space == ColorSpace::Srgb);
+
+        if !self.textures.contains_key(&key) {
+            let texture = Texture::from_image_file(path, color_space)
+                .map_err(|e| e.at(Location::Texture(format!("'{}'", path.display()))))?;
+            self.textures.insert(key.clone(), texture);
+        }
+
+        Ok(self.textures[&key].clone())
+    }
+
+    fn texture(&mut self, path: &Path, color_space: ColorSpace) -> Result<MipMap<Spectrum<f32>>> {
+        Ok(MipMap::new(self.image(path, color_space)?, MipFilter::Trilinear))
+    }
+}
+
+fn parse_mtl_statement(material: &mut MtlMaterial, keyword: &str, rest: &str, base: &Path) -> Result<()> {
+    // options like -halo come before the values.
+    let mut tokens = rest.split_whitespace().filter(|token| !token.starts_with('-') || token.parse::<f32>().is_ok());
+
+    match keyword {
+        "Kd" => material.diffuse = parse_color(&mut tokens)?,
+        "Ks" => material.specular = parse_color(&mut tokens)?,
+        "Tf" => material.transmission = parse_color(&mut tokens)?,
+        "Ns" => material.shininess = parse_floats::<1>(&mut tokens, 1)?[0],
+        "Ni" => material.ior = parse_floats::<1>(&mut tokens, 1)?[0],
+        "d" => material.dissolve = parse_floats::<1>(&mut tokens, 1)?[0],
+        "Tr" => material.dissolve = 1.0 - parse_floats::<1>(&mut tokens, 1)?[0],
+        "illum" => {
+            material.illum = rest.parse().map_err(|_| Error::invalid(format!("illumination model '{}'", rest)))?;
+        },
+        "map_Kd" => material.diffuse_map = Some(parse_map(rest, base)?.0),
+        "bump" | "map_Bump" | "map_bump" => {
+            let (path, options) = parse_map(rest, base)?;
+            let scale = match options.get("-bm") {
+                Some(values) => parse_floats::<1>(&mut values.iter().copied(), 1)?[0],
+                None => 1.0,
+            };
+            material.bump_map = Some((path, scale));
+        },
+        "norm" | "map_Kn" => material.normal_map = Some(parse_map(rest, base)?.0),
+        _ => (),
+    }
+
+    Ok(())
+}
+
+// a single value is used for all three channels.
+fn parse_color<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Result<Spectrum<f32>> {
+    let values = tokens.take(3)
+        .map(|token| token.parse().map_err(|_| Error::invalid(format!("number '{}'", token))))
+        .collect::<Result<Vec<f32>>>()?;
+
+    match values[..] {
+        [x] => Ok(Spectrum::constant(x)),
+        [r, g, b] => Ok(Spectrum::new(r, g, b)),
+        _ => Err(Error::invalid(format!("colour with {} values", values.len()))),
+    }
+}
+
+// splits the options of a texture statement from its file name, which can contain spaces.
+fn parse_map<'a>(rest: &'a str, base: &Path) -> Result<(PathBuf, HashMap<&'a str, Vec<&'a str>>)> {
+    let mut tokens: Vec<&str> = rest.split_whitespace().collect();
+    let mut options = HashMap::new();
+
+    while let Some(&option) = tokens.first().filter(|token| token.starts_with('-')) {
+        // -o, -s and -t take one to three numbers, the other options a fixed count.
+        let count = match option {
+            "-mm" => 2,
+            "-o" | "-s" | "-t" => 1 + tokens[1..].iter().skip(1).take(2).take_while(|token| token.parse::<f32>().is_ok()).count(),
+            _ => 1,
+        };
+
+        let count = count.min(tokens.len() - 1);
+        options.insert(option, tokens[1..=count].to_vec());
+        tokens.drain(..=count);
+    }
+
+    if tokens.is_empty() {
+        return Err(Error::missing("texture file name"));
+    }
+
+    // files written on windows use backslashes.
+    let name = tokens.join(" ").replace('\\', "/");
+    Ok((base.join(name), options))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, f32::consts::FRAC_1_PI};
+
+    use nalgebra::{Point2, Vector3};
+
+    use crate::{error::{Cause, Location}, geometry::SurfacePoint, scene::{SceneBuilder, Vertex, loader::{Loader, LoadOptions}}};
+
+    use super::{Obj, parse_map};
+
+    fn load(obj: &str) -> crate::error::Result<SceneBuilder> {
+        let mut builder = SceneBuilder::new();
+        Obj::load_from_reader(&mut Cursor::new(obj.as_bytes()), &mut builder, &LoadOptions::default())?;
+        Ok(builder)
+    }
+
+    #[test]
+    fn obj_load_test() {
+        let builder = load("
+            # a quad with normals and one without in its own group
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 1
+            vn 0 0 1
+            o quad
+            f 1/1/1 2/1/1 3/2/1 4/1/1
+            g triangle
+            f -4 -3 -2
+        ").unwrap();
+
+        let meshes = builder.root.flatten();
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].indices.len(), 6);
+        assert_eq!(meshes[1].indices.len(), 3);
+
+        // v is flipped as textures start at the top.
+        assert!(meshes[0].vertices.iter().any(|v| v.tex_coords == nalgebra::Point2::new(1.0, 0.0)));
+        assert!(meshes[1].vertices.iter().all(|v| v.normal == Vector3::z()));
+    }
+
+    #[test]
+    fn smoothing_group_test() {
+        // two triangles folded along the x axis by 90 degrees.
+        let folded = |smoothing: &str| format!("
+            v 0 0 0
+            v 2 0 0
+            v 0 1 0
+            v 0 0 -1
+            s {}
+            f 1 2 3
+            f 1 2 4
+        ", smoothing);
+
+        // vertices on the fold are only shared within a smoothing group, tangents may still split them.
+        let on_fold = |smoothing: &str| {
+            let meshes = load(&folded(smoothing)).unwrap().root.flatten();
+            meshes[0].vertices.iter()
+                .filter(|v| v.position.y == 0.0 && v.position.z == 0.0)
+                .map(|v| v.normal)
+                .collect::<Vec<_>>()
+        };
+
+        let flat = on_fold("off");
+        assert!(flat.contains(&Vector3::z()) && flat.contains(&Vector3::y()));
+
+        let diagonal = Vector3::new(0.0, 1.0, 1.0).normalize();
+        assert!(on_fold("1").iter().all(|n| (n - diagonal).norm() < 1e-6));
+    }
+
+    #[test]
+    fn obj_error_test() {
+        let error = load("v 0 0 0\nv 1 0 0\n\nf 1 2 3\n").err().unwrap();
+        assert_eq!(error.location, Some(Location::Line(4)));
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+
+        let error = load("v 0 0\n").err().unwrap();
+        assert!(matches!(*error.cause, Cause::Missing(_)));
+    }
+
+    #[test]
+    fn mtl_test() {
+        let dir = std::env::temp_dir().join("pbr_core_obj_test");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        image::RgbImage::from_fn(4, 4, |x, _| image::Rgb([x as u8 * 60; 3])).save(dir.join("bump map.png")).unwrap();
+        std::fs::write(dir.join("scene.mtl"), "
+            newmtl red
+            Kd 1 0 0
+            newmtl mirror
+            Kd 0 0 0
+            Ks 0.9
+            Ns 1000
+            illum 3
+            newmtl glass
+            d 0.5
+            Ni 1.45
+            newmtl bumpy
+            Kd 0.5
+            Ks 0.04
+            Ns 100
+            map_Bump -bm 0.5 bump map.png
+        ").unwrap();
+
+        let faces: String = ["red", "mirror", "glass", "bumpy", "undefined"].iter()
+            .map(|name| format!("usemtl {}\nf 1 2 3\n", name))
+            .collect();
+        let obj = format!("mtllib scene.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n{}", faces);
+        std::fs::write(dir.join("scene.obj"), obj).unwrap();
+
+        let builder = SceneBuilder::new().add_file::<Obj, _>(dir.join("scene.obj")).unwrap();
+        let meshes = builder.root.flatten();
+        assert_eq!(meshes.len(), 5);
+
+        let vertex = Vertex { tex_coords: Point2::new(0.5, 0.5), ..meshes[0].vertices[0] };
+        let point = |i: usize| SurfacePoint::from_vertex(&vertex, &*meshes[i].material);
+        let brdf = |i: usize, wi: Vector3<f32>, wo: Vector3<f32>| meshes[i].material.brdf(&point(i), &wi.normalize(), &wo.normalize());
+
+        // red is lambertian, the same in every direction.
+        let red = brdf(0, Vector3::new(0.6, 0.0, 0.8), Vector3::new(0.0, 0.3, 1.0));
+        assert!((red.r - FRAC_1_PI).abs() < 1e-5 && red.g == 0.0 && red.b == 0.0, "red: {:?}", red);
+        assert_eq!(brdf(0, Vector3::z(), Vector3::z()), red);
+
+        // the mirror is a grey metal, glossy along the reflection and dark away from it.
+        let highlight = brdf(1, Vector3::z(), Vector3::z());
+        assert!(highlight.r > 1.0 && highlight.r == highlight.g && highlight.g == highlight.b, "mirror: {:?}", highlight);
+        assert!(brdf(1, Vector3::new(0.9, 0.0, 0.4), Vector3::new(0.9, 0.0, 0.4)).r < 0.01);
+        assert!(!meshes[1].material.is_delta(&point(1)));
+
+        // glass is a thin dielectric, which only scatters in delta directions.
+        assert!(meshes[2].material.is_delta(&point(2)));
+
+        // the bump map slopes along u and tilts the normal, so retroreflection is no longer the
+        // same on both sides of it.
+        let (left, right) = (Vector3::new(-0.3, 0.0, 1.0), Vector3::new(0.3, 0.0, 1.0));
+        let (left, right) = (brdf(3, left, left), brdf(3, right, right));
+        assert!((left.r - right.r).abs() > 0.1 * left.r.max(right.r), "bumpy: {:?} {:?}", left, right);
+        assert!(!meshes[3].material.is_delta(&point(3)));
+
+        // a missing texture is reported with its own path.
+        std::fs::write(dir.join("broken.mtl"), "newmtl broken\nmap_Kd missing.png\n").unwrap();
+        std::fs::write(dir.join("broken.obj"), "mtllib broken.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl broken\nf 1 2 3\n").unwrap();
+        let error = SceneBuilder::new().add_file::<Obj, _>(dir.join("broken.obj")).err().unwrap();
+        assert_eq!(error.path, Some(dir.join("missing.png")));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn texture_options_test() {
+        let base = std::path::Path::new("textures");
+        let (path, options) = parse_map("-bm 0.5 -o 0.1 0.2 -clamp on my texture.png", base).unwrap();
+
+        assert_eq!(path, base.join("my texture.png"));
+        assert_eq!(options["-bm"], vec!["0.5"]);
+        assert_eq!(options["-o"], vec!["0.1", "0.2"]);
+        assert_eq!(options["-clamp"], vec!["on"]);
+        assert!(parse_map("-bm 0.5", base).is_err());
+    }
+}
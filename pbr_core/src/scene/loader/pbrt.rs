@@ -0,0 +1,1032 @@
+use std::{collections::HashMap, fs, io::{Read, Seek}, path::{Path, PathBuf}, sync::Arc};
+
+use nalgebra::{Affine3, Matrix3, Matrix4, Point2, Point3, Rotation3, Vector3, Unit};
+
+use super::{Loader, LoadOptions, MaterialFactory, NormalGeneration, Ply, generate, tracked::{Named, Tracked}};
+use crate::{
+    error::{Result, Error, Location},
+    camera::Camera,
+    light::{LightSource, PointLight, DirectionalLight, SkySphere},
+    material::{Material, Ggx, Metal, LambertianMaterial, OrenNayarMaterial, MetalMaterial, PlasticMaterial, PrincipledMaterial, ThinDielectricMaterial, EmissiveMaterial},
+    scene::{SceneBuilder, Node, Mesh, Vertex},
+    spectrum::Spectrum,
+    texture::{Texture, FactoredTexture, ColorSpace},
+};
+
+// A practical subset of pbrt-v3 and pbrt-v4 scene files: the perspective camera and film, transforms
+// and attribute blocks, triangle, bilinear and ply meshes, the common materials, point, distant and
+// infinite lights and diffuse area lights. Everything else is skipped with a warning.
+// pbrt's world is left handed, it is mirrored along x to bring it into ours so renders match the
+// reference images. The film only sets the aspect ratio of the camera.
+// Source: https://pbrt.org/fileformat-v4
+pub struct Pbrt;
+
+impl Loader for Pbrt {
+    fn load_from_file<P: AsRef<Path>>(path: P, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()> {
+        let dir = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+        let mut importer = Importer::new(dir, options);
+
+        importer.parse_file(path.as_ref())?;
+        importer.finish(builder);
+
+        Ok(())
+    }
+
+    fn load_from_reader<R: Read + Seek>(rdr: &mut R, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()> {
+        let mut text = String::new();
+        rdr.read_to_string(&mut text)?;
+
+        // included files are looked up relative to the working directory.
+        let mut importer = Importer::new(Path::new(""), options);
+        importer.parse(&text, "pbrt data")?;
+        importer.finish(builder);
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Str(String),
+    Word(String),
+    Open,
+    Close,
+}
+
+// splits the text into tokens with the line they are on.
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => (),
+            '#' => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            },
+            '[' => tokens.push((Token::Open, line)),
+            ']' => tokens.push((Token::Close, line)),
+            '"' => {
+                let start = line;
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => string.push('\n'),
+                            Some('t') => string.push('\t'),
+                            Some(c) => string.push(c),
+                            None => return Err(Error::invalid("string, it is not terminated").at(Location::Line(start))),
+                        },
+                        Some('\n') => return Err(Error::invalid("string, it is not terminated").at(Location::Line(start))),
+                        Some(c) => string.push(c),
+                        None => return Err(Error::invalid("string, it is not terminated").at(Location::Line(start))),
+                    }
+                }
+                tokens.push((Token::Str(string), start));
+            },
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && !matches!(c, '[' | ']' | '"' | '#')) {
+                    word.push(c);
+                }
+                tokens.push((Token::Word(word), line));
+            },
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Tokens {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+}
+
+impl Tokens {
+    fn next(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(token, _)| token)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        match self.next() {
+            Some((Token::Str(string), _)) => Ok(string),
+            Some((token, line)) => Err(Error::invalid(format!("{:?}, expected a string", token)).at(Location::Line(line))),
+            None => Err(Error::missing("string at the end of the file")),
+        }
+    }
+
+    fn number(&mut self) -> Result<f32> {
+        match self.next() {
+            Some((Token::Word(word), line)) => word.parse().map_err(|_| Error::invalid(format!("number '{}'", word)).at(Location::Line(line))),
+            Some((token, line)) => Err(Error::invalid(format!("{:?}, expected a number", token)).at(Location::Line(line))),
+            None => Err(Error::missing("number at the end of the file")),
+        }
+    }
+
+    // n numbers, either bare or in brackets.
+    fn numbers(&mut self, n: usize) -> Result<Vec<f32>> {
+        let bracketed = self.peek() == Some(&Token::Open);
+        if bracketed {
+            self.next();
+        }
+
+        let numbers = (0..n).map(|_| self.number()).collect::<Result<Vec<_>>>()?;
+
+        if bracketed {
+            match self.next() {
+                Some((Token::Close, _)) => (),
+                _ => return Err(Error::invalid(format!("arguments, expected {} numbers in brackets", n))),
+            }
+        }
+
+        Ok(numbers)
+    }
+
+    // the "type name" value pairs following a directive.
+    fn params(&mut self) -> Result<Params> {
+        let mut params = Vec::new();
+
+        while let Some(Token::Str(_)) = self.peek() {
+            let declaration = self.string()?;
+            let (ty, name) = match declaration.split_whitespace().collect::<Vec<_>>()[..] {
+                [ty, name] => (ty.to_string(), name.to_string()),
+                _ => return Err(Error::invalid(format!("parameter declaration '{}'", declaration))),
+            };
+
+            let mut values = Vec::new();
+            match self.next() {
+                Some((Token::Open, _)) => loop {
+                    match self.next() {
+                        Some((Token::Close, _)) => break,
+                        Some((token, _)) => values.push(token),
+                        None => return Err(Error::invalid(format!("parameter '{}', its values are not closed", name))),
+                    }
+                },
+                Some((token, _)) => values.push(token),
+                None => return Err(Error::missing(format!("value of parameter '{}'", name))),
+            }
+
+            let mut param = Param { ty, name, numbers: Vec::new(), strings: Vec::new(), bools: Vec::new() };
+            for value in values {
+                match value {
+                    Token::Word(word) if word == "true" || word == "false" => param.bools.push(word == "true"),
+                    Token::Str(word) if param.ty == "bool" => param.bools.push(word == "true"),
+                    Token::Word(word) => param.numbers.push(word.parse().map_err(|_| Error::invalid(format!("number '{}' in parameter '{}'", word, param.name)))?),
+                    Token::Str(string) => param.strings.push(string),
+                    _ => return Err(Error::invalid(format!("parameter '{}', brackets can not be nested", param.name))),
+                }
+            }
+            params.push(param);
+        }
+
+        Ok(Params(Tracked::new(params)))
+    }
+}
+
+struct Param {
+    ty: String,
+    name: String,
+    numbers: Vec<f32>,
+    strings: Vec<String>,
+    bools: Vec<bool>,
+}
+
+impl Named for Param {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+enum SpectrumParam<'a> {
+    Rgb(Spectrum<f32>),
+    Named(&'a str),
+    Texture(&'a str),
+}
+
+struct Params(Tracked<Param>);
+
+impl Params {
+    fn find(&self, names: &[&str]) -> Option<&Param> {
+        self.0.find(names)
+    }
+
+    fn floats(&self, names: &[&str]) -> Option<&[f32]> {
+        self.find(names).map(|param| param.numbers.as_slice())
+    }
+
+    fn float(&self, names: &[&str], default: f32) -> Result<f32> {
+        match self.find(names) {
+            Some(param) => param.numbers.first().copied().ok_or_else(|| Error::invalid(format!("parameter '{}', expected a number", param.name))),
+            None => Ok(default),
+        }
+    }
+
+    fn string(&self, names: &[&str]) -> Option<&str> {
+        self.find(names).and_then(|param| param.strings.first()).map(|s| s.as_str())
+    }
+
+    fn bool(&self, name: &str, default: bool) -> bool {
+        self.find(&[name]).and_then(|param| param.bools.first().copied()).unwrap_or(default)
+    }
+
+    fn spectrum(&self, names: &[&str]) -> Result<Option<SpectrumParam<'_>>> {
+        let param = match self.find(names) {
+            Some(param) => param,
+            None => return Ok(None),
+        };
+
+        let value = match (param.ty.as_str(), &param.numbers[..], param.strings.first()) {
+            ("rgb" | "color", &[r, g, b], _) => SpectrumParam::Rgb(Spectrum::new(r, g, b)),
+            ("float", &[x], _) => SpectrumParam::Rgb(Spectrum::constant(x)),
+            ("texture", _, Some(name)) => SpectrumParam::Texture(name),
+            ("spectrum", _, Some(name)) => SpectrumParam::Named(name),
+            // wavelength and value pairs, which are averaged to grey.
+            ("spectrum", numbers, None) if !numbers.is_empty() && numbers.len() % 2 == 0 => {
+                let values: Vec<_> = numbers.iter().skip(1).step_by(2).collect();
+                SpectrumParam::Rgb(Spectrum::constant(values.iter().copied().sum::<f32>() / values.len() as f32))
+            },
+            ("blackbody", &[_, ..], _) => {
+                log::warn!("blackbody emission is not supported, '{}' is white instead", param.name);
+                SpectrumParam::Rgb(Spectrum::constant(1.0))
+            },
+            _ => return Err(Error::invalid(format!("value of {} parameter '{}'", param.ty, param.name))),
+        };
+
+        Ok(Some(value))
+    }
+
+    // lights scale their emission by a number in pbrt-v4 and by a colour in pbrt-v3.
+    fn scale(&self) -> Spectrum<f32> {
+        match self.floats(&["scale"]) {
+            Some(&[r, g, b]) => Spectrum::new(r, g, b),
+            Some(&[x]) => Spectrum::constant(x),
+            _ => Spectrum::constant(1.0),
+        }
+    }
+
+    fn warn_unused(&self, directive: &str) {
+        for param in self.0.unused() {
+            log::warn!("{} parameter '{} {}' is not supported and is ignored", directive, param.ty, param.name);
+        }
+    }
+}
+
+#[derive(Clone)]
+struct State {
+    transform: Matrix4<f32>,
+    material: MaterialFactory,
+    // the radiance and two sidedness of the diffuse area light shapes are made into.
+    area_light: Option<(Spectrum<f32>, bool)>,
+    reverse_orientation: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Block {
+    Attribute,
+    Transform,
+    Object,
+}
+
+struct Importer<'a> {
+    dir: PathBuf,
+    options: &'a LoadOptions,
+    // the files currently being parsed, an include of one of them would never end.
+    open_files: Vec<PathBuf>,
+    state: State,
+    stack: Vec<(Block, State)>,
+    named_materials: HashMap<String, MaterialFactory>,
+    textures: HashMap<String, Texture<Spectrum<f32>>>,
+    coordinate_systems: HashMap<String, Matrix4<f32>>,
+    // the world to camera transform and the field of view of the shorter image axis.
+    camera: Option<(Matrix4<f32>, f32)>,
+    film_size: (f32, f32),
+    nodes: Vec<Node>,
+    lights: Vec<LightSource>,
+}
+
+impl<'a> Importer<'a> {
+    fn new(dir: &Path, options: &'a LoadOptions) -> Self {
+        // pbrt's default material is a grey diffuse one.
+        let material: MaterialFactory = match &options.default_material {
+            Some(factory) => factory.clone(),
+            None => Arc::new(|| Box::new(LambertianMaterial::flat(Spectrum::constant(0.5)))),
+        };
+
+        Self {
+            dir: dir.to_path_buf(),
+            options,
+            open_files: Vec::new(),
+            state: State { transform: Matrix4::identity(), material, area_light: None, reverse_orientation: false },
+            stack: Vec::new(),
+            named_materials: HashMap::new(),
+            textures: HashMap::new(),
+            coordinate_systems: HashMap::new(),
+            camera: None,
+            film_size: (1280.0, 720.0),
+            nodes: Vec::new(),
+            lights: Vec::new(),
+        }
+    }
+
+    fn parse_file(&mut self, path: &Path) -> Result<()> {
+        let text = fs::read_to_string(path).map_err(|e| Error::from(e).in_file(path))?;
+
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if self.open_files.contains(&canonical) {
+            return Err(Error::invalid(format!("{} includes itself", path.display())));
+        }
+
+        self.open_files.push(canonical);
+        let result = self.parse(&text, &path.display().to_string()).map_err(|e| e.in_file(path));
+        self.open_files.pop();
+
+        result
+    }
+
+    fn parse(&mut self, text: &str, name: &str) -> Result<()> {
+        let mut tokens = Tokens { tokens: tokenize(text)?, index: 0 };
+
+        while let Some((token, line)) = tokens.next() {
+            let directive = match token {
+                Token::Word(word) => word,
+                token => return Err(Error::invalid(format!("{:?}, expected a directive", token)).at(Location::Line(line))),
+            };
+
+            self.directive(&directive, &mut tokens, name, line).map_err(|e| e.at(Location::Line(line)))?;
+        }
+
+        Ok(())
+    }
+
+    fn directive(&mut self, directive: &str, tokens: &mut Tokens, name: &str, line: usize) -> Result<()> {
+        let ignored = |what: &str| log::warn!("{} line {}: {} is not supported and is ignored", name, line, what);
+
+        match directive {
+            "Identity" => self.state.transform = Matrix4::identity(),
+            "Translate" => {
+                let v = tokens.numbers(3)?;
+                self.state.transform *= Matrix4::new_translation(&Vector3::new(v[0], v[1], v[2]));
+            },
+            "Scale" => {
+                let v = tokens.numbers(3)?;
+                self.state.transform *= Matrix4::new_nonuniform_scaling(&Vector3::new(v[0], v[1], v[2]));
+            },
+            "Rotate" => {
+                let v = tokens.numbers(4)?;
+                let axis = Unit::try_new(Vector3::new(v[1], v[2], v[3]), 0.0).ok_or_else(|| Error::invalid("rotation axis of zero length"))?;
+                self.state.transform *= Rotation3::from_axis_angle(&axis, v[0].to_radians()).to_homogeneous();
+            },
+            "LookAt" => {
+                let v = tokens.numbers(9)?;
+                self.state.transform *= look_at(&Point3::new(v[0], v[1], v[2]), &Point3::new(v[3], v[4], v[5]), &Vector3::new(v[6], v[7], v[8]))?;
+            },
+            // matrices are given column by column.
+            "Transform" => self.state.transform = Matrix4::from_column_slice(&tokens.numbers(16)?),
+            "ConcatTransform" => self.state.transform *= Matrix4::from_column_slice(&tokens.numbers(16)?),
+            "CoordinateSystem" => {
+                self.coordinate_systems.insert(tokens.string()?, self.state.transform);
+            },
+            "CoordSysTransform" => {
+                let system = tokens.string()?;
+                match self.coordinate_systems.get(&system) {
+                    Some(transform) => self.state.transform = *transform,
+                    None => log::warn!("{} line {}: coordinate system '{}' is not defined", name, line, system),
+                }
+            },
+            "ActiveTransform" => {
+                tokens.next();
+                ignored("motion blur");
+            },
+            "TransformTimes" => {
+                tokens.numbers(2)?;
+                ignored("motion blur");
+            },
+            "ReverseOrientation" => self.state.reverse_orientation = !self.state.reverse_orientation,
+            "Camera" => {
+                let ty = tokens.string()?;
+                let params = tokens.params()?;
+                if ty == "perspective" {
+                    self.camera = Some((self.state.transform, params.float(&["fov"], 90.0)?));
+                    params.warn_unused("Camera");
+                } else {
+                    ignored(&format!("camera '{}'", ty));
+                }
+                if let Some(world_from_camera) = self.state.transform.try_inverse() {
+                    self.coordinate_systems.insert("camera".to_string(), world_from_camera);
+                }
+            },
+            "Film" => {
+                tokens.string()?;
+                let params = tokens.params()?;
+                self.film_size = (params.float(&["xresolution"], 1280.0)?, params.float(&["yresolution"], 720.0)?);
+                params.warn_unused("Film");
+            },
+            "Sampler" | "Integrator" | "PixelFilter" | "Accelerator" | "MakeNamedMedium" | "Attribute" => {
+                let ty = tokens.string()?;
+                tokens.params()?;
+                ignored(&format!("{} '{}'", directive, ty));
+            },
+            "Option" => {
+                tokens.params()?;
+                ignored("Option");
+            },
+            "ColorSpace" => {
+                tokens.string()?;
+                ignored("ColorSpace");
+            },
+            "MediumInterface" => {
+                tokens.string()?;
+                if matches!(tokens.peek(), Some(Token::Str(s)) if !s.contains(char::is_whitespace)) {
+                    tokens.string()?;
+                }
+                ignored("participating media");
+            },
+            "WorldBegin" => {
+                self.state.transform = Matrix4::identity();
+                self.coordinate_systems.insert("world".to_string(), Matrix4::identity());
+            },
+            "WorldEnd" => (),
+            "AttributeBegin" => self.stack.push((Block::Attribute, self.state.clone())),
+            "TransformBegin" => self.stack.push((Block::Transform, self.state.clone())),
+            "ObjectBegin" => {
+                tokens.string()?;
+                ignored("object instancing, the object's shapes are skipped");
+                self.stack.push((Block::Object, self.state.clone()));
+            },
+            "AttributeEnd" | "TransformEnd" | "ObjectEnd" => {
+                let expected = match directive {
+                    "AttributeEnd" => Block::Attribute,
+                    "TransformEnd" => Block::Transform,
+                    _ => Block::Object,
+                };
+                match self.stack.pop() {
+                    Some((Block::Transform, state)) if expected == Block::Transform => self.state.transform = state.transform,
+                    Some((block, state)) if block == expected => self.state = state,
+                    _ => return Err(Error::invalid(format!("{} without a matching begin", directive))),
+                }
+            },
+            "ObjectInstance" => {
+                tokens.string()?;
+                ignored("object instancing");
+            },
+            "Include" | "Import" => {
+                let path = self.dir.join(tokens.string()?);
+                self.parse_file(&path)?;
+            },
+            "Texture" => {
+                let (texture, ty, class) = (tokens.string()?, tokens.string()?, tokens.string()?);
+                let params = tokens.params()?;
+                self.texture(texture, &ty, &class, &params)?;
+            },
+            "Material" => {
+                let ty = tokens.string()?;
+                let params = tokens.params()?;
+                if let Some(material) = self.material(&ty, &params)? {
+                    self.state.material = material;
+                }
+            },
+            "MakeNamedMaterial" => {
+                let material = tokens.string()?;
+                let params = tokens.params()?;
+                let ty = params.string(&["type"]).unwrap_or("").to_string();
+                if let Some(factory) = self.material(&ty, &params)? {
+                    self.named_materials.insert(material, factory);
+                }
+            },
+            "NamedMaterial" => {
+                let material = tokens.string()?;
+                self.state.material = self.named_materials.get(&material)
+                    .cloned()
+                    .ok_or_else(|| Error::missing(format!("material '{}'", material)))?;
+            },
+            "LightSource" => {
+                let ty = tokens.string()?;
+                let params = tokens.params()?;
+                if self.in_object() {
+                    ignored("light in an object");
+                } else {
+                    self.light(&ty, &params)?;
+                }
+            },
+            "AreaLightSource" => {
+                let ty = tokens.string()?;
+                let params = tokens.params()?;
+                if ty == "diffuse" {
+                    let radiance = color(&params, &["L"], Spectrum::constant(1.0)) * params.scale();
+                    self.state.area_light = Some((radiance, params.bool("twosided", false)));
+                    params.warn_unused("AreaLightSource");
+                } else {
+                    ignored(&format!("area light '{}'", ty));
+                }
+            },
+            "Shape" => {
+                let ty = tokens.string()?;
+                let params = tokens.params()?;
+                if self.in_object() {
+                    return Ok(());
+                }
+                match ty.as_str() {
+                    "trianglemesh" | "bilinearmesh" => self.mesh(&ty, &params, name)?,
+                    "plymesh" => self.ply_mesh(&params)?,
+                    _ => ignored(&format!("shape '{}'", ty)),
+                }
+            },
+            _ => return Err(Error::unsupported(format!("directive '{}'", directive))),
+        }
+
+        Ok(())
+    }
+
+    fn in_object(&self) -> bool {
+        self.stack.iter().any(|(block, _)| *block == Block::Object)
+    }
+
+    fn texture(&mut self, texture: String, ty: &str, class: &str, params: &Params) -> Result<()> {
+        if !matches!(ty, "spectrum" | "color") || class != "imagemap" {
+            log::warn!("{} texture '{}' of class '{}' is not supported, it is ignored", ty, texture, class);
+            return Ok(());
+        }
+
+        let filename = params.string(&["filename"]).ok_or_else(|| Error::missing("filename of the image texture"))?;
+        let image = Texture::from_image_file(self.dir.join(filename), ColorSpace::Srgb)?;
+        params.warn_unused("Texture");
+        self.textures.insert(texture, image);
+
+        Ok(())
+    }
+
+    // a colour input of a material, which may be an image texture.
+    fn color_texture(&self, params: &Params, names: &[&str], default: Spectrum<f32>) -> Result<FactoredTexture<Spectrum<f32>>> {
+        Ok(match params.spectrum(names)? {
+            Some(SpectrumParam::Texture(name)) => match self.textures.get(name) {
+                Some(texture) => FactoredTexture::new(Spectrum::constant(1.0), Some(texture.clone())),
+                None => {
+                    log::warn!("texture '{}' is not defined or not supported", name);
+                    FactoredTexture::new(default, None)
+                },
+            },
+            Some(SpectrumParam::Rgb(color)) => FactoredTexture::new(color, None),
+            Some(SpectrumParam::Named(name)) => {
+                log::warn!("named spectrum '{}' is not supported as a colour", name);
+                FactoredTexture::new(default, None)
+            },
+            None => FactoredTexture::new(default, None),
+        })
+    }
+
+    fn material(&self, ty: &str, params: &Params) -> Result<Option<MaterialFactory>> {
+        let factory: MaterialFactory = match ty {
+            "matte" | "diffuse" => {
+                let texture = self.color_texture(params, &["Kd", "reflectance"], Spectrum::constant(0.5))?;
+                let sigma = params.float(&["sigma"], 0.0)?;
+                if sigma > 0.0 {
+                    let sigma = sigma.to_radians();
+                    Arc::new(move || Box::new(OrenNayarMaterial::new(FactoredTexture::new(texture.factor, texture.texture.clone()), sigma)))
+                } else {
+                    Arc::new(move || Box::new(LambertianMaterial::new(FactoredTexture::new(texture.factor, texture.texture.clone()))))
+                }
+            },
+            "plastic" | "coateddiffuse" => {
+                let texture = self.color_texture(params, &["Kd", "reflectance"], Spectrum::constant(if ty == "plastic" { 0.25 } else { 0.5 }))?;
+                let roughness = roughness(params, if ty == "plastic" { 0.1 } else { 0.0 })?;
+                let ior = params.float(&["eta"], 1.5)?;
+                Arc::new(move || Box::new(PlasticMaterial::<Ggx>::new(roughness, ior, FactoredTexture::new(texture.factor, texture.texture.clone()))))
+            },
+            "metal" | "conductor" => {
+                let roughness = roughness(params, if ty == "metal" { 0.01 } else { 0.0 })?;
+                match (params.spectrum(&["reflectance"])?, params.spectrum(&["eta"])?, params.spectrum(&["k"])?) {
+                    (Some(SpectrumParam::Rgb(reflectance)), _, _) => Arc::new(move || Box::new(MetalMaterial::<Ggx>::new(roughness, reflectance))),
+                    (_, Some(SpectrumParam::Rgb(eta)), Some(SpectrumParam::Rgb(k))) => Arc::new(move || Box::new(MetalMaterial::<Ggx>::conductor(roughness, eta, k))),
+                    (_, eta, _) => {
+                        let metal = match eta {
+                            Some(SpectrumParam::Named(name)) => named_metal(name).unwrap_or_else(|| {
+                                log::warn!("metal '{}' is not known, using copper", name);
+                                Metal::Copper
+                            }),
+                            _ => Metal::Copper,
+                        };
+                        Arc::new(move || Box::new(MetalMaterial::<Ggx>::measured(roughness, metal)))
+                    },
+                }
+            },
+            "mirror" => {
+                let color = color(params, &["Kr"], Spectrum::constant(0.9));
+                Arc::new(move || Box::new(MetalMaterial::<Ggx>::new(0.0, color)))
+            },
+            "glass" | "dielectric" => {
+                let ior = ior(params)?;
+                let roughness = roughness(params, 0.0)?;
+                Arc::new(move || {
+                    let mut material = PrincipledMaterial::flat(Spectrum::constant(1.0));
+                    material.roughness = Box::new(roughness);
+                    material.transmission = 1.0;
+                    material.ior = ior;
+                    Box::new(material) as Box<dyn Material>
+                })
+            },
+            "thindielectric" => {
+                let ior = ior(params)?;
+                Arc::new(move || Box::new(ThinDielectricMaterial::new(ior)))
+            },
+            _ => {
+                log::warn!("material '{}' is not supported, using the current material instead", ty);
+                return Ok(None);
+            },
+        };
+
+        params.find(&["type"]);
+        params.warn_unused(&format!("material '{}'", ty));
+
+        Ok(Some(factory))
+    }
+
+    fn light(&mut self, ty: &str, params: &Params) -> Result<()> {
+        let transform = Affine3::from_matrix_unchecked(self.state.transform);
+
+        let light = match ty {
+            "point" => {
+                let from = params.floats(&["from"]).and_then(point).unwrap_or_else(Point3::origin);
+                LightSource::Point(PointLight {
+                    position: transform * from,
+                    intensity: color(params, &["I"], Spectrum::constant(1.0)) * params.scale(),
+                })
+            },
+            "distant" => {
+                let from = params.floats(&["from"]).and_then(point).unwrap_or_else(Point3::origin);
+                let to = params.floats(&["to"]).and_then(point).unwrap_or_else(|| Point3::new(0.0, 0.0, 1.0));
+                let direction = (transform * (to - from)).try_normalize(0.0).ok_or_else(|| Error::invalid("light direction of zero length"))?;
+                LightSource::Directional(DirectionalLight {
+                    neg_direction: -direction,
+                    irradiance: color(params, &["L"], Spectrum::constant(1.0)) * params.scale(),
+                })
+            },
+            "infinite" => {
+                let scale = color(params, &["L"], Spectrum::constant(1.0)) * params.scale();
+                let mut texture = match params.string(&["filename", "mapname"]) {
+                    Some(filename) => {
+                        log::warn!("the orientation of environment map '{}' is not converted from pbrt's", filename);
+                        Texture::from_file(self.dir.join(filename), ColorSpace::Srgb)?
+                    },
+                    None => Texture::new(1, 1, &Spectrum::constant(1.0)),
+                };
+                texture.pixels_mut().for_each(|(_, px)| *px *= scale);
+                LightSource::SkySphere(SkySphere::new(texture))
+            },
+            _ => {
+                log::warn!("light '{}' is not supported and is ignored", ty);
+                return Ok(());
+            },
+        };
+
+        params.warn_unused(&format!("light '{}'", ty));
+        self.lights.push(light);
+
+        Ok(())
+    }
+
+    // the material of the next shape, made emissive when an area light is active.
+    fn shape_material(&self) -> MaterialFactory {
+        let material = self.state.material.clone();
+        match self.state.area_light {
+            Some((radiance, two_sided)) => Arc::new(move || Box::new(EmissiveMaterial::new(material(), radiance, two_sided))),
+            None => material,
+        }
+    }
+
+    fn mesh(&mut self, ty: &str, params: &Params, name: &str) -> Result<()> {
+        let positions = params.floats(&["P"]).ok_or_else(|| Error::missing(format!("positions of the {}", ty)))?;
+        if positions.len() % 3 != 0 || positions.is_empty() {
+            return Err(Error::invalid(format!("positions of the {}, expected triples", ty)));
+        }
+        let count = positions.len() / 3;
+
+        let normals = params.floats(&["N"]).filter(|n| n.len() == positions.len());
+        let tex_coords = params.floats(&["uv", "st"]).filter(|uv| uv.len() == count * 2);
+
+        let mut vertices: Vec<_> = (0..count)
+            .map(|i| Vertex {
+                position: Point3::new(positions[3 * i], positions[3 * i + 1], positions[3 * i + 2]),
+                normal: normals.map_or_else(Vector3::zeros, |n| Vector3::new(n[3 * i], n[3 * i + 1], n[3 * i + 2])),
+                // pbrt's v axis points up the image.
+                tex_coords: tex_coords.map_or_else(Point2::origin, |uv| Point2::new(uv[2 * i], 1.0 - uv[2 * i + 1])),
+                ..Default::default()
+            })
+            .collect();
+
+        let given: Vec<u32> = match params.floats(&["indices"]) {
+            Some(indices) => indices.iter()
+                .map(|&i| if i >= 0.0 && i.fract() == 0.0 { Ok(i as u32) } else { Err(Error::invalid(format!("index {}", i))) })
+                .collect::<Result<_>>()?,
+            None if ty == "trianglemesh" && count == 3 => vec![0, 1, 2],
+            None if ty == "bilinearmesh" && count == 4 => vec![0, 1, 2, 3],
+            None => return Err(Error::missing(format!("indices of the {}", ty))),
+        };
+
+        if let Some(index) = given.iter().find(|&&i| i as usize >= count) {
+            return Err(Error::invalid(format!("index {} for {} vertices", index, count)));
+        }
+
+        // bilinear patches list their corners as p00, p10, p01, p11.
+        let mut indices: Vec<u32> = match ty {
+            "bilinearmesh" if given.len().is_multiple_of(4) => given.chunks_exact(4).flat_map(|q| [q[0], q[1], q[3], q[0], q[3], q[2]]).collect(),
+            "trianglemesh" if given.len().is_multiple_of(3) => given,
+            _ => return Err(Error::invalid(format!("number of indices of the {}", ty))),
+        };
+
+        if normals.is_none() {
+            match self.options.normals {
+                NormalGeneration::Flat => generate::flat_normals(&mut vertices, &mut indices),
+                NormalGeneration::Smooth => generate::smooth_normals(&mut vertices, &indices),
+            }
+            // given normals decide the facing on their own, like in pbrt.
+            if self.state.reverse_orientation {
+                vertices.iter_mut().for_each(|v| v.normal = -v.normal);
+            }
+        }
+
+        if tex_coords.is_none() {
+            log::warn!("a {} in {} has no texture coordinates, projecting them onto its bounding box", ty, name);
+            generate::planar_tex_coords(&mut vertices);
+        }
+
+        generate::mikktspace_tangents(&mut vertices, &mut indices);
+        params.warn_unused(&format!("shape '{}'", ty));
+
+        let material = self.shape_material()();
+        self.nodes.push(Node {
+            transform: Affine3::from_matrix_unchecked(self.state.transform),
+            meshes: vec![Mesh { vertices, indices, material: material.into() }],
+            ..Default::default()
+        });
+
+        Ok(())
+    }
+
+    fn ply_mesh(&mut self, params: &Params) -> Result<()> {
+        let filename = params.string(&["filename"]).ok_or_else(|| Error::missing("filename of the plymesh"))?;
+        params.warn_unused("shape 'plymesh'");
+
+        let mut options = self.options.clone();
+        options.default_material = Some(self.shape_material());
+
+        let mut builder = SceneBuilder::new();
+        Ply::load_from_file(self.dir.join(filename), &mut builder, &options)?;
+
+        let mut root = builder.root;
+        root.transform = Affine3::from_matrix_unchecked(self.state.transform);
+        self.nodes.push(root);
+
+        Ok(())
+    }
+
+    fn finish(self, builder: &mut SceneBuilder) {
+        let world_from_camera = self.camera.and_then(|(camera_from_world, _)| camera_from_world.try_inverse());
+
+        // a camera transform that mirrors the world already turns it right handed.
+        let mirror = match world_from_camera {
+            Some(m) if m.fixed_slice::<3, 3>(0, 0).determinant() < 0.0 => Matrix4::identity(),
+            _ => Matrix4::new_nonuniform_scaling(&Vector3::new(-1.0, 1.0, 1.0)),
+        };
+        let mirror = Affine3::from_matrix_unchecked(mirror);
+
+        if let (Some((_, fov)), Some(world_from_camera)) = (self.camera, world_from_camera) {
+            let to_world = mirror * Affine3::from_matrix_unchecked(world_from_camera);
+            let aspect = self.film_size.0 / self.film_size.1;
+
+            // pbrt's field of view spans the shorter side of the image.
+            let fov = fov.to_radians();
+            let vfov = if aspect >= 1.0 { fov } else { 2.0 * ((fov / 2.0).tan() / aspect).atan() };
+
+            builder.camera = Camera::perspective_look_to(
+                &(to_world * Point3::origin()),
+                &(to_world * Vector3::z()),
+                &(to_world * Vector3::y()),
+                vfov,
+                aspect,
+            );
+        }
+
+        builder.root.children.push(Node { children: self.nodes, transform: mirror, ..Default::default() });
+
+        builder.light_sources.extend(self.lights.into_iter().map(|light| match light {
+            LightSource::Point(light) => LightSource::Point(PointLight { position: mirror * light.position, ..light }),
+            LightSource::Directional(light) => LightSource::Directional(DirectionalLight { neg_direction: mirror * light.neg_direction, ..light }),
+            light => light,
+        }));
+    }
+}
+
+// the camera from world transform of a camera at pos looking at look.
+fn look_at(pos: &Point3<f32>, look: &Point3<f32>, up: &Vector3<f32>) -> Result<Matrix4<f32>> {
+    let dir = (look - pos).try_normalize(0.0).ok_or_else(|| Error::invalid("LookAt, the camera looks at its own position"))?;
+    let right = up.normalize().cross(&dir).try_normalize(0.0).ok_or_else(|| Error::invalid("LookAt, the up vector is parallel to the view direction"))?;
+    let new_up = dir.cross(&right);
+
+    let rotation = Matrix3::from_columns(&[right, new_up, dir]);
+    let world_from_camera = Matrix4::new_translation(&pos.coords) * rotation.to_homogeneous();
+
+    Ok(world_from_camera.try_inverse().unwrap_or_else(Matrix4::identity))
+}
+
+fn point(values: &[f32]) -> Option<Point3<f32>> {
+    match *values {
+        [x, y, z] => Some(Point3::new(x, y, z)),
+        _ => None,
+    }
+}
+
+// colours that are named spectra or textures fall back to the default, as lights can not use them.
+fn color(params: &Params, names: &[&str], default: Spectrum<f32>) -> Spectrum<f32> {
+    match params.spectrum(names) {
+        Ok(Some(SpectrumParam::Rgb(color))) => color,
+        Ok(None) => default,
+        _ => {
+            log::warn!("parameter '{}' is not an rgb colour, using the default", names[0]);
+            default
+        },
+    }
+}
+
+fn named_metal(name: &str) -> Option<Metal> {
+    name.split(|c: char| !c.is_ascii_alphanumeric()).find_map(Metal::from_name)
+}
+
+fn ior(params: &Params) -> Result<f32> {
+    match params.spectrum(&["eta", "index"])? {
+        Some(SpectrumParam::Rgb(eta)) => Ok(eta.g),
+        Some(SpectrumParam::Named(name)) => {
+            log::warn!("named spectrum '{}' is not supported as an index of refraction, using 1.5", name);
+            Ok(1.5)
+        },
+        _ => Ok(1.5),
+    }
+}
+
+// pbrt remaps its roughness to the microfacet alpha with a square root unless told otherwise, ours
+// is squared to get alpha.
+fn roughness(params: &Params, default: f32) -> Result<f32> {
+    let roughness = match (params.floats(&["uroughness"]), params.floats(&["vroughness"])) {
+        (Some(&[u]), Some(&[v])) => (u + v) / 2.0,
+        _ => params.float(&["roughness"], default)?,
+    };
+
+    let alpha = if params.bool("remaproughness", true) { roughness.max(0.0).sqrt() } else { roughness.max(0.0) };
+    Ok(alpha.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use nalgebra::{Point2, Point3, Vector3};
+
+    use crate::{
+        accelerator::Bvh,
+        error::{Cause, Location},
+        geometry::{Ray, SurfacePoint},
+        light::{LightSource, PointLight, Emitter},
+        material::LambertianMaterial,
+        scene::{SceneBuilder, Vertex, loader::{Loader, LoadOptions}},
+        spectrum::Spectrum,
+    };
+
+    use super::Pbrt;
+
+    const SCENE: &str = r#"
+        LookAt 0 0 -5  0 0 0  0 1 0  # on -z looking at the origin
+        Camera "perspective" "float fov" [ 45 ]
+        Film "rgb" "integer xresolution" [ 200 ] "integer yresolution" [ 100 ] "string filename" "out.exr"
+        Sampler "halton" "integer pixelsamples" 16
+        WorldBegin
+        LightSource "point" "point3 from" [ 1 2 3 ] "rgb I" [ 4 4 4 ]
+        LightSource "infinite" "rgb L" [ 0.1 0.2 0.3 ]
+        AttributeBegin
+            Material "diffuse" "rgb reflectance" [ 0.2 0.4 0.6 ]
+            Translate 2 0 0
+            Shape "trianglemesh" "point3 P" [ 0 0 0  1 0 0  0 1 0 ] "integer indices" [ 0 1 2 ] "point2 uv" [ 0 0  1 0  0 1 ]
+        AttributeEnd
+        AttributeBegin
+            AreaLightSource "diffuse" "rgb L" [ 5 5 5 ]
+            ReverseOrientation
+            Shape "bilinearmesh" "point3 P" [ -1 -1 1  1 -1 1  -1 1 1  1 1 1 ]
+        AttributeEnd
+        Shape "sphere" "float radius" 1
+    "#;
+
+    fn load(text: &str) -> crate::error::Result<SceneBuilder> {
+        let mut builder = SceneBuilder::new();
+        Pbrt::load_from_reader(&mut Cursor::new(text.as_bytes()), &mut builder, &LoadOptions::default())?;
+        Ok(builder)
+    }
+
+    #[test]
+    fn pbrt_load_test() {
+        let builder = load(SCENE).unwrap();
+
+        // x is mirrored to turn pbrt's left handed world into ours.
+        let point = match &builder.light_sources[0] {
+            LightSource::Point(light) => light.position,
+            _ => panic!("expected a point light"),
+        };
+        assert_eq!(point, Point3::new(-1.0, 2.0, 3.0));
+        assert!(matches!(builder.light_sources[1], LightSource::SkySphere(_)));
+
+        // the right side of the image is towards pbrt's +x, which is our -x.
+        let center = builder.camera.get_ray(Point2::new(100, 50), (200, 100)).ray;
+        assert!((center.origin - Point3::new(0.0, 0.0, -5.0)).norm() < 1e-4);
+        assert!((center.direction - Vector3::z()).norm() < 0.02);
+        assert!(builder.camera.get_ray(Point2::new(199, 50), (200, 100)).ray.direction.x < 0.0);
+
+        let meshes = load(SCENE).unwrap().root.flatten();
+        assert_eq!(meshes.len(), 2);
+        let mut positions: Vec<_> = meshes[0].vertices.iter().map(|v| [v.position.x, v.position.y, v.position.z]).collect();
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        positions.dedup();
+        assert_eq!(positions, vec![[-3.0, 0.0, 0.0], [-2.0, 0.0, 0.0], [-2.0, 1.0, 0.0]]);
+
+        let scene = builder.build::<Bvh>();
+
+        // the reversed area light faces the camera.
+        let emission = |origin: Point3<f32>, direction: Vector3<f32>| {
+            let p = scene.intersect(&Ray { origin, direction }).unwrap();
+            p.emission(&(p.tangent_to_world().transpose() * -direction))
+        };
+        assert_eq!(emission(Point3::new(0.0, 0.0, -5.0), Vector3::z()), Spectrum::constant(5.0));
+        assert_eq!(emission(Point3::new(0.0, 0.0, 5.0), -Vector3::z()), Spectrum::black());
+
+        // the area light's quad sits between the point light and points below it.
+        let light = PointLight { position: point, intensity: Spectrum::constant(4.0) };
+        let material = LambertianMaterial::flat(Spectrum::constant(0.5));
+        let visible = |position: Point3<f32>| {
+            let vertex = Vertex { position, normal: Vector3::z(), tangent: Vector3::x(), ..Default::default() };
+            let sample = light.sample(&SurfacePoint::from_vertex(&vertex, &material));
+            sample.visibility_test.eval(&scene)
+        };
+        assert!(!visible(Point3::new(0.0, -1.0, -1.0)));
+        assert!(visible(Point3::new(-1.0, 2.0, 2.0)));
+        assert!(visible(Point3::new(-1.0, 4.0, -1.0)));
+    }
+
+    #[test]
+    fn pbrt_include_test() {
+        let dir = std::env::temp_dir().join("pbrt_include_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("materials.pbrt"), "MakeNamedMaterial \"gold\" \"string type\" \"conductor\" \"spectrum eta\" \"metal-Au-eta\" \"spectrum k\" \"metal-Au-k\"\n").unwrap();
+        std::fs::write(dir.join("triangle.ply"), "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n").unwrap();
+        let scene = "WorldBegin\nInclude \"materials.pbrt\"\nNamedMaterial \"gold\"\n\
+            ObjectBegin \"skipped\"\nShape \"trianglemesh\" \"point3 P\" [ 0 0 0 1 0 0 0 1 0 ]\nObjectEnd\n\
+            Scale 2 2 2\nShape \"plymesh\" \"string filename\" \"triangle.ply\"\n";
+        std::fs::write(dir.join("scene.pbrt"), scene).unwrap();
+
+        let builder = SceneBuilder::new().add_file::<Pbrt, _>(dir.join("scene.pbrt")).unwrap();
+        let meshes = builder.root.flatten();
+        assert_eq!(meshes.len(), 1);
+        assert!(meshes[0].vertices.iter().any(|v| v.position == Point3::new(-2.0, 0.0, 0.0)));
+
+        // errors in an included file point at it.
+        std::fs::write(dir.join("materials.pbrt"), "\n\nMaterial \"diffuse\" \"rgb reflectance\" [ 1 2 ]\n").unwrap();
+        let error = SceneBuilder::new().add_file::<Pbrt, _>(dir.join("scene.pbrt")).err().unwrap();
+        assert_eq!(error.path, Some(dir.join("materials.pbrt")));
+        assert_eq!(error.location, Some(Location::Line(3)));
+
+        std::fs::write(dir.join("materials.pbrt"), "Include \"scene.pbrt\"\n").unwrap();
+        let error = SceneBuilder::new().add_file::<Pbrt, _>(dir.join("scene.pbrt")).err().unwrap();
+        assert_eq!(error.path, Some(dir.join("materials.pbrt")));
+        assert_eq!(error.location, Some(Location::Line(1)));
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pbrt_error_test() {
+        let error = load("WorldBegin\nNamedMaterial \"missing\"\n").err().unwrap();
+        assert_eq!(error.location, Some(Location::Line(2)));
+        assert!(matches!(*error.cause, Cause::Missing(_)));
+
+        let error = load("AttributeBegin\nAttributeEnd\nAttributeEnd\n").err().unwrap();
+        assert_eq!(error.location, Some(Location::Line(3)));
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+
+        let error = load("Camera \"perspective\" \"float fov\n").err().unwrap();
+        assert_eq!(error.location, Some(Location::Line(1)));
+
+        let error = load("WorldBegin\nShape \"trianglemesh\" \"point3 P\" [ 0 0 0 1 0 0 0 1 0 ] \"integer indices\" [ 0 1 3 ]\n").err().unwrap();
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+
+        let error = load("WorldBegin\nShape \"trianglemesh\" \"point3 P\" [ 0 0 0 1 0 0 0 1 0 ] \"integer indices\" [ 0 1.5 -1 ]\n").err().unwrap();
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+
+        let error = load("Frobnicate 1 2 3\n").err().unwrap();
+        assert!(matches!(*error.cause, Cause::Unsupported(_)));
+    }
+}
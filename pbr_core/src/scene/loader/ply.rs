@@ -0,0 +1,551 @@
+use std::{fs::File, io::{BufRead, BufReader, Read, Seek}, path::Path};
+
+use nalgebra::{Point2, Point3, Vector3};
+
+use super::{Loader, LoadOptions, NormalGeneration, generate};
+use crate::{
+    error::{Result, Error, Location},
+    material::{Material, LambertianMaterial},
+    scene::{SceneBuilder, Node, Mesh, Vertex},
+    spectrum::Spectrum,
+    texture::{ColorSpace, VertexColorTexture},
+};
+
+// Polygon files in ascii or binary, as scanners and research meshes like the stanford bunny come in.
+// The whole file becomes a single mesh with the default material of the load options, or a
+// diffuse one showing the vertex colours when there are any.
+// Source: https://paulbourke.net/dataformats/ply/
+pub struct Ply;
+
+impl Loader for Ply {
+    fn load_from_file<P: AsRef<Path>>(path: P, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()> {
+        let load = || -> Result<Node> {
+            let mut reader = BufReader::new(File::open(&path)?);
+            load_ply(&mut reader, &path.as_ref().display().to_string(), options)
+        };
+
+        let node = load().map_err(|e| e.in_file(&path))?;
+        builder.root.children.push(node);
+
+        Ok(())
+    }
+
+    fn load_from_reader<R: Read + Seek>(rdr: &mut R, builder: &mut SceneBuilder, options: &LoadOptions) -> Result<()> {
+        let node = load_ply(&mut BufReader::new(rdr), "ply data", options)?;
+        builder.root.children.push(node);
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "char" | "int8" => Ok(ScalarType::I8),
+            "uchar" | "uint8" => Ok(ScalarType::U8),
+            "short" | "int16" => Ok(ScalarType::I16),
+            "ushort" | "uint16" => Ok(ScalarType::U16),
+            "int" | "int32" => Ok(ScalarType::I32),
+            "uint" | "uint32" => Ok(ScalarType::U32),
+            "float" | "float32" => Ok(ScalarType::F32),
+            "double" | "float64" => Ok(ScalarType::F64),
+            _ => Err(Error::invalid(format!("property type '{}'", name))),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+
+    // integer colours span the range of their type, float ones [0, 1].
+    fn unit_scale(&self) -> f32 {
+        match self {
+            ScalarType::U8 => 1.0 / u8::MAX as f32,
+            ScalarType::U16 => 1.0 / u16::MAX as f32,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum PropertyType {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+struct Property {
+    name: String,
+    kind: PropertyType,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|property| names.contains(&property.name.as_str()))
+    }
+}
+
+struct Header {
+    format: Option<Format>,
+    elements: Vec<Element>,
+}
+
+fn read_header(reader: &mut impl BufRead) -> Result<Header> {
+    let mut header = Header { format: None, elements: Vec::new() };
+    let mut line = String::new();
+    let mut number = 0;
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(Error::missing("end_header"));
+        }
+        number += 1;
+
+        let done = parse_header_line(&mut header, &line, number).map_err(|e| e.at(Location::Line(number)))?;
+        if done {
+            return Ok(header);
+        }
+    }
+}
+
+// returns whether the line ends the header.
+fn parse_header_line(header: &mut Header, line: &str, number: usize) -> Result<bool> {
+    let mut tokens = line.split_whitespace();
+    let keyword = tokens.next();
+
+    if number == 1 {
+        return match keyword {
+            Some("ply") => Ok(false),
+            _ => Err(Error::invalid("file, it does not start with 'ply'")),
+        };
+    }
+
+    match keyword {
+        Some("format") => {
+            header.format = Some(match tokens.next() {
+                Some("ascii") => Format::Ascii,
+                Some("binary_little_endian") => Format::BinaryLittleEndian,
+                Some("binary_big_endian") => Format::BinaryBigEndian,
+                other => return Err(Error::unsupported(format!("format '{}'", other.unwrap_or_default()))),
+            });
+        },
+        Some("element") => {
+            let name = tokens.next().ok_or_else(|| Error::missing("element name"))?;
+            let count = tokens.next()
+                .and_then(|count| count.parse().ok())
+                .ok_or_else(|| Error::invalid(format!("count of element '{}'", name)))?;
+            header.elements.push(Element { name: name.to_string(), count, properties: Vec::new() });
+        },
+        Some("property") => {
+            let element = header.elements.last_mut().ok_or_else(|| Error::missing("element before property"))?;
+            let words: Vec<&str> = tokens.collect();
+
+            let (kind, name) = match words[..] {
+                ["list", count, item, name] => (PropertyType::List { count: ScalarType::from_name(count)?, item: ScalarType::from_name(item)? }, name),
+                [scalar, name] => (PropertyType::Scalar(ScalarType::from_name(scalar)?), name),
+                _ => return Err(Error::invalid("property declaration")),
+            };
+            element.properties.push(Property { name: name.to_string(), kind });
+        },
+        Some("end_header") => {
+            if header.format.is_none() {
+                return Err(Error::missing("format"));
+            }
+            return Ok(true);
+        },
+        // comments and obj_info.
+        _ => (),
+    }
+
+    Ok(false)
+}
+
+// reads the values of the body one at a time, whatever their encoding.
+trait ValueReader {
+    fn read(&mut self, scalar: ScalarType) -> Result<f64>;
+}
+
+struct AsciiReader<R> {
+    reader: R,
+    // the remaining tokens of the current line, in reverse.
+    tokens: Vec<String>,
+}
+
+impl<R: BufRead> ValueReader for AsciiReader<R> {
+    fn read(&mut self, _scalar: ScalarType) -> Result<f64> {
+        while self.tokens.is_empty() {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(Error::missing("values, the file ends early"));
+            }
+            self.tokens = line.split_whitespace().rev().map(str::to_string).collect();
+        }
+
+        let token = self.tokens.pop().expect("tokens are not empty");
+        token.parse().map_err(|_| Error::invalid(format!("number '{}'", token)))
+    }
+}
+
+struct BinaryReader<R> {
+    reader: R,
+    big_endian: bool,
+}
+
+macro_rules! from_bytes {
+    ($ty:ty, $bytes:expr, $big_endian:expr) => {{
+        let bytes = $bytes.try_into().expect("sized by the scalar type");
+        (if $big_endian { <$ty>::from_be_bytes(bytes) } else { <$ty>::from_le_bytes(bytes) }) as f64
+    }};
+}
+
+impl<R: Read> ValueReader for BinaryReader<R> {
+    fn read(&mut self, scalar: ScalarType) -> Result<f64> {
+        let mut buffer = [0u8; 8];
+        let bytes = &mut buffer[..scalar.size()];
+        self.reader.read_exact(bytes)?;
+
+        let big = self.big_endian;
+        Ok(match scalar {
+            ScalarType::I8 => from_bytes!(i8, &bytes[..], big),
+            ScalarType::U8 => from_bytes!(u8, &bytes[..], big),
+            ScalarType::I16 => from_bytes!(i16, &bytes[..], big),
+            ScalarType::U16 => from_bytes!(u16, &bytes[..], big),
+            ScalarType::I32 => from_bytes!(i32, &bytes[..], big),
+            ScalarType::U32 => from_bytes!(u32, &bytes[..], big),
+            ScalarType::F32 => from_bytes!(f32, &bytes[..], big),
+            ScalarType::F64 => from_bytes!(f64, &bytes[..], big),
+        })
+    }
+}
+
+// reads one row of an element. Scalar properties go into scalars by their index, the items of
+// the list at index `list` into items and any other list is skipped.
+fn read_row(reader: &mut dyn ValueReader, element: &Element, list: Option<usize>, scalars: &mut Vec<f64>, items: &mut Vec<f64>) -> Result<()> {
+    scalars.clear();
+    items.clear();
+
+    for (index, property) in element.properties.iter().enumerate() {
+        match property.kind {
+            PropertyType::Scalar(scalar) => scalars.push(reader.read(scalar)?),
+            PropertyType::List { count, item } => {
+                scalars.push(f64::NAN);
+
+                let count = reader.read(count)?;
+                if count < 0.0 {
+                    return Err(Error::invalid(format!("list length {}", count)));
+                }
+
+                for _ in 0..count as usize {
+                    let value = reader.read(item)?;
+                    if Some(index) == list {
+                        items.push(value);
+                    }
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
+
+// the indices of the properties a vertex attribute is read from, if all of them are present.
+fn find_attribute<const N: usize>(element: &Element, names: [&[&str]; N]) -> Option<[usize; N]> {
+    let indices = names.map(|names| element.find(names));
+    indices.iter().all(Option::is_some).then(|| indices.map(|index| index.unwrap_or_default()))
+}
+
+// name is the file or stream in log messages.
+fn load_ply(reader: &mut impl BufRead, name: &str, options: &LoadOptions) -> Result<Node> {
+    let header = read_header(reader)?;
+
+    let mut values: Box<dyn ValueReader + '_> = match header.format.expect("the header checks the format") {
+        Format::Ascii => Box::new(AsciiReader { reader: &mut *reader, tokens: Vec::new() }),
+        Format::BinaryLittleEndian => Box::new(BinaryReader { reader: &mut *reader, big_endian: false }),
+        Format::BinaryBigEndian => Box::new(BinaryReader { reader: &mut *reader, big_endian: true }),
+    };
+
+    let mut vertices: Option<Vec<Vertex>> = None;
+    let mut faces: Option<Vec<Vec<u32>>> = None;
+    let mut has_normals = false;
+    let mut has_tex_coords = false;
+    let mut has_colors = false;
+    let (mut scalars, mut items) = (Vec::new(), Vec::new());
+
+    for element in &header.elements {
+        let location = Location::Element(element.name.clone());
+
+        match element.name.as_str() {
+            "vertex" => {
+                let position = find_attribute(element, [&["x"], &["y"], &["z"]])
+                    .ok_or_else(|| Error::missing("vertex positions"))?;
+                let normal = find_attribute(element, [&["nx"], &["ny"], &["nz"]]);
+                let tex_coords = find_attribute(element, [
+                    &["u", "s", "texture_u", "texture_s"],
+                    &["v", "t", "texture_v", "texture_t"],
+                ]);
+                let color = find_attribute(element, [&["red", "r"], &["green", "g"], &["blue", "b"]]);
+
+                let color_scale = color.map(|[r, _, _]| match element.properties[r].kind {
+                    PropertyType::Scalar(scalar) => scalar.unit_scale(),
+                    PropertyType::List { .. } => 1.0,
+                });
+
+                has_normals = normal.is_some();
+                has_tex_coords = tex_coords.is_some();
+                has_colors = color.is_some();
+
+                // the count comes from the file, so the list only grows with the rows actually read.
+                let mut list = Vec::new();
+                for _ in 0..element.count {
+                    read_row(values.as_mut(), element, None, &mut scalars, &mut items).map_err(|e| e.at(location.clone()))?;
+                    let value = |index: usize| scalars[index] as f32;
+
+                    let mut vertex = Vertex { position: Point3::from(position.map(value)), ..Default::default() };
+                    if let Some(normal) = normal {
+                        vertex.normal = Vector3::from(normal.map(value));
+                    }
+                    // like obj the origin of the texture coordinates is at the bottom left.
+                    if let Some([u, v]) = tex_coords {
+                        vertex.tex_coords = Point2::new(value(u), 1.0 - value(v));
+                    }
+                    // colours are stored for display, so they are srgb encoded.
+                    if let (Some(color), Some(scale)) = (color, color_scale) {
+                        let [r, g, b] = color.map(|index| ColorSpace::Srgb.decode(value(index) * scale));
+                        vertex.color = Spectrum::new(r, g, b);
+                    }
+                    list.push(vertex);
+                }
+                vertices = Some(list);
+            },
+            "face" => {
+                let indices = element.find(&["vertex_indices", "vertex_index"])
+                    .ok_or_else(|| Error::missing("face vertex indices").at(location.clone()))?;
+
+                let mut list = Vec::new();
+                for _ in 0..element.count {
+                    read_row(values.as_mut(), element, Some(indices), &mut scalars, &mut items).map_err(|e| e.at(location.clone()))?;
+                    let face = items.iter()
+                        .map(|&i| if i >= 0.0 && i.fract() == 0.0 { Ok(i as u32) } else { Err(Error::invalid(format!("vertex index {}", i)).at(location.clone())) })
+                        .collect::<Result<_>>()?;
+                    list.push(face);
+                }
+                faces = Some(list);
+            },
+            // other elements like edges are read past.
+            _ => {
+                for _ in 0..element.count {
+                    read_row(values.as_mut(), element, None, &mut scalars, &mut items).map_err(|e| e.at(location.clone()))?;
+                }
+            },
+        }
+    }
+
+    let mut vertices = vertices.ok_or_else(|| Error::missing("vertex element"))?;
+    let faces = faces.ok_or_else(|| Error::missing("face element, point clouds can not be rendered"))?;
+
+    let mut indices = Vec::new();
+    for (face_index, face) in faces.iter().enumerate() {
+        if let Some(index) = face.iter().find(|&&i| i as usize >= vertices.len()) {
+            return Err(Error::invalid(format!("index {} in face {} for {} vertices", index, face_index, vertices.len())));
+        }
+        if face.len() < 3 {
+            continue;
+        }
+
+        let positions: Vec<_> = face.iter().map(|&i| vertices[i as usize].position).collect();
+        for triangle in generate::triangulate(&positions) {
+            indices.extend(triangle.map(|k| face[k]));
+        }
+    }
+
+    if !has_normals {
+        match options.normals {
+            NormalGeneration::Flat => {
+                log::warn!("{} has no normals, generating flat normals", name);
+                generate::flat_normals(&mut vertices, &mut indices);
+            },
+            NormalGeneration::Smooth => {
+                log::warn!("{} has no normals, generating smooth normals", name);
+                generate::smooth_normals(&mut vertices, &indices);
+            },
+        }
+    }
+
+    if !has_tex_coords {
+        log::warn!("{} has no texture coordinates, projecting them onto its bounding box", name);
+        generate::planar_tex_coords(&mut vertices);
+    }
+
+    // ply has no tangents, they are always generated.
+    generate::mikktspace_tangents(&mut vertices, &mut indices);
+
+    let material: Box<dyn Material> = match &options.default_material {
+        None if has_colors => Box::new(LambertianMaterial::new(VertexColorTexture)),
+        _ => options.default_material(),
+    };
+
+    Ok(Node { meshes: vec![Mesh { vertices, indices, material: material.into() }], ..Default::default() })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::{Arc, atomic::{AtomicUsize, Ordering}}};
+
+    use nalgebra::{Point2, Point3, Vector3};
+
+    use crate::{
+        error::{Cause, Location},
+        material::LambertianMaterial,
+        scene::{SceneBuilder, Mesh, loader::{Loader, LoadOptions}},
+        spectrum::Spectrum,
+    };
+
+    use super::Ply;
+
+    fn load(data: &[u8], options: &LoadOptions) -> crate::error::Result<Mesh> {
+        let mut builder = SceneBuilder::new();
+        Ply::load_from_reader(&mut Cursor::new(data), &mut builder, options)?;
+        Ok(builder.root.flatten().remove(0))
+    }
+
+    const HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property float s
+property float t
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property uchar flags
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+";
+
+    // a unit quad facing +z, red in its first corner and white elsewhere.
+    const CORNERS: [([f32; 3], [f32; 2], [u8; 3]); 4] = [
+        ([0.0, 0.0, 0.0], [0.0, 0.0], [255, 0, 0]),
+        ([1.0, 0.0, 0.0], [1.0, 0.0], [255, 255, 255]),
+        ([1.0, 1.0, 0.0], [1.0, 1.0], [255, 255, 255]),
+        ([0.0, 1.0, 0.0], [0.0, 1.0], [255, 255, 255]),
+    ];
+
+    fn binary_quad(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut data = format!("ply\nformat {} 1.0\ncomment written by a test\n{}", format, HEADER).into_bytes();
+
+        let float = |x: f32| if big_endian { x.to_be_bytes() } else { x.to_le_bytes() };
+        let int = |x: i32| if big_endian { x.to_be_bytes() } else { x.to_le_bytes() };
+
+        for (position, tex_coords, color) in CORNERS {
+            data.extend(position.into_iter().chain([0.0, 0.0, 1.0]).chain(tex_coords).flat_map(float));
+            data.extend(color);
+        }
+
+        data.extend([7, 4]);
+        data.extend([0, 1, 2, 3].into_iter().flat_map(int));
+        data.extend([0, 1].into_iter().flat_map(int));
+        data
+    }
+
+    fn check_quad(mesh: &Mesh) {
+        assert_eq!(mesh.indices.len(), 6);
+
+        let corner = mesh.vertices.iter().find(|v| v.position == Point3::origin()).unwrap();
+        assert_eq!(corner.normal, Vector3::z());
+        assert_eq!(corner.color, Spectrum::new(1.0, 0.0, 0.0));
+        // v is flipped as textures start at the top.
+        assert_eq!(corner.tex_coords, Point2::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn ply_load_test() {
+        let mut ascii = format!("ply\nformat ascii 1.0\n{}", HEADER);
+        for (position, tex_coords, color) in CORNERS {
+            ascii += &format!("{} {} {} 0 0 1 {} {} {} {} {}\n", position[0], position[1], position[2], tex_coords[0], tex_coords[1], color[0], color[1], color[2]);
+        }
+        ascii += "7 4 0 1 2 3\n0 1\n";
+
+        let options = LoadOptions::default();
+        check_quad(&load(ascii.as_bytes(), &options).unwrap());
+        check_quad(&load(&binary_quad(false), &options).unwrap());
+        check_quad(&load(&binary_quad(true), &options).unwrap());
+    }
+
+    #[test]
+    fn ply_material_test() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let counter = created.clone();
+        let options = LoadOptions::default().with_default_material(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Box::new(LambertianMaterial::flat(Spectrum::constant(0.5)))
+        });
+
+        load(&binary_quad(false), &options).unwrap();
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn ply_error_test() {
+        let mut truncated = binary_quad(false);
+        truncated.truncate(truncated.len() - 10);
+        let error = load(&truncated, &LoadOptions::default()).err().unwrap();
+        assert_eq!(error.location, Some(Location::Element("face".to_string())));
+        assert!(matches!(*error.cause, Cause::Io(_)));
+
+        let error = load(b"ply\nelement vertex 0\nend_header\n", &LoadOptions::default()).err().unwrap();
+        assert_eq!(error.location, Some(Location::Line(3)));
+        assert!(matches!(*error.cause, Cause::Missing(_)));
+
+        let bad_index = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n1 0 0\n0 1 0\n3 0 1 3\n";
+        let error = load(bad_index.as_bytes(), &LoadOptions::default()).err().unwrap();
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+
+        let negative_index = bad_index.replace("3 0 1 3\n", "3 0 1 -1\n");
+        let error = load(negative_index.as_bytes(), &LoadOptions::default()).err().unwrap();
+        assert_eq!(error.location, Some(Location::Element("face".to_string())));
+        assert!(matches!(*error.cause, Cause::Invalid(_)));
+
+        // a huge count in the header runs out of data instead of allocating up front.
+        let huge_count = "ply\nformat ascii 1.0\nelement vertex 18446744073709551615\nproperty float x\nproperty float y\nproperty float z\nend_header\n0 0 0\n";
+        let error = load(huge_count.as_bytes(), &LoadOptions::default()).err().unwrap();
+        assert_eq!(error.location, Some(Location::Element("vertex".to_string())));
+    }
+}
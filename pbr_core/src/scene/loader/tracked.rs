@@ -0,0 +1,56 @@
+use std::cell::Cell;
+
+// Parameters of text scene formats remember being read, so the ones an importer has no use
+// for can be reported instead of being dropped silently.
+
+pub(super) trait Named {
+    fn name(&self) -> &str;
+}
+
+pub(super) struct Tracked<T> {
+    items: Vec<T>,
+    used: Vec<Cell<bool>>,
+}
+
+impl<T: Named> Tracked<T> {
+    pub(super) fn new(items: Vec<T>) -> Self {
+        let used = items.iter().map(|_| Cell::new(false)).collect();
+        Self { items, used }
+    }
+
+    // the first item with one of the names, which counts as used from now on.
+    pub(super) fn find(&self, names: &[&str]) -> Option<&T> {
+        let index = self.items.iter().position(|item| names.contains(&item.name()))?;
+        self.used[index].set(true);
+        Some(&self.items[index])
+    }
+
+    pub(super) fn unused(&self) -> impl Iterator<Item = &T> {
+        self.items.iter()
+            .zip(&self.used)
+            .filter(|(_, used)| !used.get())
+            .map(|(item, _)| item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Named, Tracked};
+
+    impl Named for (&str, i32) {
+        fn name(&self) -> &str {
+            self.0
+        }
+    }
+
+    #[test]
+    fn tracked_test() {
+        let tracked = Tracked::new(vec![("a", 1), ("b", 2), ("c", 3)]);
+
+        assert_eq!(tracked.find(&["x", "b"]), Some(&("b", 2)));
+        assert_eq!(tracked.find(&["x"]), None);
+
+        let unused: Vec<_> = tracked.unused().map(|item| item.0).collect();
+        assert_eq!(unused, vec!["a", "c"]);
+    }
+}
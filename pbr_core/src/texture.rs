@@ -1,20 +1,137 @@
-use std::{io::{BufReader, Result, Error, ErrorKind}, fs::File, path::Path, ops::Mul};
+mod combinators;
+mod procedural;
+mod mipmap;
+mod openexr;
+mod writer;
 
-use image::{codecs::hdr, RgbaImage};
+use std::{io::{self, BufReader}, fs::File, path::Path, ops::{Mul, Add}, sync::Arc};
+
+use image::codecs::hdr;
 use nalgebra::{Point2, Vector3, SVector, Scalar, ClosedMul, ClosedDiv};
 use rayon::prelude::{IntoParallelRefIterator, IndexedParallelIterator, ParallelIterator, IntoParallelRefMutIterator};
 
-use crate::spectrum::Spectrum;
+use crate::{spectrum::Spectrum, geometry::SurfacePoint, error::{Result, Error}};
+
+pub use self::combinators::{ScaleTexture, MixTexture, MultiplyTexture, UvTransformTexture, ChannelTexture, NormalTexture};
+pub use self::procedural::{TextureSpace, CheckerboardTexture, NoiseTexture, VoronoiTexture, VertexColorTexture};
+pub use self::mipmap::{MipMap, MipFilter};
+pub use self::openexr::{ExrPrecision, save_exr_layers};
+pub use self::writer::{ImageWriter, ImageFormat, BitDepth, TransferFunction};
+
+// A material input that can be evaluated anywhere on a surface, whether it is a constant,
+// an image or computed from other textures.
+pub trait SurfaceTexture<T>: Send + Sync {
+    fn evaluate(&self, p: &SurfacePoint) -> T;
+}
+
+macro_rules! impl_constant_texture {
+    ($ty:ty) => {
+        impl SurfaceTexture<$ty> for $ty {
+            fn evaluate(&self, _p: &SurfacePoint) -> $ty {
+                *self
+            }
+        }
+    };
+}
 
+impl_constant_texture!(f32);
+impl_constant_texture!(Spectrum<f32>);
+impl_constant_texture!(Vector3<f32>);
+
+impl<T> SurfaceTexture<T> for Box<dyn SurfaceTexture<T>> {
+    fn evaluate(&self, p: &SurfacePoint) -> T {
+        self.as_ref().evaluate(p)
+    }
+}
+
+// lets materials share one texture instead of each holding a copy.
+impl<T, S> SurfaceTexture<T> for Arc<S> where
+    S: SurfaceTexture<T> + ?Sized,
+{
+    fn evaluate(&self, p: &SurfacePoint) -> T {
+        self.as_ref().evaluate(p)
+    }
+}
+
+// the encoding of stored values. colour data is usually srgb encoded, while data like
+// roughness, metalness or normals is stored linearly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum ColorSpace {
+    #[default]
     Linear,
     Srgb,
 }
 
+impl ColorSpace {
+    // converts an encoded value in [0, 1] to linear light.
+    // Source: https://www.color.org/chardata/rgb/srgb.xalter
+    pub fn decode(&self, x: f32) -> f32 {
+        match self {
+            ColorSpace::Linear => x,
+            ColorSpace::Srgb if x <= 0.04045 => x / 12.92,
+            ColorSpace::Srgb => ((x + 0.055) / 1.055).powf(2.4),
+        }
+    }
+
+    // converts linear light in [0, 1] to an encoded value.
+    pub fn encode(&self, x: f32) -> f32 {
+        match self {
+            ColorSpace::Linear => x,
+            ColorSpace::Srgb if x <= 0.0031308 => x * 12.92,
+            ColorSpace::Srgb => 1.055 * x.powf(1.0 / 2.4) - 0.055,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    #[default]
+    Nearest,
+    Bilinear,
+}
+
+// how texture coordinates outside of [0, 1] are mapped back onto the texture.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    ClampToEdge,
+    MirroredRepeat,
+}
+
+impl WrapMode {
+    fn apply(&self, i: i64, n: u32) -> usize {
+        let n = n as i64;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::ClampToEdge => i.clamp(0, n - 1),
+            WrapMode::MirroredRepeat => {
+                let i = i.rem_euclid(2 * n);
+                if i < n { i } else { 2 * n - 1 - i }
+            }
+        };
+        i as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub wrap_u: WrapMode,
+    pub wrap_v: WrapMode,
+}
+
+impl Sampler {
+    pub fn new(filter: Filter, wrap_u: WrapMode, wrap_v: WrapMode) -> Self {
+        Self { filter, wrap_u, wrap_v }
+    }
+}
+
 #[derive(Clone)]
 pub struct Texture<T> {
     size: (u32, u32),
     data: Box<[T]>,
+    sampler: Sampler,
 }
 
 impl<T> Texture<T> {
@@ -22,6 +139,15 @@ impl<T> Texture<T> {
         self.size
     }
 
+    pub fn sampler(&self) -> &Sampler {
+        &self.sampler
+    }
+
+    pub fn with_sampler(mut self, sampler: Sampler) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
     pub fn set(&mut self, pos: Point2<u32>, val: T) {
         self.data[(pos.y * self.size.0 + pos.x) as usize] = val;
     }
@@ -34,19 +160,22 @@ impl<T> Texture<T> where
         let size = (width, height);
         let data = vec![*fill_col; width as usize * height as usize].into_boxed_slice();
         
-        Self { size, data, }
+        Self { size, data, sampler: Sampler::default() }
     }
 
-    pub fn sample(&self, uv: &Point2<f32>) -> T {
-        let u = uv[0];
-        let v = uv[1];
+    // the texel at integer coordinates, wrapped according to the sampler.
+    pub fn texel(&self, x: i64, y: i64) -> T {
+        let x = self.sampler.wrap_u.apply(x, self.size.0);
+        let y = self.sampler.wrap_v.apply(y, self.size.1);
 
-        let x = ((u * self.size.0 as f32) as i32).rem_euclid(self.size.0 as i32);
-        let y = ((v * self.size.1 as f32) as i32).rem_euclid(self.size.1 as i32);
-        
-        let index = y as usize * self.size.0 as usize + x as usize;
+        self.data[y * self.size.0 as usize + x]
+    }
 
-        self.data[index]
+    pub fn sample_nearest(&self, uv: &Point2<f32>) -> T {
+        let x = (uv[0] * self.size.0 as f32).floor() as i64;
+        let y = (uv[1] * self.size.1 as f32).floor() as i64;
+
+        self.texel(x, y)
     }
 
     pub fn aspect_ratio(&self) -> f32 {
@@ -75,6 +204,32 @@ impl<T> Texture<T> where
 
 }
 
+impl<T> Texture<T> where
+    T: Copy + Mul<f32, Output = T> + Add<T, Output = T>,
+{
+    pub fn sample(&self, uv: &Point2<f32>) -> T {
+        match self.sampler.filter {
+            Filter::Nearest => self.sample_nearest(uv),
+            Filter::Bilinear => self.sample_bilinear(uv),
+        }
+    }
+
+    pub fn sample_bilinear(&self, uv: &Point2<f32>) -> T {
+        // texel centers sit at half integer coordinates.
+        let x = uv[0] * self.size.0 as f32 - 0.5;
+        let y = uv[1] * self.size.1 as f32 - 0.5;
+
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.texel(x0, y0) * (1.0 - tx) + self.texel(x0 + 1, y0) * tx;
+        let bottom = self.texel(x0, y0 + 1) * (1.0 - tx) + self.texel(x0 + 1, y0 + 1) * tx;
+
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
 impl<T> Texture<T> where
     T: Sync
 {
@@ -92,7 +247,7 @@ impl<T> Texture<T> where
 impl<T> Texture<T> where
     T: Send
 {
-    pub fn par_pixels_mut(&mut self) -> impl ParallelIterator<Item = (Point2<u32>, &mut T)> {
+    pub fn par_pixels_mut(&mut self) -> impl IndexedParallelIterator<Item = (Point2<u32>, &mut T)> {
         self.data
             .par_iter_mut()
             .enumerate()
@@ -112,27 +267,54 @@ pub trait PixelComponent: Copy + Scalar + ClosedMul + ClosedDiv {
     {
         Self::from(u) * Self::MAX / Self::from(T::MAX)
     }
+
+    // the value normalized to [0, 1].
+    fn to_unit(self) -> f32;
+    fn from_unit(x: f32) -> Self;
+
+    fn decode(self, color_space: ColorSpace) -> Self {
+        match color_space {
+            ColorSpace::Linear => self,
+            _ => Self::from_unit(color_space.decode(self.to_unit())),
+        }
+    }
 }
 
 macro_rules! impl_pixel_component {
-    ($ty:ty, $min:expr, $max:expr) => {
+    ($ty:ty, $min:expr, $max:expr, $from_unit:expr) => {
         impl PixelComponent for $ty {
             const MAX: Self = $max;
+
+            fn to_unit(self) -> f32 {
+                self as f32 / $max as f32
+            }
+
+            fn from_unit(x: f32) -> Self {
+                $from_unit(x)
+            }
         }
     };
 }
 
-impl_pixel_component!(u8, 0, 255);
-impl_pixel_component!(f32, 0.0, 1.0);
+impl_pixel_component!(u8, 0, 255, |x: f32| (x.clamp(0.0, 1.0) * 255.0).round() as u8);
+impl_pixel_component!(f32, 0.0, 1.0, |x: f32| x);
+
+// an image without texels can't be sampled or mipmapped.
+fn check_size(width: u32, height: u32) -> Result<()> {
+    if width == 0 || height == 0 {
+        return Err(Error::invalid(format!("image size {}x{}", width, height)));
+    }
+    Ok(())
+}
 
 impl<T> Texture<Spectrum<T>> where
     T: PixelComponent,
 {
-    pub fn from_raw_data<U, const K: usize>(width: u32, height: u32, data: &[u8]) -> Result<Self> where
+    pub fn from_raw_data<U, const K: usize>(width: u32, height: u32, data: &[u8], color_space: ColorSpace) -> Result<Self> where
         U: PixelComponent,
         T: From<U>,
     {
-        let vec_texture = Texture::<SVector<T, 3>>::from_raw_data::<U, K>(width, height, data)?;
+        let vec_texture = Texture::<SVector<T, 3>>::from_raw_data::<U, K>(width, height, data, color_space)?;
         let (size, vec_data) = (vec_texture.size, vec_texture.data);
 
         let data = unsafe { std::mem::transmute(vec_data) };
@@ -140,6 +322,7 @@ impl<T> Texture<Spectrum<T>> where
         Ok(Self {
             size,
             data,
+            sampler: Sampler::default(),
         })
     }
 }
@@ -147,11 +330,13 @@ impl<T> Texture<Spectrum<T>> where
 impl<T, const D: usize> Texture<SVector<T, D>> where
     T: PixelComponent 
 {
-    pub fn from_raw_data<U, const K: usize>(width: u32, height: u32, data: &[u8]) -> Result<Self> where
+    // color_space is the encoding of the first three components, any further ones like alpha are always linear.
+    pub fn from_raw_data<U, const K: usize>(width: u32, height: u32, data: &[u8], color_space: ColorSpace) -> Result<Self> where
         U: PixelComponent,
         T: From<U>,
     {
         assert!(D <= K);
+        check_size(width, height)?;
 
         let component_count = K;
         let component_size = std::mem::size_of::<U>();
@@ -159,20 +344,24 @@ impl<T, const D: usize> Texture<SVector<T, D>> where
         let pixel_size = component_count * component_size;
 
         if pixel_count * pixel_size != data.len() {
-            return Err(Error::from(ErrorKind::InvalidData));
+            return Err(Error::invalid(format!("pixel data of {} bytes for a {}x{} image", data.len(), width, height)));
         }
 
         let buffer = (0..pixel_count)
             .map(|i| {
                 let pixel_data = &data[i*pixel_size..(i+1)*pixel_size];
                 let pixel_data = unsafe { std::slice::from_raw_parts(pixel_data.as_ptr() as *const U, D) };
-                SVector::<T, D>::from_fn(|j, _| T::map(pixel_data[j]))
+                SVector::<T, D>::from_fn(|j, _| {
+                    let x = T::map(pixel_data[j]);
+                    if j < 3 { x.decode(color_space) } else { x }
+                })
             })
             .collect();
 
         Ok(Self {
             size: (width, height),
             data: buffer,
+            sampler: Sampler::default(),
         })
     }
 
@@ -180,46 +369,91 @@ impl<T, const D: usize> Texture<SVector<T, D>> where
 
 
 impl Texture<Spectrum<f32>> {
-    pub fn from_hdr_file(path: &str) -> Self {
-        let reader = BufReader::new(File::open(path).unwrap());
-        let decoder = hdr::HdrDecoder::new(reader).unwrap();
-        let meta = decoder.metadata();
-        let size = (meta.width, meta.height);
-        let data = decoder.read_image_hdr().unwrap();
-        
-        let data = data.into_iter()
-            .map(|px| Spectrum::new(px[0], px[1], px[2]))
-            .collect();
+    pub fn from_hdr_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let load = || -> Result<Self> {
+            let reader = BufReader::new(File::open(&path)?);
+            let decoder = hdr::HdrDecoder::new(reader)?;
+            let meta = decoder.metadata();
+            let size = (meta.width, meta.height);
+            check_size(size.0, size.1)?;
+            let data = decoder.read_image_hdr()?;
+
+            let data = data.into_iter()
+                .map(|px| Spectrum::new(px[0], px[1], px[2]))
+                .collect();
+
+            Ok(Texture { size, data, sampler: Sampler::default() })
+        };
+
+        load().map_err(|e| e.in_file(&path))
+    }
 
-        Texture { size, data }
+    // picks the loader by the extension. hdr and exr files hold linear values, so the colour
+    // space only applies to the 8 bit formats.
+    pub fn from_file<P: AsRef<Path>>(path: P, color_space: ColorSpace) -> Result<Self> {
+        match ImageFormat::from_path(&path) {
+            Some(ImageFormat::Hdr) => Self::from_hdr_file(path),
+            Some(ImageFormat::Exr) => Self::from_exr_file(path),
+            _ => Self::from_image_file(path, color_space),
+        }
     }
 
-    pub fn save<P: AsRef<Path>>(&self, path: P) {
-        let mut img = RgbaImage::new(self.size.0, self.size.1);
+    // reads any 8 bit image format the image crate supports, like png, jpeg or tga.
+    pub fn from_image_file<P: AsRef<Path>>(path: P, color_space: ColorSpace) -> Result<Self> {
+        let load = || -> Result<Self> {
+            let image = image::open(&path)?.into_rgb8();
+            let (width, height) = image.dimensions();
+            Self::from_raw_data::<u8, 3>(width, height, image.as_raw(), color_space)
+        };
 
-        self.data
-            .iter()
-            .copied()
-            .map(|px| {
-                let mut px: Vector3<f32> = px.into();
-                px.apply(|x| *x = x.powf(1.0/2.2).mul(255.0).clamp(0.0, 255.0));
-                [px.x as u8, px.y as u8, px.z as u8, 255u8]
-            })
-            .zip(img.pixels_mut())
-            .for_each(|(value,target)| *target = value.into());
+        load().map_err(|e| e.in_file(&path))
+    }
+
+    // turns a height map into a tangent space normal map as read by NormalTexture, from the
+    // slope between neighbouring texels. The height is the mean of the channels.
+    pub fn height_to_normal(&self, scale: f32) -> Self {
+        let height = |x: i64, y: i64| {
+            let px = self.texel(x, y);
+            (px.r + px.g + px.b) / 3.0
+        };
+
+        let mut normals = self.clone();
+        for (pos, px) in normals.pixels_mut() {
+            let (x, y) = (pos.x as i64, pos.y as i64);
+            let n = Vector3::new(
+                -(height(x + 1, y) - height(x - 1, y)) * 0.5 * scale,
+                -(height(x, y + 1) - height(x, y - 1)) * 0.5 * scale,
+                1.0,
+            ).normalize();
+
+            *px = Spectrum::new(n.x * 0.5 + 0.5, n.y * 0.5 + 0.5, n.z * 0.5 + 0.5);
+        }
 
-        img.save(path).unwrap();
+        normals
+    }
+
+    // writes the image in the format given by the extension of the path, with its default settings.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        ImageWriter::from_path(&path)?.write(path, self, None)
     }
 }
 
 
+impl<T> SurfaceTexture<T> for Texture<T> where
+    T: Copy + Send + Sync + Mul<f32, Output = T> + Add<T, Output = T>,
+{
+    fn evaluate(&self, p: &SurfacePoint) -> T {
+        self.sample(&p.tex_coords)
+    }
+}
+
 pub enum MaybeTexture<T> {
     Texture(Texture<T>),
     Value(T),
 }
 
 impl<T> MaybeTexture<T> where
-    T: Copy,
+    T: Copy + Mul<f32, Output = T> + Add<T, Output = T>,
 {
     pub fn sample(&self, uv: &Point2<f32>) -> T {
         match self {
@@ -229,13 +463,21 @@ impl<T> MaybeTexture<T> where
     }
 }
 
+impl<T> SurfaceTexture<T> for MaybeTexture<T> where
+    T: Copy + Send + Sync + Mul<f32, Output = T> + Add<T, Output = T>,
+{
+    fn evaluate(&self, p: &SurfacePoint) -> T {
+        self.sample(&p.tex_coords)
+    }
+}
+
 pub struct FactoredTexture<T> {
     pub factor: T,
     pub texture: Option<Texture<T>>,
 }
 
 impl<T> FactoredTexture<T> where
-    T: Copy + ClosedMul
+    T: Copy + ClosedMul + Mul<f32, Output = T> + Add<T, Output = T>,
 {
     pub fn new(factor: T, texture: Option<Texture<T>>) -> Self {
         Self {
@@ -250,4 +492,107 @@ impl<T> FactoredTexture<T> where
             None => self.factor,
         }
     }
-}
\ No newline at end of file
+}
+
+impl<T> SurfaceTexture<T> for FactoredTexture<T> where
+    T: Copy + ClosedMul + Send + Sync + Mul<f32, Output = T> + Add<T, Output = T>,
+{
+    fn evaluate(&self, p: &SurfacePoint) -> T {
+        self.sample(&p.tex_coords)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use crate::{error::Cause, spectrum::Spectrum};
+
+    use super::{Texture, Sampler, Filter, WrapMode, ColorSpace};
+
+    // a 2x1 texture holding 0 on the left and 1 on the right.
+    fn ramp(filter: Filter, wrap: WrapMode) -> Texture<f32> {
+        let mut texture = Texture::new(2, 1, &0.0);
+        texture.set(Point2::new(1, 0), 1.0);
+        texture.with_sampler(Sampler::new(filter, wrap, wrap))
+    }
+
+    #[test]
+    fn wrap_mode_test() {
+        let repeat = ramp(Filter::Nearest, WrapMode::Repeat);
+        assert_eq!(repeat.sample(&Point2::new(1.25, 0.5)), 0.0);
+        assert_eq!(repeat.sample(&Point2::new(-0.25, 0.5)), 1.0);
+
+        let clamp = ramp(Filter::Nearest, WrapMode::ClampToEdge);
+        assert_eq!(clamp.sample(&Point2::new(1.25, 0.5)), 1.0);
+        assert_eq!(clamp.sample(&Point2::new(-0.25, 0.5)), 0.0);
+
+        let mirror = ramp(Filter::Nearest, WrapMode::MirroredRepeat);
+        assert_eq!(mirror.sample(&Point2::new(1.25, 0.5)), 1.0);
+        assert_eq!(mirror.sample(&Point2::new(1.75, 0.5)), 0.0);
+        assert_eq!(mirror.sample(&Point2::new(-0.25, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn bilinear_test() {
+        let clamp = ramp(Filter::Bilinear, WrapMode::ClampToEdge);
+
+        // exactly on the texel centers and halfway between them.
+        assert_eq!(clamp.sample(&Point2::new(0.25, 0.5)), 0.0);
+        assert_eq!(clamp.sample(&Point2::new(0.75, 0.5)), 1.0);
+        assert!((clamp.sample(&Point2::new(0.5, 0.5)) - 0.5).abs() < 1e-6);
+        assert_eq!(clamp.sample(&Point2::new(0.0, 0.5)), 0.0);
+
+        // with repeat the left edge blends towards the right most texel.
+        let repeat = ramp(Filter::Bilinear, WrapMode::Repeat);
+        assert!((repeat.sample(&Point2::new(0.0, 0.5)) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn srgb_decode_test() {
+        let srgb = ColorSpace::Srgb;
+        assert_eq!(srgb.decode(0.0), 0.0);
+        assert!((srgb.decode(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb.decode(0.5) - 0.2140).abs() < 1e-4);
+        assert!((srgb.decode(0.02) - 0.02 / 12.92).abs() < 1e-7);
+
+        for x in [0.001, 0.1, 0.5, 0.9] {
+            assert!((srgb.encode(srgb.decode(x)) - x).abs() < 1e-5);
+        }
+
+        // colour channels are decoded, alpha is left alone.
+        let data = [128u8, 255, 0, 128];
+        let decoded = Texture::<Spectrum<f32>>::from_raw_data::<u8, 4>(1, 1, &data, ColorSpace::Srgb).unwrap();
+        let px = decoded.sample_nearest(&Point2::new(0.0, 0.0));
+        assert!((px.r - srgb.decode(128.0 / 255.0)).abs() < 1e-6);
+        assert_eq!((px.g, px.b), (1.0, 0.0));
+
+        let linear = Texture::<Spectrum<f32>>::from_raw_data::<u8, 4>(1, 1, &data, ColorSpace::Linear).unwrap();
+        assert!((linear.sample_nearest(&Point2::new(0.0, 0.0)).r - 128.0 / 255.0).abs() < 1e-6);
+    }
+
+    // images without texels are rejected before they get to be mipmapped.
+    #[test]
+    fn empty_image_test() {
+        for (width, height) in [(0, 0), (0, 4), (4, 0)] {
+            let error = Texture::<Spectrum<f32>>::from_raw_data::<u8, 3>(width, height, &[], ColorSpace::Srgb).err().unwrap();
+            assert!(matches!(*error.cause, Cause::Invalid(_)));
+        }
+    }
+
+    #[test]
+    fn height_to_normal_test() {
+        let mut height = Texture::new(3, 1, &Spectrum::constant(0.0));
+        height.set(Point2::new(1, 0), Spectrum::constant(0.5));
+        height.set(Point2::new(2, 0), Spectrum::constant(1.0));
+        let height = height.with_sampler(Sampler::new(Filter::Nearest, WrapMode::ClampToEdge, WrapMode::ClampToEdge));
+
+        // rising to the right tilts the normal to the left, the flat v direction leaves green at half.
+        let normals = height.height_to_normal(2.0);
+        let px = normals.texel(1, 0);
+        let expected = 1.0 / 2.0f32.sqrt();
+        assert!((px.r - (0.5 - 0.5 * expected)).abs() < 1e-6);
+        assert_eq!(px.g, 0.5);
+        assert!((px.b - (0.5 + 0.5 * expected)).abs() < 1e-6);
+    }
+}
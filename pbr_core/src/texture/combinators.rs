@@ -0,0 +1,231 @@
+use std::ops::{Mul, Add};
+
+use nalgebra::{Vector2, Vector3, Matrix3};
+
+use crate::{spectrum::Spectrum, geometry::SurfacePoint};
+
+use super::SurfaceTexture;
+
+// multiplies a texture by a scalar texture.
+pub struct ScaleTexture<T> {
+    texture: Box<dyn SurfaceTexture<T>>,
+    scale: Box<dyn SurfaceTexture<f32>>,
+}
+
+impl<T> ScaleTexture<T> {
+    pub fn new(texture: impl SurfaceTexture<T> + 'static, scale: impl SurfaceTexture<f32> + 'static) -> Self {
+        Self { texture: Box::new(texture), scale: Box::new(scale) }
+    }
+}
+
+impl<T> SurfaceTexture<T> for ScaleTexture<T> where
+    T: Mul<f32, Output = T>,
+{
+    fn evaluate(&self, p: &SurfacePoint) -> T {
+        self.texture.evaluate(p) * self.scale.evaluate(p)
+    }
+}
+
+// linearly blends between two textures, an amount of 0 returns a and 1 returns b.
+pub struct MixTexture<T> {
+    a: Box<dyn SurfaceTexture<T>>,
+    b: Box<dyn SurfaceTexture<T>>,
+    amount: Box<dyn SurfaceTexture<f32>>,
+}
+
+impl<T> MixTexture<T> {
+    pub fn new(
+        a: impl SurfaceTexture<T> + 'static,
+        b: impl SurfaceTexture<T> + 'static,
+        amount: impl SurfaceTexture<f32> + 'static,
+    ) -> Self {
+        Self { a: Box::new(a), b: Box::new(b), amount: Box::new(amount) }
+    }
+}
+
+impl<T> SurfaceTexture<T> for MixTexture<T> where
+    T: Mul<f32, Output = T> + Add<T, Output = T>,
+{
+    fn evaluate(&self, p: &SurfacePoint) -> T {
+        let t = self.amount.evaluate(p);
+
+        // skip evaluating the side that does not contribute.
+        if t <= 0.0 {
+            self.a.evaluate(p)
+        } else if t >= 1.0 {
+            self.b.evaluate(p)
+        } else {
+            self.a.evaluate(p) * (1.0 - t) + self.b.evaluate(p) * t
+        }
+    }
+}
+
+// the component-wise product of two textures.
+pub struct MultiplyTexture<T> {
+    a: Box<dyn SurfaceTexture<T>>,
+    b: Box<dyn SurfaceTexture<T>>,
+}
+
+impl<T> MultiplyTexture<T> {
+    pub fn new(a: impl SurfaceTexture<T> + 'static, b: impl SurfaceTexture<T> + 'static) -> Self {
+        Self { a: Box::new(a), b: Box::new(b) }
+    }
+}
+
+impl<T> SurfaceTexture<T> for MultiplyTexture<T> where
+    T: Mul<T, Output = T>,
+{
+    fn evaluate(&self, p: &SurfacePoint) -> T {
+        self.a.evaluate(p) * self.b.evaluate(p)
+    }
+}
+
+// evaluates a texture at transformed texture coordinates.
+pub struct UvTransformTexture<T> {
+    texture: Box<dyn SurfaceTexture<T>>,
+    transform: Matrix3<f32>,
+}
+
+impl<T> UvTransformTexture<T> {
+    pub fn new(texture: impl SurfaceTexture<T> + 'static, transform: Matrix3<f32>) -> Self {
+        Self { texture: Box::new(texture), transform }
+    }
+
+    // scales, then rotates and finally offsets the coordinates, like KHR_texture_transform.
+    pub fn offset_rotation_scale(texture: impl SurfaceTexture<T> + 'static, offset: Vector2<f32>, rotation: f32, scale: Vector2<f32>) -> Self {
+        let (sin, cos) = rotation.sin_cos();
+
+        let translation = Matrix3::new(
+            1.0, 0.0, offset.x,
+            0.0, 1.0, offset.y,
+            0.0, 0.0, 1.0,
+        );
+        let rotation = Matrix3::new(
+            cos, sin, 0.0,
+            -sin, cos, 0.0,
+            0.0, 0.0, 1.0,
+        );
+        let scale = Matrix3::new_nonuniform_scaling(&scale);
+
+        Self::new(texture, translation * rotation * scale)
+    }
+}
+
+impl<T> SurfaceTexture<T> for UvTransformTexture<T> {
+    fn evaluate(&self, p: &SurfacePoint) -> T {
+        let mut p = *p;
+        p.tex_coords = self.transform.transform_point(&p.tex_coords);
+        p.duvdx = self.transform.transform_vector(&p.duvdx);
+        p.duvdy = self.transform.transform_vector(&p.duvdy);
+        self.texture.evaluate(&p)
+    }
+}
+
+// extracts a single channel of a colour texture, e.g. the roughness from a packed metallic-roughness map.
+pub struct ChannelTexture {
+    texture: Box<dyn SurfaceTexture<Spectrum<f32>>>,
+    channel: usize,
+}
+
+impl ChannelTexture {
+    pub fn new(texture: impl SurfaceTexture<Spectrum<f32>> + 'static, channel: usize) -> Self {
+        assert!(channel < 3);
+        Self { texture: Box::new(texture), channel }
+    }
+}
+
+impl SurfaceTexture<f32> for ChannelTexture {
+    fn evaluate(&self, p: &SurfacePoint) -> f32 {
+        let col = self.texture.evaluate(p);
+        match self.channel {
+            0 => col.r,
+            1 => col.g,
+            _ => col.b,
+        }
+    }
+}
+
+// decodes a tangent space normal map stored as a colour, scale strengthens or weakens the bumps.
+pub struct NormalTexture {
+    texture: Box<dyn SurfaceTexture<Spectrum<f32>>>,
+    scale: f32,
+}
+
+impl NormalTexture {
+    pub fn new(texture: impl SurfaceTexture<Spectrum<f32>> + 'static, scale: f32) -> Self {
+        Self { texture: Box::new(texture), scale }
+    }
+}
+
+impl SurfaceTexture<Vector3<f32>> for NormalTexture {
+    fn evaluate(&self, p: &SurfacePoint) -> Vector3<f32> {
+        let col = self.texture.evaluate(p);
+        let n = Vector3::new(
+            (2.0 * col.r - 1.0) * self.scale,
+            (2.0 * col.g - 1.0) * self.scale,
+            2.0 * col.b - 1.0,
+        );
+
+        if n.z <= 0.0 || n.norm_squared() == 0.0 {
+            Vector3::new(0.0, 0.0, 1.0)
+        } else {
+            n.normalize()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use nalgebra::{Point2, Vector2, Vector3};
+
+    use crate::{geometry::SurfacePoint, material::LambertianMaterial, scene::Vertex, spectrum::Spectrum, texture::{SurfaceTexture, Texture}};
+
+    use super::{ScaleTexture, MixTexture, MultiplyTexture, UvTransformTexture, ChannelTexture, NormalTexture};
+
+    #[test]
+    fn combinators_test() {
+        let material = LambertianMaterial::flat(Spectrum::constant(1.0));
+        let vertex = Vertex { tex_coords: Point2::new(0.25, 0.75), ..Default::default() };
+        let p = SurfacePoint::from_vertex(&vertex, &material);
+
+        let scaled = ScaleTexture::new(Spectrum::new(0.2, 0.4, 0.6), 0.5);
+        assert_eq!(scaled.evaluate(&p), Spectrum::new(0.1, 0.2, 0.3));
+
+        let mixed = MixTexture::new(0.0, 1.0, 0.25);
+        assert!((mixed.evaluate(&p) - 0.25).abs() < 1e-6);
+
+        let multiplied = MultiplyTexture::new(Spectrum::constant(0.5), Spectrum::new(1.0, 0.5, 0.0));
+        assert_eq!(multiplied.evaluate(&p), Spectrum::new(0.5, 0.25, 0.0));
+
+        let channel = ChannelTexture::new(Spectrum::new(0.1, 0.2, 0.3), 1);
+        assert_eq!(channel.evaluate(&p), 0.2);
+
+        let flat = NormalTexture::new(Spectrum::new(0.5, 0.5, 1.0), 1.0);
+        assert!((flat.evaluate(&p) - Vector3::new(0.0, 0.0, 1.0)).norm() < 1e-6);
+    }
+
+    #[test]
+    fn uv_transform_test() {
+        let material = LambertianMaterial::flat(Spectrum::constant(1.0));
+        let vertex = Vertex { tex_coords: Point2::new(0.25, 0.25), ..Default::default() };
+        let p = SurfacePoint::from_vertex(&vertex, &material);
+
+        // a 2x2 texture with a different value in every quadrant.
+        let mut texture = Texture::new(2, 2, &0.0f32);
+        texture.set(Point2::new(1, 0), 1.0);
+        texture.set(Point2::new(0, 1), 2.0);
+        texture.set(Point2::new(1, 1), 3.0);
+
+        let offset = UvTransformTexture::offset_rotation_scale(texture.clone(), Vector2::new(0.5, 0.0), 0.0, Vector2::new(1.0, 1.0));
+        assert_eq!(offset.evaluate(&p), 1.0);
+
+        let scaled = UvTransformTexture::offset_rotation_scale(texture.clone(), Vector2::new(0.0, 0.0), 0.0, Vector2::new(1.0, 3.0));
+        assert_eq!(scaled.evaluate(&p), 2.0);
+
+        // a quarter turn maps (0.25, 0.25) to (0.25, -0.25), the offset moves it to (1.25, 0.75).
+        let rotated = UvTransformTexture::offset_rotation_scale(texture, Vector2::new(1.0, 1.0), FRAC_PI_2, Vector2::new(1.0, 1.0));
+        assert_eq!(rotated.evaluate(&p), 2.0);
+    }
+}
@@ -0,0 +1,229 @@
+use std::ops::{Mul, Add};
+
+use nalgebra::{Point2, Vector2};
+
+use crate::geometry::SurfacePoint;
+
+use super::{Texture, SurfaceTexture};
+
+// longest the major axis of an ewa ellipse may be compared to its minor axis, longer ellipses
+// are widened so that the lookup stays bounded.
+const MAX_ANISOTROPY: f32 = 8.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MipFilter {
+    #[default]
+    Trilinear,
+    Ewa,
+}
+
+// A pyramid of successively halved copies of a texture, filtered according to the
+// footprint of the lookup on the surface.
+pub struct MipMap<T> {
+    levels: Vec<Texture<T>>,
+    pub filter: MipFilter,
+}
+
+impl<T> MipMap<T> where
+    T: Copy + Mul<f32, Output = T> + Add<T, Output = T>,
+{
+    // the texture needs at least one texel, the texture loaders reject empty images.
+    pub fn new(texture: Texture<T>, filter: MipFilter) -> Self {
+        let mut levels = vec![texture];
+
+        loop {
+            let prev = levels.last().unwrap();
+            let (width, height) = prev.size();
+            if width == 1 && height == 1 {
+                break;
+            }
+
+            let size = ((width / 2).max(1), (height / 2).max(1));
+            let mut level = Texture::new(size.0, size.1, &prev.texel(0, 0)).with_sampler(*prev.sampler());
+
+            // box filter the up to four texels that collapse into one.
+            level.pixels_mut().for_each(|(xy, px)| {
+                let (x, y) = (2 * xy.x as i64, 2 * xy.y as i64);
+                let (x1, y1) = (if width > 1 { x + 1 } else { x }, if height > 1 { y + 1 } else { y });
+                *px = (prev.texel(x, y) + prev.texel(x1, y) + prev.texel(x, y1) + prev.texel(x1, y1)) * 0.25;
+            });
+
+            levels.push(level);
+        }
+
+        Self { levels, filter }
+    }
+
+    pub fn levels(&self) -> &[Texture<T>] {
+        &self.levels
+    }
+
+    // lerps between the two levels whose texels are closest to the size of the footprint.
+    pub fn sample_trilinear(&self, uv: &Point2<f32>, width: f32) -> T {
+        let level = (self.levels.len() - 1) as f32 + width.max(1e-8).log2();
+
+        if level <= 0.0 {
+            self.levels[0].sample_bilinear(uv)
+        } else if level >= (self.levels.len() - 1) as f32 {
+            self.levels.last().unwrap().sample_bilinear(uv)
+        } else {
+            let i = level.floor() as usize;
+            let t = level - i as f32;
+            self.levels[i].sample_bilinear(uv) * (1.0 - t) + self.levels[i + 1].sample_bilinear(uv) * t
+        }
+    }
+
+    // elliptically weighted average over the footprint spanned by the two axes.
+    // Source: https://www.pbr-book.org/3ed-2018/Texture/Image_Texture#EllipticallyWeightedAverage
+    pub fn sample_ewa(&self, uv: &Point2<f32>, duvdx: &Vector2<f32>, duvdy: &Vector2<f32>) -> T {
+        let (major, mut minor) = if duvdx.norm_squared() < duvdy.norm_squared() {
+            (*duvdy, *duvdx)
+        } else {
+            (*duvdx, *duvdy)
+        };
+
+        let major_length = major.norm();
+        let mut minor_length = minor.norm();
+
+        if minor_length * MAX_ANISOTROPY < major_length && minor_length > 0.0 {
+            let scale = major_length / (minor_length * MAX_ANISOTROPY);
+            minor *= scale;
+            minor_length *= scale;
+        }
+
+        if minor_length == 0.0 {
+            return self.levels[0].sample_bilinear(uv);
+        }
+
+        // the level is chosen by the minor axis, the major one is covered by more texels.
+        let level = ((self.levels.len() - 1) as f32 + minor_length.log2()).max(0.0);
+        let i = level.floor() as usize;
+
+        if i >= self.levels.len() - 1 {
+            return self.levels.last().unwrap().texel(0, 0);
+        }
+
+        let t = level - i as f32;
+        let a = self.ewa(i, uv, &major, &minor);
+        let b = self.ewa(i + 1, uv, &major, &minor);
+        a * (1.0 - t) + b * t
+    }
+
+    fn ewa(&self, level: usize, uv: &Point2<f32>, major: &Vector2<f32>, minor: &Vector2<f32>) -> T {
+        let texture = &self.levels[level];
+        let size = Vector2::new(texture.size().0 as f32, texture.size().1 as f32);
+
+        // work in texel coordinates of this level.
+        let s = uv.x * size.x - 0.5;
+        let t = uv.y * size.y - 0.5;
+        let d0 = major.component_mul(&size);
+        let d1 = minor.component_mul(&size);
+
+        // coefficients of the implicit ellipse a s^2 + b s t + c t^2 < 1.
+        let a = d0.y * d0.y + d1.y * d1.y + 1.0;
+        let b = -2.0 * (d0.x * d0.y + d1.x * d1.y);
+        let c = d0.x * d0.x + d1.x * d1.x + 1.0;
+        let inv_f = 1.0 / (a * c - b * b * 0.25);
+        let (a, b, c) = (a * inv_f, b * inv_f, c * inv_f);
+
+        let det = -b * b + 4.0 * a * c;
+        let inv_det = 1.0 / det;
+        let u_sqrt = (det * c).sqrt();
+        let v_sqrt = (a * det).sqrt();
+
+        let s0 = (s - 2.0 * inv_det * u_sqrt).ceil() as i64;
+        let s1 = (s + 2.0 * inv_det * u_sqrt).floor() as i64;
+        let t0 = (t - 2.0 * inv_det * v_sqrt).ceil() as i64;
+        let t1 = (t + 2.0 * inv_det * v_sqrt).floor() as i64;
+
+        let alpha = 2.0f32;
+        let mut sum: Option<T> = None;
+        let mut weight_sum = 0.0;
+
+        for it in t0..=t1 {
+            let tt = it as f32 - t;
+            for is in s0..=s1 {
+                let ss = is as f32 - s;
+
+                let r2 = a * ss * ss + b * ss * tt + c * tt * tt;
+                if r2 < 1.0 {
+                    let weight = (-alpha * r2).exp() - (-alpha).exp();
+                    let value = texture.texel(is, it) * weight;
+                    sum = Some(match sum {
+                        Some(sum) => sum + value,
+                        None => value,
+                    });
+                    weight_sum += weight;
+                }
+            }
+        }
+
+        match sum {
+            Some(sum) if weight_sum > 0.0 => sum * (1.0 / weight_sum),
+            _ => texture.sample_bilinear(uv),
+        }
+    }
+}
+
+impl<T> SurfaceTexture<T> for MipMap<T> where
+    T: Copy + Send + Sync + Mul<f32, Output = T> + Add<T, Output = T>,
+{
+    fn evaluate(&self, p: &SurfacePoint) -> T {
+        match self.filter {
+            MipFilter::Trilinear => {
+                let width = 2.0 * p.duvdx.abs().max().max(p.duvdy.abs().max());
+                self.sample_trilinear(&p.tex_coords, width)
+            },
+            MipFilter::Ewa => self.sample_ewa(&p.tex_coords, &p.duvdx, &p.duvdy),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point2, Vector2};
+
+    use crate::texture::{Texture, Sampler, Filter, WrapMode};
+
+    use super::{MipMap, MipFilter};
+
+    // an 8x8 checkerboard of single texels, which averages to 0.5.
+    fn checkerboard() -> Texture<f32> {
+        let mut texture = Texture::new(8, 8, &0.0).with_sampler(Sampler::new(Filter::Bilinear, WrapMode::Repeat, WrapMode::Repeat));
+        texture.pixels_mut().for_each(|(xy, px)| *px = ((xy.x + xy.y) % 2) as f32);
+        texture
+    }
+
+    #[test]
+    fn mipmap_levels_test() {
+        let mipmap = MipMap::new(checkerboard(), MipFilter::Trilinear);
+
+        let sizes: Vec<_> = mipmap.levels().iter().map(|level| level.size()).collect();
+        assert_eq!(sizes, vec![(8, 8), (4, 4), (2, 2), (1, 1)]);
+
+        for level in &mipmap.levels()[1..] {
+            assert!(level.pixels().all(|(_, px)| (px - 0.5).abs() < 1e-6));
+        }
+
+        // odd sizes round down and keep at least one texel.
+        let odd = MipMap::new(Texture::new(5, 1, &1.0f32), MipFilter::Trilinear);
+        let sizes: Vec<_> = odd.levels().iter().map(|level| level.size()).collect();
+        assert_eq!(sizes, vec![(5, 1), (2, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn mipmap_filtering_test() {
+        let uv = Point2::new(0.5 / 8.0, 0.5 / 8.0);
+
+        let trilinear = MipMap::new(checkerboard(), MipFilter::Trilinear);
+        assert_eq!(trilinear.sample_trilinear(&uv, 0.0), 0.0);
+        assert!((trilinear.sample_trilinear(&uv, 0.5) - 0.5).abs() < 1e-6);
+
+        // a long thin footprint across the checkerboard still averages the texels.
+        let ewa = MipMap::new(checkerboard(), MipFilter::Ewa);
+        assert_eq!(ewa.sample_ewa(&uv, &Vector2::zeros(), &Vector2::zeros()), 0.0);
+
+        let value = ewa.sample_ewa(&uv, &Vector2::new(0.5, 0.0), &Vector2::new(0.0, 0.1));
+        assert!((value - 0.5).abs() < 0.05, "value: {}", value);
+    }
+}
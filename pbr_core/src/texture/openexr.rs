@@ -0,0 +1,157 @@
+use std::{io::{self, ErrorKind}, path::Path};
+
+use exr::prelude::{f16, AnyChannel, AnyChannels, FlatSamples, Image, WritableImage};
+
+use crate::{spectrum::Spectrum, error::{Result, Error}};
+
+use super::{Texture, Sampler, check_size};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExrPrecision {
+    #[default]
+    Half,
+    Float,
+}
+
+impl Texture<Spectrum<f32>> {
+    // reads the R, G and B channels of the first layer, a file with only a Y channel is read as grey.
+    pub fn from_exr_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_exr_layer(path, "")
+    }
+
+    // reads the channels of a named layer, e.g. "albedo" reads "albedo.R", "albedo.G" and "albedo.B".
+    pub fn from_exr_layer<P: AsRef<Path>>(path: P, layer: &str) -> Result<Self> {
+        let image = exr::prelude::read_first_flat_layer_from_file(&path).map_err(|e| Error::from(e).in_file(&path))?;
+        let (width, height) = (image.layer_data.size.x(), image.layer_data.size.y());
+        check_size(width as u32, height as u32).map_err(|e| e.in_file(&path))?;
+        let channels = &image.layer_data.channel_data.list;
+
+        let find = |name: &str| {
+            let name = if layer.is_empty() { name.to_string() } else { format!("{}.{}", layer, name) };
+            channels.iter().find(|channel| channel.name.eq(&name))
+        };
+
+        let (r, g, b) = match (find("R"), find("G"), find("B"), find("Y")) {
+            (Some(r), Some(g), Some(b), _) => (r, g, b),
+            (_, _, _, Some(y)) => (y, y, y),
+            _ => return Err(Error::missing(format!("rgb channels in layer '{}'", layer)).in_file(&path)),
+        };
+
+        let data = (0..width * height)
+            .map(|i| Spectrum::new(
+                r.sample_data.value_by_flat_index(i).to_f32(),
+                g.sample_data.value_by_flat_index(i).to_f32(),
+                b.sample_data.value_by_flat_index(i).to_f32(),
+            ))
+            .collect();
+
+        Ok(Texture { size: (width as u32, height as u32), data, sampler: Sampler::default() })
+    }
+
+    pub fn save_exr<P: AsRef<Path>>(&self, path: P, precision: ExrPrecision) -> io::Result<()> {
+        save_exr_layers(path, &[("", self)], precision)
+    }
+}
+
+// writes several textures of the same size into one file, each as the R, G and B channels of
+// a layer with the given name. The layer with an empty name becomes the plain R, G and B channels.
+pub fn save_exr_layers<P: AsRef<Path>>(path: P, layers: &[(&str, &Texture<Spectrum<f32>>)], precision: ExrPrecision) -> io::Result<()> {
+    write_exr(path, layers, None, precision)
+}
+
+// like save_exr_layers, with an optional alpha written to the A channel.
+pub(super) fn write_exr<P: AsRef<Path>>(
+    path: P,
+    layers: &[(&str, &Texture<Spectrum<f32>>)],
+    alpha: Option<&Texture<f32>>,
+    precision: ExrPrecision,
+) -> io::Result<()> {
+    let size = match layers.first() {
+        Some((_, texture)) => texture.size(),
+        None => return Err(io::Error::new(ErrorKind::InvalidInput, "no layers to write")),
+    };
+
+    if layers.iter().any(|(_, texture)| texture.size() != size) || alpha.is_some_and(|alpha| alpha.size() != size) {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "layers differ in size"));
+    }
+
+    let samples = |values: &mut dyn Iterator<Item = f32>| match precision {
+        ExrPrecision::Half => FlatSamples::F16(values.map(f16::from_f32).collect()),
+        ExrPrecision::Float => FlatSamples::F32(values.collect()),
+    };
+
+    let mut channels = Vec::new();
+    for (layer, texture) in layers {
+        for (i, name) in ["R", "G", "B"].into_iter().enumerate() {
+            let name = if layer.is_empty() { name.to_string() } else { format!("{}.{}", layer, name) };
+            let mut values = texture.data.iter().map(|c| [c.r, c.g, c.b][i]);
+
+            channels.push(AnyChannel::new(name.as_str(), samples(&mut values)));
+        }
+    }
+
+    if let Some(alpha) = alpha {
+        channels.push(AnyChannel::new("A", samples(&mut alpha.data.iter().copied())));
+    }
+
+    let size = (size.0 as usize, size.1 as usize);
+    let image = Image::from_channels(size, AnyChannels::sort(channels.into()));
+    image.write().to_file(path).map_err(to_io_error)
+}
+
+fn to_io_error(error: exr::error::Error) -> io::Error {
+    match error {
+        exr::error::Error::Io(error) => error,
+        error => io::Error::new(ErrorKind::InvalidData, error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use crate::{spectrum::Spectrum, texture::Texture};
+
+    use super::{ExrPrecision, save_exr_layers};
+
+    #[test]
+    fn exr_round_trip_test() {
+        let mut beauty = Texture::new(3, 2, &Spectrum::new(0.25, 1.5, 100.0));
+        beauty.set(Point2::new(2, 1), Spectrum::new(-1.0, 0.0, 0.125));
+        let albedo = Texture::new(3, 2, &Spectrum::new(0.1, 0.2, 0.3));
+
+        let dir = std::env::temp_dir();
+        let float_path = dir.join("pbr_core_exr_float_test.exr");
+        let half_path = dir.join("pbr_core_exr_half_test.exr");
+
+        save_exr_layers(&float_path, &[("", &beauty), ("albedo", &albedo)], ExrPrecision::Float).unwrap();
+        beauty.save_exr(&half_path, ExrPrecision::Half).unwrap();
+
+        let read = Texture::from_exr_file(&float_path).unwrap();
+        assert_eq!(read.size(), (3, 2));
+        assert!(read.pixels().zip(beauty.pixels()).all(|((_, a), (_, b))| a == b));
+
+        let read_albedo = Texture::from_exr_layer(&float_path, "albedo").unwrap();
+        assert!(read_albedo.pixels().all(|(_, px)| *px == Spectrum::new(0.1, 0.2, 0.3)));
+        assert!(Texture::from_exr_layer(&float_path, "normal").is_err());
+
+        // these values are exactly representable as halfs.
+        let read_half = Texture::from_exr_file(&half_path).unwrap();
+        assert!(read_half.pixels().zip(beauty.pixels()).all(|((_, a), (_, b))| a == b));
+
+        std::fs::remove_file(float_path).unwrap();
+        std::fs::remove_file(half_path).unwrap();
+    }
+
+    #[test]
+    fn exr_normal_map_test() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../resources/concrete_brick_wall_001_nor_gl_1k.exr");
+        let normals = Texture::from_exr_file(path).unwrap();
+
+        assert_eq!(normals.size(), (1024, 1024));
+
+        // a tangent space normal map mostly points straight out of the surface.
+        let mean_blue = normals.pixels().map(|(_, px)| px.b).sum::<f32>() / (1024.0 * 1024.0);
+        assert!(mean_blue > 0.5, "mean blue: {}", mean_blue);
+    }
+}
@@ -0,0 +1,261 @@
+use nalgebra::{Point3, Vector3, Affine3};
+
+use crate::{geometry::SurfacePoint, spectrum::Spectrum};
+
+use super::SurfaceTexture;
+
+// The coordinates a procedural texture is evaluated in. Uv places the pattern on the texture
+// coordinates, world on the world position and object on the world position brought back into
+// the space of an object with the given object to world transform.
+#[derive(Clone, Copy)]
+pub enum TextureSpace {
+    Uv,
+    World,
+    Object(Affine3<f32>),
+}
+
+impl TextureSpace {
+    fn point(&self, p: &SurfacePoint) -> Point3<f32> {
+        match self {
+            TextureSpace::Uv => Point3::new(p.tex_coords.x, p.tex_coords.y, 0.0),
+            TextureSpace::World => p.position,
+            TextureSpace::Object(transform) => transform.inverse_transform_point(&p.position),
+        }
+    }
+}
+
+// alternates between two textures on a grid of cells with the given size.
+pub struct CheckerboardTexture<T> {
+    a: Box<dyn SurfaceTexture<T>>,
+    b: Box<dyn SurfaceTexture<T>>,
+    size: f32,
+    space: TextureSpace,
+}
+
+impl<T> CheckerboardTexture<T> {
+    pub fn new(a: impl SurfaceTexture<T> + 'static, b: impl SurfaceTexture<T> + 'static, size: f32, space: TextureSpace) -> Self {
+        Self { a: Box::new(a), b: Box::new(b), size, space }
+    }
+}
+
+impl<T> SurfaceTexture<T> for CheckerboardTexture<T> {
+    fn evaluate(&self, p: &SurfacePoint) -> T {
+        let x = self.space.point(p) / self.size;
+
+        // the z coordinate is 0 in uv space, so this is a 2d checkerboard there.
+        let parity = x.x.floor() as i64 + x.y.floor() as i64 + x.z.floor() as i64;
+
+        if parity.rem_euclid(2) == 0 {
+            self.a.evaluate(p)
+        } else {
+            self.b.evaluate(p)
+        }
+    }
+}
+
+// Fractal brownian motion of gradient noise, remapped to [0, 1].
+// Source: https://mrl.cs.nyu.edu/~perlin/paper445.pdf
+pub struct NoiseTexture {
+    pub frequency: f32,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub gain: f32,
+    space: TextureSpace,
+}
+
+impl NoiseTexture {
+    pub fn new(frequency: f32, space: TextureSpace) -> Self {
+        Self { frequency, octaves: 1, lacunarity: 2.0, gain: 0.5, space }
+    }
+
+    pub fn fbm(frequency: f32, octaves: u32, space: TextureSpace) -> Self {
+        Self { octaves, ..Self::new(frequency, space) }
+    }
+}
+
+impl SurfaceTexture<f32> for NoiseTexture {
+    fn evaluate(&self, p: &SurfacePoint) -> f32 {
+        let x = self.space.point(p) * self.frequency;
+
+        let mut sum = 0.0;
+        let mut norm = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+
+        for _ in 0..self.octaves.max(1) {
+            sum += amplitude * gradient_noise(&(x * frequency));
+            norm += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+
+        (0.5 + 0.5 * sum / norm).clamp(0.0, 1.0)
+    }
+}
+
+// Distance to the closest of a set of randomly placed feature points, one per unit cell,
+// scaled so that it mostly stays within [0, 1].
+// Source: https://dl.acm.org/doi/10.1145/237170.237267
+pub struct VoronoiTexture {
+    pub frequency: f32,
+    pub jitter: f32,
+    space: TextureSpace,
+}
+
+impl VoronoiTexture {
+    pub fn new(frequency: f32, space: TextureSpace) -> Self {
+        Self { frequency, jitter: 1.0, space }
+    }
+}
+
+impl SurfaceTexture<f32> for VoronoiTexture {
+    fn evaluate(&self, p: &SurfacePoint) -> f32 {
+        let x = self.space.point(p) * self.frequency;
+        let cell = x.map(|c| c.floor() as i32);
+
+        let mut closest = f32::INFINITY;
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let neighbour = cell + Vector3::new(dx, dy, dz);
+                    let offset = Vector3::new(
+                        hash_unit(&neighbour, 0),
+                        hash_unit(&neighbour, 1),
+                        hash_unit(&neighbour, 2),
+                    );
+                    let feature = neighbour.map(|c| c as f32) + Vector3::repeat(0.5) + (offset - Vector3::repeat(0.5)) * self.jitter;
+                    closest = closest.min((feature - x).norm_squared());
+                }
+            }
+        }
+
+        closest.sqrt().min(1.0)
+    }
+}
+
+// the colour interpolated between the vertices of a mesh, as scanned meshes often store it.
+pub struct VertexColorTexture;
+
+impl SurfaceTexture<Spectrum<f32>> for VertexColorTexture {
+    fn evaluate(&self, p: &SurfacePoint) -> Spectrum<f32> {
+        p.color
+    }
+}
+
+// a well mixed hash of an integer lattice point and a seed.
+fn hash(cell: &Point3<i32>, seed: u32) -> u32 {
+    let mut h = seed.wrapping_mul(0x9e3779b9);
+    for c in cell.iter() {
+        h ^= (*c as u32).wrapping_add(0x7f4a7c15).wrapping_add(h << 6).wrapping_add(h >> 2);
+        h = h.wrapping_mul(0x85ebca6b);
+        h ^= h >> 13;
+    }
+    h ^= h >> 16;
+    h = h.wrapping_mul(0xc2b2ae35);
+    h ^ (h >> 16)
+}
+
+fn hash_unit(cell: &Point3<i32>, seed: u32) -> f32 {
+    (hash(cell, seed) >> 8) as f32 / (1u32 << 24) as f32
+}
+
+// gradient noise in roughly [-1, 1], with the gradients picked from the edges of a cube.
+fn gradient_noise(x: &Point3<f32>) -> f32 {
+    let cell = x.map(|c| c.floor() as i32);
+    let f = x - cell.map(|c| c as f32);
+    let fade = f.map(|t| t * t * t * (t * (t * 6.0 - 15.0) + 10.0));
+
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        let g = match hash(&(cell + Vector3::new(dx, dy, dz)), 3) % 12 {
+            0 => Vector3::new(1.0, 1.0, 0.0),
+            1 => Vector3::new(-1.0, 1.0, 0.0),
+            2 => Vector3::new(1.0, -1.0, 0.0),
+            3 => Vector3::new(-1.0, -1.0, 0.0),
+            4 => Vector3::new(1.0, 0.0, 1.0),
+            5 => Vector3::new(-1.0, 0.0, 1.0),
+            6 => Vector3::new(1.0, 0.0, -1.0),
+            7 => Vector3::new(-1.0, 0.0, -1.0),
+            8 => Vector3::new(0.0, 1.0, 1.0),
+            9 => Vector3::new(0.0, -1.0, 1.0),
+            10 => Vector3::new(0.0, 1.0, -1.0),
+            _ => Vector3::new(0.0, -1.0, -1.0),
+        };
+        g.dot(&(f - Vector3::new(dx as f32, dy as f32, dz as f32)))
+    };
+
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), fade.x);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), fade.x);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), fade.x);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), fade.x);
+
+    let y0 = lerp(x00, x10, fade.y);
+    let y1 = lerp(x01, x11, fade.y);
+
+    lerp(y0, y1, fade.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point2, Point3, Affine3, Translation3, convert};
+
+    use crate::{geometry::SurfacePoint, material::LambertianMaterial, scene::Vertex, spectrum::Spectrum, texture::SurfaceTexture};
+
+    use super::{TextureSpace, CheckerboardTexture, NoiseTexture, VoronoiTexture};
+
+    #[test]
+    fn checkerboard_test() {
+        let material = LambertianMaterial::flat(Spectrum::constant(1.0));
+        let at = |u: f32, v: f32| {
+            let vertex = Vertex { tex_coords: Point2::new(u, v), position: Point3::new(u, 0.0, v), ..Default::default() };
+            SurfacePoint::from_vertex(&vertex, &material)
+        };
+
+        let uv = CheckerboardTexture::new(0.0, 1.0, 0.5, TextureSpace::Uv);
+        assert_eq!(uv.evaluate(&at(0.25, 0.25)), 0.0);
+        assert_eq!(uv.evaluate(&at(0.75, 0.25)), 1.0);
+        assert_eq!(uv.evaluate(&at(0.75, 0.75)), 0.0);
+        assert_eq!(uv.evaluate(&at(-0.25, 0.25)), 1.0);
+
+        // moving the object moves the pattern along with it.
+        let transform: Affine3<f32> = convert(Translation3::new(0.5, 0.0, 0.0));
+        let object = CheckerboardTexture::new(0.0, 1.0, 0.5, TextureSpace::Object(transform));
+        assert_eq!(object.evaluate(&at(0.75, 0.25)), 0.0);
+    }
+
+    #[test]
+    fn noise_test() {
+        let material = LambertianMaterial::flat(Spectrum::constant(1.0));
+        let noise = NoiseTexture::fbm(4.0, 5, TextureSpace::World);
+        let voronoi = VoronoiTexture::new(4.0, TextureSpace::World);
+
+        let mut values = Vec::new();
+        for i in 0..1000 {
+            let x = i as f32 * 0.0137;
+            let vertex = Vertex { position: Point3::new(x, 2.0 * x, 0.5 - x), ..Default::default() };
+            let p = SurfacePoint::from_vertex(&vertex, &material);
+
+            let n = noise.evaluate(&p);
+            let v = voronoi.evaluate(&p);
+            assert!((0.0..=1.0).contains(&n) && (0.0..=1.0).contains(&v));
+
+            // evaluation is deterministic.
+            assert_eq!(n, noise.evaluate(&p));
+            values.push(n);
+        }
+
+        // the noise actually varies, but averages out around the middle.
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        assert!((mean - 0.5).abs() < 0.1, "mean: {}", mean);
+        assert!(max - min > 0.3, "range: {} - {}", min, max);
+
+        // feature points sit on the lattice without jitter, so cell centres have a distance of 0.
+        let mut regular = VoronoiTexture::new(1.0, TextureSpace::World);
+        regular.jitter = 0.0;
+        let vertex = Vertex { position: Point3::new(2.5, -0.5, 7.5), ..Default::default() };
+        assert!(regular.evaluate(&SurfacePoint::from_vertex(&vertex, &material)) < 1e-6);
+    }
+}
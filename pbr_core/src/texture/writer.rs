@@ -0,0 +1,282 @@
+use std::{io::{BufWriter, Write, Result, Error, ErrorKind}, fs::File, path::Path};
+
+use image::{codecs::{png::PngEncoder, hdr::HdrEncoder}, ColorType, ImageError, Rgb};
+
+use crate::spectrum::Spectrum;
+
+use super::{Texture, ColorSpace, ExrPrecision, openexr::write_exr};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Pfm,
+    Exr,
+    Hdr,
+}
+
+impl ImageFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<ImageFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "pfm" => Some(ImageFormat::Pfm),
+            "exr" => Some(ImageFormat::Exr),
+            "hdr" => Some(ImageFormat::Hdr),
+            _ => None,
+        }
+    }
+
+    // the bit depth and transfer function used when none are chosen explicitly.
+    fn defaults(&self) -> (BitDepth, TransferFunction) {
+        match self {
+            ImageFormat::Png => (BitDepth::Eight, TransferFunction::Srgb),
+            ImageFormat::Pfm => (BitDepth::Float, TransferFunction::Linear),
+            ImageFormat::Exr => (BitDepth::Half, TransferFunction::Linear),
+            ImageFormat::Hdr => (BitDepth::Float, TransferFunction::Linear),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+    Half,
+    Float,
+}
+
+// how linear values are encoded before they are written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransferFunction {
+    Linear,
+    Srgb,
+    Gamma(f32),
+}
+
+impl TransferFunction {
+    pub fn encode(&self, x: f32) -> f32 {
+        match self {
+            TransferFunction::Linear => x,
+            TransferFunction::Srgb => ColorSpace::Srgb.encode(x),
+            TransferFunction::Gamma(gamma) => x.signum() * x.abs().powf(1.0 / gamma),
+        }
+    }
+}
+
+// Writes rendered images to disk. PNG stores 8 or 16 bit integers clamped to [0, 1], PFM and
+// HDR store floats, EXR stores halfs or floats. Only PNG and EXR can hold an alpha channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageWriter {
+    pub format: ImageFormat,
+    pub bit_depth: BitDepth,
+    pub transfer: TransferFunction,
+}
+
+impl ImageWriter {
+    pub fn new(format: ImageFormat) -> Self {
+        let (bit_depth, transfer) = format.defaults();
+        Self { format, bit_depth, transfer }
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        match ImageFormat::from_path(&path) {
+            Some(format) => Ok(Self::new(format)),
+            None => Err(Error::new(ErrorKind::InvalidInput, format!("unknown image format: {}", path.as_ref().display()))),
+        }
+    }
+
+    pub fn with_bit_depth(mut self, bit_depth: BitDepth) -> Self {
+        self.bit_depth = bit_depth;
+        self
+    }
+
+    pub fn with_transfer(mut self, transfer: TransferFunction) -> Self {
+        self.transfer = transfer;
+        self
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P, image: &Texture<Spectrum<f32>>, alpha: Option<&Texture<f32>>) -> Result<()> {
+        let supported = match self.format {
+            ImageFormat::Png => matches!(self.bit_depth, BitDepth::Eight | BitDepth::Sixteen),
+            ImageFormat::Pfm | ImageFormat::Hdr => self.bit_depth == BitDepth::Float,
+            ImageFormat::Exr => matches!(self.bit_depth, BitDepth::Half | BitDepth::Float),
+        };
+
+        if !supported {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{:?} can not be written with {:?} bit depth", self.format, self.bit_depth)));
+        }
+
+        if let Some(alpha) = alpha {
+            if !matches!(self.format, ImageFormat::Png | ImageFormat::Exr) {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("{:?} has no alpha channel", self.format)));
+            }
+            if alpha.size() != image.size() {
+                return Err(Error::new(ErrorKind::InvalidInput, "alpha differs in size from the image"));
+            }
+        }
+
+        let mut encoded = image.clone();
+        encoded.data.iter_mut().for_each(|px| *px = px.apply_into(|x| self.transfer.encode(x)));
+
+        match self.format {
+            ImageFormat::Png => self.write_png(path, &encoded, alpha),
+            ImageFormat::Pfm => write_pfm(path, &encoded),
+            ImageFormat::Hdr => write_hdr(path, &encoded),
+            ImageFormat::Exr => {
+                let precision = if self.bit_depth == BitDepth::Half { ExrPrecision::Half } else { ExrPrecision::Float };
+                write_exr(path, &[("", &encoded)], alpha, precision)
+            },
+        }
+    }
+
+    fn write_png<P: AsRef<Path>>(&self, path: P, image: &Texture<Spectrum<f32>>, alpha: Option<&Texture<f32>>) -> Result<()> {
+        let (width, height) = image.size();
+        let channels = if alpha.is_some() { 4 } else { 3 };
+
+        let values = image.data.iter()
+            .enumerate()
+            .flat_map(|(i, px)| [px.r, px.g, px.b, alpha.map_or(1.0, |alpha| alpha.data[i])].into_iter().take(channels));
+
+        // 16 bit samples are stored big endian.
+        let (data, color): (Vec<u8>, _) = match (self.bit_depth, alpha.is_some()) {
+            (BitDepth::Eight, has_alpha) => (
+                values.map(|x| (x.clamp(0.0, 1.0) * 255.0).round() as u8).collect(),
+                if has_alpha { ColorType::Rgba8 } else { ColorType::Rgb8 },
+            ),
+            (_, has_alpha) => (
+                values.flat_map(|x| ((x.clamp(0.0, 1.0) * 65535.0).round() as u16).to_be_bytes()).collect(),
+                if has_alpha { ColorType::Rgba16 } else { ColorType::Rgb16 },
+            ),
+        };
+
+        let file = BufWriter::new(File::create(path)?);
+        PngEncoder::new(file).encode(&data, width, height, color).map_err(to_io_error)
+    }
+}
+
+// Source: http://www.pauldebevec.com/Research/HDR/PFM/
+fn write_pfm<P: AsRef<Path>>(path: P, image: &Texture<Spectrum<f32>>) -> Result<()> {
+    let (width, height) = image.size();
+    let mut file = BufWriter::new(File::create(path)?);
+
+    // a negative scale marks little endian data, rows go from the bottom to the top.
+    write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+
+    for row in image.data.chunks(width as usize).rev() {
+        for px in row {
+            for x in [px.r, px.g, px.b] {
+                file.write_all(&x.to_le_bytes())?;
+            }
+        }
+    }
+
+    file.flush()
+}
+
+fn write_hdr<P: AsRef<Path>>(path: P, image: &Texture<Spectrum<f32>>) -> Result<()> {
+    let (width, height) = image.size();
+    let data: Vec<_> = image.data.iter().map(|px| Rgb([px.r.max(0.0), px.g.max(0.0), px.b.max(0.0)])).collect();
+
+    let file = BufWriter::new(File::create(path)?);
+    HdrEncoder::new(file).encode(&data, width as usize, height as usize).map_err(to_io_error)
+}
+
+fn to_io_error(error: ImageError) -> Error {
+    match error {
+        ImageError::IoError(error) => error,
+        error => Error::other(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use crate::{spectrum::Spectrum, texture::Texture};
+
+    use super::{ImageWriter, ImageFormat, BitDepth, TransferFunction};
+
+    // a 2x2 image with a distinct colour in every pixel and a half transparent top row.
+    fn image() -> (Texture<Spectrum<f32>>, Texture<f32>) {
+        let mut image = Texture::new(2, 2, &Spectrum::new(0.0, 0.5, 1.0));
+        image.set(Point2::new(1, 0), Spectrum::new(0.25, 2.0, 0.0));
+        image.set(Point2::new(0, 1), Spectrum::new(1.0, 0.0, 0.0));
+        image.set(Point2::new(1, 1), Spectrum::new(0.0, 0.0, 4.0));
+
+        let mut alpha = Texture::new(2, 2, &1.0);
+        alpha.set(Point2::new(0, 0), 0.5);
+        alpha.set(Point2::new(1, 0), 0.5);
+
+        (image, alpha)
+    }
+
+    #[test]
+    fn png_writer_test() {
+        let (image, alpha) = image();
+        let path = std::env::temp_dir().join("pbr_core_writer_test.png");
+
+        ImageWriter::new(ImageFormat::Png).write(&path, &image, Some(&alpha)).unwrap();
+        let png = image::open(&path).unwrap().into_rgba8();
+        assert_eq!(png.get_pixel(0, 0).0, [0, 188, 255, 128]);
+        assert_eq!(png.get_pixel(1, 0).0, [137, 255, 0, 128]);
+        assert_eq!(png.get_pixel(1, 1).0, [0, 0, 255, 255]);
+
+        ImageWriter::new(ImageFormat::Png)
+            .with_bit_depth(BitDepth::Sixteen)
+            .with_transfer(TransferFunction::Linear)
+            .write(&path, &image, None)
+            .unwrap();
+        let png = image::open(&path).unwrap().into_rgb16();
+        assert_eq!(png.get_pixel(0, 0).0, [0, 32768, 65535]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn float_writer_test() {
+        let (image, alpha) = image();
+        let dir = std::env::temp_dir();
+
+        let pfm_path = dir.join("pbr_core_writer_test.pfm");
+        ImageWriter::from_path(&pfm_path).unwrap().write(&pfm_path, &image, None).unwrap();
+
+        let pfm = std::fs::read(&pfm_path).unwrap();
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&pfm[..header.len()], header);
+
+        // the first stored pixel is the bottom left one.
+        let first: Vec<f32> = pfm[header.len()..header.len() + 12]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(first, vec![1.0, 0.0, 0.0]);
+        assert_eq!(pfm.len(), header.len() + 4 * 3 * 4);
+
+        let exr_path = dir.join("pbr_core_writer_test.exr");
+        ImageWriter::from_path(&exr_path).unwrap().write(&exr_path, &image, Some(&alpha)).unwrap();
+        let exr = Texture::from_exr_file(&exr_path).unwrap();
+        assert!(exr.pixels().zip(image.pixels()).all(|((_, a), (_, b))| a == b));
+
+        let hdr_path = dir.join("pbr_core_writer_test.hdr");
+        ImageWriter::from_path(&hdr_path).unwrap().write(&hdr_path, &image, None).unwrap();
+        let hdr = Texture::from_hdr_file(&hdr_path).unwrap();
+        let close = |a: &Spectrum<f32>, b: &Spectrum<f32>| (a.r - b.r).abs() < 0.02 && (a.g - b.g).abs() < 0.02 && (a.b - b.b).abs() < 0.02;
+        assert!(hdr.pixels().zip(image.pixels()).all(|((_, a), (_, b))| close(a, b)));
+
+        for path in [pfm_path, exr_path, hdr_path] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn unsupported_output_test() {
+        let (image, alpha) = image();
+        let path = std::env::temp_dir().join("pbr_core_writer_unsupported.pfm");
+
+        assert!(ImageWriter::from_path("image.jpg").is_err());
+        assert!(ImageWriter::new(ImageFormat::Png).with_bit_depth(BitDepth::Float).write(&path, &image, None).is_err());
+        assert!(ImageWriter::new(ImageFormat::Pfm).write(&path, &image, Some(&alpha)).is_err());
+        assert!(!path.exists());
+    }
+}
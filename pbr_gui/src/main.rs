@@ -1,11 +1,11 @@
 #![allow(unused_imports)]
 
-use std::ops::Mul;
+use std::{ops::Mul, sync::{Arc, atomic::{AtomicBool, Ordering}}};
 
 use pbr_core::{
-    texture::Texture,
+    texture::{Texture, ImageWriter, ImageFormat},
     spectrum::Spectrum,
-    scene::{SceneBuilder, loader::Gltf},
+    scene::{SceneBuilder, loader::Gltf, description::SceneDescription},
     light::{LightSource, SkySphere},
     camera::Camera,
     accelerator::Bvh,
@@ -23,22 +23,19 @@ use pixels::{SurfaceTexture, Pixels};
 
 
 fn main() {
-    
-    let begin_time = std::time::Instant::now();
+    env_logger::init();
 
-    let render_size = (1280, 720);
-    let env_map = Texture::<Spectrum<f32>>::from_hdr_file("resources/abandoned_greenhouse_4k.hdr");
+    let begin_time = std::time::Instant::now();
 
-    let scene = SceneBuilder::new()
-        .add_file::<Gltf, _>("resources/bunny.gltf").unwrap()
-        .add_light(LightSource::SkySphere(SkySphere::new(env_map)))
-        .camera(Camera::perspective_look_at(
-            &Point3::new(0.0, 3.0, 5.0), 
-            &Point3::new(0.0, 2.0, 0.0), 
-            &Vector3::new(0.0, 1.0, 0.0), 
-            std::f32::consts::PI / 2.0,
-            render_size.0 as f32 / render_size.1 as f32,
-        ));
+    let scene_path = std::env::args().nth(1).unwrap_or_else(|| "resources/scene.toml".to_string());
+    let (scene, settings) = match SceneDescription::load(&scene_path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Failed to load scene: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let render_size = settings.film_size;
 
     println!("Load time: {}s", (std::time::Instant::now() - begin_time).as_secs_f32());
     let begin_time = std::time::Instant::now();
@@ -47,8 +44,6 @@ fn main() {
     
     println!("Build time: {}s", (std::time::Instant::now() - begin_time).as_secs_f32());
     
-    let integrator = BruteForcer::new(4, 512);
-    
     let event_loop = EventLoop::new();
     let size = LogicalSize::new(render_size.0, render_size.1);
     let window = WindowBuilder::new()
@@ -62,21 +57,21 @@ fn main() {
     let mut pixels = Pixels::new(render_size.0, render_size.1, surface_texture).unwrap();
 
     let (tx, rx) = std::sync::mpsc::sync_channel(0);
+    // the window stays open after a failed save, closing it exits with an error.
+    let save_failed = Arc::new(AtomicBool::new(false));
+    let render_failed = save_failed.clone();
     
     let _render_thread = std::thread::spawn(move || {
         let begin_time = std::time::Instant::now();
         
-        let render_img = integrator.render(&scene, render_size, |img| tx.send(img.clone()).unwrap_or(()));
+        let (render_img, coverage) = settings.render(&scene, |img| tx.send(img.clone()).unwrap_or(()));
 
         println!("Render time: {}s", (std::time::Instant::now() - begin_time).as_secs_f32());
 
-        let mut render_img_linear = render_img.clone();
-        LinearToneMap::new().apply(&mut render_img_linear);
-        render_img_linear.save("test_linear.png");
-
-        let mut render_img_reinhard = render_img.clone();
-        ReinhardToneMap::new().apply(&mut render_img_reinhard);
-        render_img_reinhard.save("test_reinhard.png");
+        if let Err(e) = settings.write_outputs(&render_img, &coverage) {
+            eprintln!("Failed to save render: {}", e);
+            render_failed.store(true, Ordering::Relaxed);
+        }
     });
 
     event_loop.run(move |event, _, control_flow| {
@@ -104,6 +99,9 @@ fn main() {
         if let Event::WindowEvent { window_id: _, event } = event {
             match event {
                 WindowEvent::CloseRequested => {
+                    if save_failed.load(Ordering::Relaxed) {
+                        std::process::exit(1);
+                    }
                     *control_flow = ControlFlow::Exit;
                 },
                 _ => (),